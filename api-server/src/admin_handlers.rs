@@ -1,7 +1,13 @@
-use axum::{Json, response::IntoResponse, extract::{State, Extension}};
+use axum::{Json, response::IntoResponse, extract::{State, Extension}, http::{header, Extensions, HeaderMap}};
 use serde_json::json;
 use crate::router::AppState;
-use db::{UserRepo, ToolPolicyRepo, ConfigRepo, StatsRepo, models::User, models::ModelConfig};
+use crate::admin_middleware::{AdminRoles, load_roles};
+use crate::error::ApiError;
+use crate::audit::Auditor;
+use lowart_core::BucketConfig;
+use auth::rbac::{self, Role};
+use db::{AdminSession, AdminSessionRepo, UserRepo, ToolPolicyRepo, ConfigRepo, StatsRepo, BillingRepo, CreditRepo, QuotaRepo, QuotaPolicy, OrgRepo, ReportGroupBy, models::User, models::ModelConfig};
+use lowart_core::billing::MICROS_PER_CENT;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -9,6 +15,12 @@ pub struct UpdateQuotaRequest {
     pub user_id: String,
     pub rpm_limit: i64,
     pub token_quota: i64,
+    #[serde(default)]
+    pub cost_quota: Option<i64>, // 金额配额 (微单位)，不传表示不限
+    #[serde(default)]
+    pub tpm_limit: Option<i64>, // 每分钟 Token 数，不传表示不限
+    #[serde(default)]
+    pub priority: Option<i64>, // 模型排队优先级，不传表示不修改
 }
 
 #[derive(Deserialize)]
@@ -32,7 +44,50 @@ pub struct UnregisterMcpRequest {
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
-    pub api_key: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>, // 旧的登录方式，需 ADMIN_API_KEY_LOGIN=true 开启
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+/// 会话默认有效期 (秒)
+const DEFAULT_ADMIN_SESSION_TTL_SECS: i64 = 8 * 3600;
+/// 每个用户名每分钟允许的密码登录尝试次数
+const LOGIN_ATTEMPTS_PER_MINUTE: i64 = 10;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(default)]
+    pub current_password: Option<String>, // 首次设置密码时可不传
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetUserPasswordRequest {
+    pub user_id: String,
+    pub password: Option<String>, // 不传表示清除密码 (之后无法以用户名密码登录)
+    #[serde(default)]
+    pub reset_totp: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SessionsQuery {
+    pub user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeSessionsRequest {
+    pub id: Option<i64>,
+    pub user_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -46,7 +101,8 @@ pub struct CreateUserRequest {
 pub struct UpdateUserRequest {
     pub user_id: String,
     pub username: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: Option<String>, // 不传或为空表示不修改 (已保存的 Key 只有哈希，无法回显)
     pub status: String,
 }
 
@@ -63,6 +119,40 @@ pub struct CreateModelRequest {
     pub base_url: String,
     pub vendor_type: String,
     pub cost_per_1k_tokens: i64,
+    // 分项单价 (每千 Token，微单位)，未传时按 cost_per_1k_tokens 折算
+    #[serde(default)]
+    pub input_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub output_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub cached_input_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub model_group: Option<String>, // 模型分组，用于按组设置周期配额
+    #[serde(default)]
+    pub tpm_limit: Option<i64>, // 全部调用方对该模型的每分钟 Token 总数
+    #[serde(default)]
+    pub max_concurrency: Option<i64>, // 上游最大并发请求数，不传表示不限
+    #[serde(default)]
+    pub max_queue: Option<i64>,
+    #[serde(default)]
+    pub queue_timeout_ms: Option<i64>,
+    // 断路器参数，不传表示使用全局默认值
+    #[serde(default)]
+    pub breaker_window_secs: Option<i64>,
+    #[serde(default)]
+    pub breaker_min_requests: Option<i64>,
+    #[serde(default)]
+    pub breaker_error_rate: Option<i64>, // 百分比
+    #[serde(default)]
+    pub breaker_slow_call_ms: Option<i64>,
+    #[serde(default)]
+    pub breaker_slow_call_rate: Option<i64>, // 百分比
+    #[serde(default)]
+    pub breaker_open_secs: Option<i64>,
+    #[serde(default)]
+    pub breaker_max_open_secs: Option<i64>,
+    #[serde(default)]
+    pub health_probe: Option<String>, // 健康检查探测方式，不传表示厂商默认，"off" 不检查，其余为 JSON 对话请求体
     pub is_active: bool,
 }
 
@@ -75,6 +165,40 @@ pub struct UpdateModelRequest {
     pub base_url: String,
     pub vendor_type: String,
     pub cost_per_1k_tokens: i64,
+    // 分项单价 (每千 Token，微单位)，未传时按 cost_per_1k_tokens 折算
+    #[serde(default)]
+    pub input_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub output_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub cached_input_price_per_1k: Option<i64>,
+    #[serde(default)]
+    pub model_group: Option<String>, // 模型分组，用于按组设置周期配额
+    #[serde(default)]
+    pub tpm_limit: Option<i64>, // 全部调用方对该模型的每分钟 Token 总数
+    #[serde(default)]
+    pub max_concurrency: Option<i64>, // 上游最大并发请求数，不传表示不限
+    #[serde(default)]
+    pub max_queue: Option<i64>,
+    #[serde(default)]
+    pub queue_timeout_ms: Option<i64>,
+    // 断路器参数，不传表示使用全局默认值
+    #[serde(default)]
+    pub breaker_window_secs: Option<i64>,
+    #[serde(default)]
+    pub breaker_min_requests: Option<i64>,
+    #[serde(default)]
+    pub breaker_error_rate: Option<i64>, // 百分比
+    #[serde(default)]
+    pub breaker_slow_call_ms: Option<i64>,
+    #[serde(default)]
+    pub breaker_slow_call_rate: Option<i64>, // 百分比
+    #[serde(default)]
+    pub breaker_open_secs: Option<i64>,
+    #[serde(default)]
+    pub breaker_max_open_secs: Option<i64>,
+    #[serde(default)]
+    pub health_probe: Option<String>, // 健康检查探测方式，不传表示厂商默认，"off" 不检查，其余为 JSON 对话请求体
     pub is_active: bool,
 }
 
@@ -83,142 +207,1198 @@ pub struct DeleteModelRequest {
     pub id: String,
 }
 
+#[derive(Deserialize)]
+pub struct CircuitActionRequest {
+    pub model_id: String,
+}
+
 #[derive(Deserialize)]
 pub struct CreateKeyRequest {
     pub user_id: String,
     pub label: String,
 }
 
-#[derive(Deserialize)]
-pub struct ResetKeyRequest {
-    pub key_id: i64,
+#[derive(Deserialize)]
+pub struct UpdateKeyLimitsRequest {
+    pub key_id: i64,
+    #[serde(flatten)]
+    pub settings: db::ApiKeySettings, // 未传的项表示不限
+}
+
+#[derive(Deserialize)]
+pub struct ResetKeyRequest {
+    pub key_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct KeyClientCertRequest {
+    pub key_id: i64,
+    #[serde(default)]
+    pub subject: Option<String>, // 客户端证书的 CN 或任一 SAN，不传或为空表示解除绑定
+}
+
+#[derive(Deserialize)]
+pub struct KeyUnixPeerRequest {
+    pub key_id: i64,
+    #[serde(default)]
+    pub uid: Option<i64>, // 不传表示不按 uid 匹配
+    #[serde(default)]
+    pub gid: Option<i64>, // 不传表示不按 gid 匹配
+}
+
+/// 默认轮换宽限期: 24 小时
+const DEFAULT_ROTATION_GRACE_SECS: i64 = 24 * 3600;
+
+#[derive(Deserialize)]
+pub struct RotateKeyRequest {
+    pub key_id: i64,
+    #[serde(default)]
+    pub grace_seconds: Option<i64>, // 旧 Key 继续有效的时长，默认 24 小时
+}
+
+#[derive(Deserialize)]
+pub struct StaleKeysQuery {
+    pub days: Option<i64>, // 超过多少天未使用，默认 30
+}
+
+#[derive(Deserialize)]
+pub struct DeleteKeyRequest {
+    pub key_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub user_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreditTransactionQuery {
+    pub user_id: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct TopUpRequest {
+    pub user_id: String,
+    pub amount: i64, // 微单位
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub note: Option<String>,
+    pub low_balance_threshold: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct AdjustCreditRequest {
+    pub user_id: String,
+    pub amount: i64, // 正数入账，负数扣减
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RefundCreditRequest {
+    pub user_id: String,
+    pub amount: i64,
+    pub ledger_id: Option<i64>,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UsageReportQuery {
+    pub group_by: Option<String>, // user, key, model, org, day, hour (默认 day)
+    pub org_id: Option<String>,   // 仅统计该组织的成员
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub format: Option<String>, // json (默认), csv
+}
+
+#[derive(Deserialize)]
+pub struct HealthHistoryQuery {
+    pub model_id: String,
+    pub limit: Option<i64>, // 默认 20，最多为每个模型保留的样本数
+}
+
+#[derive(Deserialize)]
+pub struct QuotaPolicyQuery {
+    pub user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateQuotaPolicyRequest {
+    pub user_id: Option<String>, // 不传表示对所有用户生效
+    #[serde(default = "default_scope_type")]
+    pub scope_type: String, // all, model, group
+    pub scope_value: Option<String>,
+    pub period: String, // daily, weekly, monthly
+    #[serde(default = "default_window_type")]
+    pub window_type: String, // calendar, rolling
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>, // 微单位
+}
+
+fn default_scope_type() -> String {
+    "all".to_string()
+}
+
+fn default_window_type() -> String {
+    "calendar".to_string()
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuotaPolicyRequest {
+    pub id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrgRequest {
+    pub name: String,
+    pub token_budget: Option<i64>,
+    pub cost_budget: Option<i64>, // 微单位，不传表示不限
+}
+
+#[derive(Deserialize)]
+pub struct UpdateOrgRequest {
+    pub id: String,
+    pub name: String,
+    pub token_budget: Option<i64>,
+    pub cost_budget: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteOrgRequest {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct OrgMembersQuery {
+    pub org_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpsertOrgMemberRequest {
+    pub org_id: String,
+    pub user_id: String,
+    #[serde(default = "crate::org_handlers::default_role")]
+    pub role: String, // member, admin
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveOrgMemberRequest {
+    pub org_id: String,
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetUserRolesRequest {
+    pub user_id: String,
+    pub roles: Vec<String>, // 整体替换，空列表表示撤销全部管理角色
+}
+
+#[derive(Deserialize)]
+pub struct QuotaUsageQuery {
+    pub user_id: String,
+}
+
+
+/// 审计用的对象快照 (查询失败或不存在时为 null)
+pub(crate) fn snapshot<T: serde::Serialize>(value: utils::Result<Option<T>>) -> serde_json::Value {
+    value.ok().flatten().map(|v| json!(v)).unwrap_or(serde_json::Value::Null)
+}
+
+/// 用户在指定组织中的成员关系 (审计快照，不属于该组织时为 null)
+pub(crate) async fn org_membership(org_repo: &OrgRepo<'_>, org_id: &str, user_id: &str) -> serde_json::Value {
+    snapshot(org_repo.find_membership(user_id).await.map(|m| m.filter(|m| m.org_id == org_id)))
+}
+
+/// 目标用户为超级管理员时，只有超级管理员可修改其账号、角色与 Key (防止越权接管)
+async fn ensure_manageable(state: &AppState, caller: &AdminRoles, user_id: &str) -> Result<(), axum::response::Response> {
+    if caller.is_super_admin() {
+        return Ok(());
+    }
+    match load_roles(state, user_id).await {
+        Ok(roles) if roles.contains(&Role::SuperAdmin) => {
+            Err((axum::http::StatusCode::FORBIDDEN, "仅超级管理员可管理超级管理员账号").into_response())
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err((axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// 按 Key 所属用户校验 (Key 不存在时交由后续处理)
+async fn ensure_key_manageable(state: &AppState, caller: &AdminRoles, key_id: i64) -> Result<(), axum::response::Response> {
+    let db = state.model_manager.db();
+    match db::ApiKeyRepo::new(&db).find_by_id(key_id).await {
+        Ok(Some(key)) => ensure_manageable(state, caller, &key.user_id).await,
+        Ok(None) => Ok(()),
+        Err(e) => Err((axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// 获取所有用户列表
+pub async fn list_users(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+    match user_repo.list_all().await {
+        Ok(users) => Json(users).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 更新用户配额
+pub async fn update_user_quota(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<UpdateQuotaRequest>
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+    let before = snapshot(user_repo.find_by_id(&payload.user_id).await);
+    let result = match user_repo.update_quota(&payload.user_id, payload.rpm_limit, payload.token_quota, payload.cost_quota).await {
+        Ok(_) => user_repo.update_tpm_limit(&payload.user_id, payload.tpm_limit).await,
+        Err(e) => Err(e),
+    };
+    let result = match (result, payload.priority) {
+        (Ok(_), Some(priority)) => user_repo.update_priority(&payload.user_id, priority).await,
+        (result, _) => result,
+    };
+    match result {
+        Ok(_) => {
+            // 内存配额计数以数据库为准重新加载 (鉴权缓存由变更事件淘汰)
+            state.reservations.invalidate_user(&payload.user_id);
+            let after = snapshot(user_repo.find_by_id(&payload.user_id).await);
+            audit.record("user.quota", "user", &payload.user_id, before, after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 设置工具治理策略
+pub async fn update_tool_policy(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<UpdatePolicyRequest>
+) -> impl IntoResponse {
+    let db_conn = state.model_manager.db();
+    let policy_repo = ToolPolicyRepo::new(&db_conn.pool);
+    let before = policy_repo.get_policy(&payload.tool_name, payload.user_id.as_deref()).await.ok();
+    
+    match policy_repo.upsert_policy(&payload.tool_name, payload.user_id.as_deref(), &payload.policy).await {
+        Ok(_) => {
+            let target = format!("{}:{}", payload.tool_name, payload.user_id.as_deref().unwrap_or("*"));
+            audit.record("tool_policy.update", "tool_policy", &target, json!({"policy": before}), json!({"policy": payload.policy})).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 动态注册 MCP 客户端
+pub async fn register_mcp(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<RegisterMcpRequest>
+) -> impl IntoResponse {
+    use protocols::stdio_mcp_client::StdioMcpClient;
+    use protocols::mcp::{McpServerMeta, McpClient};
+    use std::sync::Arc;
+
+    let args_ref: Vec<&str> = payload.args.iter().map(|s| s.as_str()).collect();
+    match StdioMcpClient::spawn(&payload.command, &args_ref).await {
+        Ok(client) => {
+            let client: Arc<dyn McpClient> = Arc::new(client);
+            // 初始化
+
+            let meta = McpServerMeta {
+                name: payload.name.clone(),
+                version: "1.0.0".to_string(),
+                capabilities: json!({}),
+            };
+
+            if let Err(e) = client.initialize(meta).await {
+                return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, format!("MCP 初始化失败: {}", e)).into_response();
+            }
+            let after = json!({"command": payload.command, "args": payload.args});
+            audit.record("mcp.register", "mcp", &payload.name, serde_json::Value::Null, after).await;
+            state.mcp_manager.register_client(payload.name, client).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, format!("MCP 启动失败: {}", e)).into_response(),
+    }
+}
+
+/// 动态注销 MCP 客户端
+pub async fn unregister_mcp(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<UnregisterMcpRequest>
+) -> impl IntoResponse {
+    state.mcp_manager.unregister_client(&payload.name).await;
+    audit.record("mcp.unregister", "mcp", &payload.name, json!({"name": payload.name}), serde_json::Value::Null).await;
+    Json(json!({"status": "success"})).into_response()
+}
+
+/// 获取所有模型列表
+pub async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let config_repo = ConfigRepo::new(&db);
+    match config_repo.list_all().await {
+        Ok(configs) => Json(configs).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 模型健康状态 (最近一次主动检查的结果与当前断路器状态)
+pub async fn list_model_health(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.health_checker.snapshot())
+}
+
+/// 模型最近的健康检查样本 (含重启前的记录)
+pub async fn list_model_health_history(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<HealthHistoryQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let limit = query.limit.unwrap_or(20).clamp(1, db::health_repo::HEALTH_SAMPLES_PER_MODEL);
+    match db::HealthRepo::new(&db).list_recent(&query.model_id, limit).await {
+        Ok(samples) => Json(samples).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 立即检查全部模型
+pub async fn check_model_health(State(state): State<AppState>, audit: Auditor) -> impl IntoResponse {
+    let before = json!(state.health_checker.snapshot());
+    match state.health_checker.check_all().await {
+        Ok(_) => {
+            let after = state.health_checker.snapshot();
+            audit.record("model.health_check", "model", "*", before, json!(after)).await;
+            Json(after).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 全部模型的断路器状态
+/// 实现逻辑: 以模型配置为准列出全部模型 (未产生过调用的模型为闭合的空快照)，
+/// 再补上已删除但仍在统计中的模型。
+pub async fn list_circuits(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let config_repo = ConfigRepo::new(&db);
+    let configs = match config_repo.list_all().await {
+        Ok(configs) => configs,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let mut circuits: Vec<lowart_core::CircuitSnapshot> = configs.iter()
+        .map(|config| state.circuit_breaker.snapshot(&config.model_id))
+        .collect();
+    for tracked in state.circuit_breaker.snapshots() {
+        if !circuits.iter().any(|c| c.model_id == tracked.model_id) {
+            circuits.push(tracked);
+        }
+    }
+    circuits.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+    Json(circuits).into_response()
+}
+
+/// 强制熔断模型 (维护摘除，直到强制闭合或重置)
+pub async fn force_open_circuit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<CircuitActionRequest>
+) -> impl IntoResponse {
+    circuit_action(&state, &audit, &payload.model_id, "circuit.force_open", |breaker, model_id| breaker.force_open(model_id)).await
+}
+
+/// 强制闭合模型断路器
+pub async fn force_close_circuit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<CircuitActionRequest>
+) -> impl IntoResponse {
+    circuit_action(&state, &audit, &payload.model_id, "circuit.force_close", |breaker, model_id| breaker.force_close(model_id)).await
+}
+
+/// 重置模型断路器统计
+pub async fn reset_circuit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<CircuitActionRequest>
+) -> impl IntoResponse {
+    circuit_action(&state, &audit, &payload.model_id, "circuit.reset", |breaker, model_id| breaker.reset(model_id)).await
+}
+
+/// 执行断路器人工操作并记录审计 (仅允许已配置的模型)
+async fn circuit_action(
+    state: &AppState,
+    audit: &Auditor,
+    model_id: &str,
+    action: &str,
+    apply: impl FnOnce(&lowart_core::CircuitBreaker, &str),
+) -> axum::response::Response {
+    let db = state.model_manager.db();
+    let config_repo = ConfigRepo::new(&db);
+    match config_repo.find_by_model_id(model_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "模型不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+    let before = json!(state.circuit_breaker.snapshot(model_id));
+    apply(&state.circuit_breaker, model_id);
+    let after = state.circuit_breaker.snapshot(model_id);
+    audit.record(action, "model", model_id, before, json!(after)).await;
+    Json(after).into_response()
+}
+
+/// 获取最近调用日志
+pub async fn list_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let stats_repo = StatsRepo::new(&db);
+    match stats_repo.list_recent(100).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 用量报表 (按维度汇总，支持 JSON 与 CSV 导出)
+pub async fn usage_report(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<UsageReportQuery>,
+) -> impl IntoResponse {
+    let org_id = query.org_id.clone();
+    render_usage_report(&state, &query, org_id.as_deref()).await
+}
+
+/// 生成用量报表响应 (管理员与组织管理员共用)
+pub(crate) async fn render_usage_report(state: &AppState, query: &UsageReportQuery, org_id: Option<&str>) -> axum::response::Response {
+    let group_name = query.group_by.as_deref().unwrap_or("day");
+    let Some(group_by) = ReportGroupBy::parse(group_name) else {
+        return (axum::http::StatusCode::BAD_REQUEST, "group_by 仅支持 user, key, model, org, day, hour").into_response();
+    };
+
+    let db = state.model_manager.db();
+    let stats_repo = StatsRepo::new(&db);
+    let rows = match stats_repo.usage_report(group_by, query.from, query.to, org_id).await {
+        Ok(rows) => rows,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let total = match stats_repo.usage_total(query.from, query.to, org_id).await {
+        Ok(total) => total,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    match query.format.as_deref().unwrap_or("json") {
+        "json" => Json(json!({
+            "group_by": group_name,
+            "from": query.from,
+            "to": query.to,
+            "rows": rows,
+            "total": total,
+        })).into_response(),
+        "csv" => {
+            let mut csv = String::from("group,requests,errors,input_tokens,output_tokens,cost,avg_latency_ms,p50_latency_ms,p95_latency_ms,p99_latency_ms\n");
+            for row in rows.iter().chain(std::iter::once(&total)) {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{}\n",
+                    csv_field(&row.group), row.requests, row.errors, row.input_tokens, row.output_tokens,
+                    row.cost, row.avg_latency_ms, row.p50_latency_ms, row.p95_latency_ms, row.p99_latency_ms
+                ));
+            }
+            (
+                [
+                    (axum::http::header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                    (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"usage_by_{}.csv\"", group_name)),
+                ],
+                csv,
+            ).into_response()
+        }
+        _ => (axum::http::StatusCode::BAD_REQUEST, "format 仅支持 json, csv").into_response(),
+    }
+}
+
+/// CSV 字段转义: 含逗号、引号或换行时加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 查询计费账本 (可按用户过滤)
+pub async fn list_ledger(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<LedgerQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let billing_repo = BillingRepo::new(&db);
+    match billing_repo.list(query.user_id.as_deref(), query.limit.unwrap_or(100)).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 查询用户的余额流水
+pub async fn list_credit_transactions(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<CreditTransactionQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let credit_repo = CreditRepo::new(&db);
+    match credit_repo.list_transactions(&query.user_id, query.limit.unwrap_or(100)).await {
+        Ok(txs) => Json(txs).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 为用户充值 (首次充值自动开通预付费账户)
+pub async fn top_up_credit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<TopUpRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let credit_repo = CreditRepo::new(&db);
+    let before = snapshot(credit_repo.get_account(&payload.user_id).await);
+    let balance = match credit_repo.top_up(&payload.user_id, payload.amount, payload.expires_at, payload.note.as_deref()).await {
+        Ok(b) => b,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if let Some(threshold) = payload.low_balance_threshold {
+        if let Err(e) = credit_repo.set_low_balance_threshold(&payload.user_id, threshold).await {
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+    let after = snapshot(credit_repo.get_account(&payload.user_id).await);
+    audit.record("credit.top_up", "user", &payload.user_id, before, after).await;
+    Json(json!({"status": "success", "balance": balance})).into_response()
+}
+
+/// 人工调整余额
+pub async fn adjust_credit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<AdjustCreditRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let credit_repo = CreditRepo::new(&db);
+    let before = snapshot(credit_repo.get_account(&payload.user_id).await);
+    match credit_repo.adjust(&payload.user_id, payload.amount, payload.note.as_deref()).await {
+        Ok(balance) => {
+            let after = snapshot(credit_repo.get_account(&payload.user_id).await);
+            audit.record("credit.adjust", "user", &payload.user_id, before, after).await;
+            Json(json!({"status": "success", "balance": balance})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 退款至余额
+pub async fn refund_credit(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<RefundCreditRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let credit_repo = CreditRepo::new(&db);
+    let before = snapshot(credit_repo.get_account(&payload.user_id).await);
+    match credit_repo.refund(&payload.user_id, payload.amount, payload.ledger_id, payload.note.as_deref()).await {
+        Ok(balance) => {
+            let after = snapshot(credit_repo.get_account(&payload.user_id).await);
+            audit.record("credit.refund", "user", &payload.user_id, before, after).await;
+            Json(json!({"status": "success", "balance": balance})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 列出周期配额策略 (指定用户时包含对其生效的全局策略)
+pub async fn list_quota_policies(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<QuotaPolicyQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let quota_repo = QuotaRepo::new(&db);
+    match quota_repo.list_policies(query.user_id.as_deref()).await {
+        Ok(policies) => Json(policies).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 创建周期配额策略
+pub async fn create_quota_policy(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<CreateQuotaPolicyRequest>,
+) -> impl IntoResponse {
+    if payload.scope_type != "all" && payload.scope_value.is_none() {
+        return (axum::http::StatusCode::BAD_REQUEST, "按模型或分组限定时必须提供 scope_value").into_response();
+    }
+
+    let db = state.model_manager.db();
+    let quota_repo = QuotaRepo::new(&db);
+    let policy = QuotaPolicy {
+        id: 0, // 自动递增
+        user_id: payload.user_id,
+        scope_type: payload.scope_type,
+        scope_value: payload.scope_value,
+        period: payload.period,
+        window_type: payload.window_type,
+        token_limit: payload.token_limit,
+        cost_limit: payload.cost_limit,
+        created_at: chrono::Utc::now(),
+    };
+    match quota_repo.create_policy(&policy).await {
+        Ok(id) => {
+            let after = snapshot(quota_repo.find_policy(id).await);
+            audit.record("quota_policy.create", "quota_policy", &id.to_string(), serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "id": id})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 删除周期配额策略
+pub async fn delete_quota_policy(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<DeleteQuotaPolicyRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let quota_repo = QuotaRepo::new(&db);
+    let before = snapshot(quota_repo.find_policy(payload.id).await);
+    match quota_repo.delete_policy(payload.id).await {
+        Ok(_) => {
+            audit.record("quota_policy.delete", "quota_policy", &payload.id.to_string(), before, serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 查询用户各周期配额在当前窗口内的用量
+pub async fn get_quota_usage(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<QuotaUsageQuery>,
+) -> impl IntoResponse {
+    match state.quota.usage(&query.user_id).await {
+        Ok(usage) => Json(usage).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 列出所有组织
+pub async fn list_orgs(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    match org_repo.list_all().await {
+        Ok(orgs) => Json(orgs).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 创建组织
+pub async fn create_org(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<CreateOrgRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let org_id = uuid::Uuid::new_v4().to_string();
+    match org_repo.create(&org_id, &payload.name, payload.token_budget, payload.cost_budget).await {
+        Ok(_) => {
+            let after = snapshot(org_repo.find_by_id(&org_id).await);
+            audit.record("org.create", "org", &org_id, serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "id": org_id})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 更新组织名称与共享预算
+pub async fn update_org(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<UpdateOrgRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = snapshot(org_repo.find_by_id(&payload.id).await);
+    match org_repo.update(&payload.id, &payload.name, payload.token_budget, payload.cost_budget).await {
+        Ok(_) => {
+            state.reservations.invalidate_org(&payload.id);
+            let after = snapshot(org_repo.find_by_id(&payload.id).await);
+            audit.record("org.update", "org", &payload.id, before, after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 删除组织 (成员用户保留，不再受组织预算约束)
+pub async fn delete_org(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<DeleteOrgRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = snapshot(org_repo.find_by_id(&payload.id).await);
+    match org_repo.delete(&payload.id).await {
+        Ok(_) => {
+            state.reservations.invalidate_org(&payload.id);
+            audit.record("org.delete", "org", &payload.id, before, serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 列出组织成员
+pub async fn list_org_members(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<OrgMembersQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    match org_repo.list_members(&query.org_id).await {
+        Ok(members) => Json(members).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 添加组织成员或更新其角色与子限额
+pub async fn upsert_org_member(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<UpsertOrgMemberRequest>,
+) -> impl IntoResponse {
+    if let Some(res) = crate::org_handlers::reject_invalid_role(&payload.role) {
+        return res;
+    }
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = org_membership(&org_repo, &payload.org_id, &payload.user_id).await;
+    match org_repo.upsert_member(&payload.org_id, &payload.user_id, &payload.role, payload.token_limit, payload.cost_limit).await {
+        Ok(_) => {
+            state.reservations.invalidate_user(&payload.user_id);
+            let after = org_membership(&org_repo, &payload.org_id, &payload.user_id).await;
+            let target = format!("{}:{}", payload.org_id, payload.user_id);
+            audit.record("org_member.upsert", "org_member", &target, before, after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 移除组织成员
+pub async fn remove_org_member(
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(payload): Json<RemoveOrgMemberRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = org_membership(&org_repo, &payload.org_id, &payload.user_id).await;
+    match org_repo.remove_member(&payload.org_id, &payload.user_id).await {
+        Ok(_) => {
+            state.reservations.invalidate_user(&payload.user_id);
+            let target = format!("{}:{}", payload.org_id, payload.user_id);
+            audit.record("org_member.remove", "org_member", &target, before, serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 管理后台登录
+/// 实现逻辑: 支持用户名 + 密码 (Argon2 哈希校验，按用户名限制尝试频率) 与旧的 API Key 两种方式，
+/// 用户须拥有管理角色，启用两步验证时还需校验 TOTP 验证码。成功后签发短期会话令牌，
+/// 前端只保存该令牌，不再持有 API Key。
+pub async fn login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    extensions: Extensions,
+    Json(payload): Json<LoginRequest>
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+    let attempt = LoginAttempt {
+        state: &state,
+        headers: &headers,
+        extensions: &extensions,
+        username: payload.username.as_deref().unwrap_or_default(),
+        method: if payload.username.is_some() { "password" } else { "api_key" },
+    };
+
+    // 1. 校验凭据
+    let found = match (&payload.username, &payload.password, &payload.api_key) {
+        (Some(username), Some(password), _) => {
+            let limit = state.rate_limiter
+                .acquire(&format!("admin-login:{}", username), BucketConfig::per_minute(LOGIN_ATTEMPTS_PER_MINUTE), 1.0)
+                .await;
+            if !limit.allowed {
+                return ApiError::rate_limited("requests", "登录尝试过于频繁，请稍后再试", limit.retry_after).into_response();
+            }
+            // 用户不存在或未设置密码时校验占位哈希，响应耗时不暴露用户名是否存在
+            match user_repo.find_by_username(username).await {
+                Ok(Some(user)) => match user_repo.password_hash(&user.id).await {
+                    Ok(Some(hash)) if auth::password::verify_password(password, &hash) => Ok(Some(user)),
+                    Ok(Some(_)) => Ok(None),
+                    Ok(None) => {
+                        auth::password::verify_dummy(password);
+                        Ok(None)
+                    }
+                    Err(e) => Err(e),
+                },
+                Ok(None) => {
+                    auth::password::verify_dummy(password);
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        (_, _, Some(api_key)) if state.admin_auth.api_key_login => user_repo.find_by_api_key(api_key).await,
+        (_, _, Some(_)) => return (axum::http::StatusCode::FORBIDDEN, "未开启 API Key 登录，请使用用户名和密码").into_response(),
+        _ => return (axum::http::StatusCode::BAD_REQUEST, "请提供用户名和密码").into_response(),
+    };
+    let user = match found {
+        Ok(Some(user)) if user.status == "Active" => user,
+        Ok(_) => {
+            attempt.record(None, Some("invalid_credentials")).await;
+            return ApiError::unauthorized("用户名或密码错误").into_response();
+        }
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // 2. 管理角色
+    let roles = match load_roles(&state, &user.id).await {
+        Ok(roles) => roles,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    tracing::debug!("登录用户: {}, roles: {:?}, status: {}", user.username, roles, user.status);
+    if roles.is_empty() {
+        attempt.record(Some(&user), Some("not_admin")).await;
+        return (axum::http::StatusCode::FORBIDDEN, "仅限管理员登录").into_response();
+    }
+
+    // 3. 两步验证 (每个时间步的验证码只能使用一次)
+    if user.totp_enabled {
+        let Some(code) = payload.totp_code.as_deref().filter(|c| !c.is_empty()) else {
+            return ApiError::unauthorized("请输入两步验证码").with_code("totp_required").into_response();
+        };
+        let step = match user_repo.totp_secret(&user.id).await {
+            Ok(secret) => secret.and_then(|secret| auth::totp::verify_code_step(&secret, code)),
+            Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let Some(step) = step else {
+            attempt.record(Some(&user), Some("invalid_totp")).await;
+            return ApiError::unauthorized("两步验证码错误").with_code("invalid_totp").into_response();
+        };
+        match user_repo.accept_totp_step(&user.id, step as i64).await {
+            Ok(true) => {}
+            Ok(false) => {
+                attempt.record(Some(&user), Some("totp_replayed")).await;
+                return ApiError::unauthorized("两步验证码已使用，请等待下一个验证码").with_code("invalid_totp").into_response();
+            }
+            Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    // 4. 签发会话
+    match issue_admin_session(&state, &user.id, &headers, &extensions).await {
+        Ok((token, expires_at)) => {
+            attempt.record(Some(&user), None).await;
+            Json(json!({
+                "status": "success",
+                "token": token,
+                "expires_at": expires_at,
+                "user": user,
+                "roles": roles,
+                "permissions": rbac::permissions_of(&roles)
+            })).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 一次登录尝试 (用于审计，登录请求尚未认证)
+struct LoginAttempt<'a> {
+    state: &'a AppState,
+    headers: &'a HeaderMap,
+    extensions: &'a Extensions,
+    username: &'a str, // 尝试的用户名 (API Key 登录时为空，不记录 Key)
+    method: &'a str,
+}
+
+impl LoginAttempt<'_> {
+    /// 记录登录结果: 操作者为登录的用户，凭据无效时为尝试的用户名
+    async fn record(&self, user: Option<&User>, failure: Option<&str>) {
+        let (user_id, username) = user.map_or(("", self.username), |u| (u.id.as_str(), u.username.as_str()));
+        let audit = Auditor::new(self.state, self.headers, self.extensions, user_id, username);
+        let target_id = if user_id.is_empty() { self.username } else { user_id };
+        match failure {
+            None => audit.record("auth.login", "user", target_id, serde_json::Value::Null, json!({"method": self.method})).await,
+            Some(reason) => {
+                let after = json!({"method": self.method, "reason": reason});
+                audit.record("auth.login_failed", "user", target_id, serde_json::Value::Null, after).await
+            }
+        }
+    }
+}
+
+/// 签发管理后台会话 (顺带清理早已失效的会话记录)，返回明文令牌与过期时间
+pub(crate) async fn issue_admin_session(
+    state: &AppState,
+    user_id: &str,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+) -> utils::Result<(String, chrono::DateTime<chrono::Utc>)> {
+    let db = state.model_manager.db();
+    let session_repo = AdminSessionRepo::new(&db);
+    let now = chrono::Utc::now();
+    if let Err(e) = session_repo.delete_expired(now - chrono::Duration::days(7)).await {
+        tracing::warn!("清理过期会话失败: {}", e);
+    }
+    let expires_at = now + chrono::Duration::seconds(admin_session_ttl_secs());
+    let ip = crate::auth_middleware::client_ip(headers, extensions, &state.trusted_proxies);
+    let user_agent = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok());
+    let token = session_repo.create(user_id, ip.as_deref(), user_agent, expires_at).await?;
+    Ok((token, expires_at))
+}
+
+/// 当前登录的管理员信息 (单点登录跳转回控制台后据此补全用户与角色)
+pub async fn get_account(
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<AdminRoles>,
+    session: Option<Extension<AdminSession>>,
+) -> impl IntoResponse {
+    Json(json!({
+        "user": user,
+        "roles": roles.0,
+        "permissions": rbac::permissions_of(&roles.0),
+        "expires_at": session.map(|Extension(s)| s.expires_at)
+    }))
 }
 
-#[derive(Deserialize)]
-pub struct DeleteKeyRequest {
-    pub key_id: i64,
+/// 会话有效期 (秒)，环境变量 `ADMIN_SESSION_TTL_SECS` 可覆盖
+fn admin_session_ttl_secs() -> i64 {
+    std::env::var("ADMIN_SESSION_TTL_SECS").ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs: &i64| *secs > 0)
+        .unwrap_or(DEFAULT_ADMIN_SESSION_TTL_SECS)
 }
 
-
-/// 获取所有用户列表
-pub async fn list_users(State(state): State<AppState>) -> impl IntoResponse {
+/// 注销当前会话 (使用 API Key 访问时无会话可注销)
+pub async fn logout(
+    State(state): State<AppState>,
+    session: Option<Extension<AdminSession>>,
+    audit: Auditor,
+) -> impl IntoResponse {
+    let Some(Extension(session)) = session else {
+        return Json(json!({"status": "success"})).into_response();
+    };
     let db = state.model_manager.db();
-    let user_repo = UserRepo::new(&db);
-    match user_repo.list_all().await {
-        Ok(users) => Json(users).into_response(),
+    match AdminSessionRepo::new(&db).revoke(session.id).await {
+        Ok(_) => {
+            audit.record("auth.logout", "session", &session.id.to_string(), json!({"revoked": false}), json!({"revoked": true})).await;
+            Json(json!({"status": "success"})).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 更新用户配额
-pub async fn update_user_quota(
+/// 修改当前账号的密码 (已设置密码时需校验旧密码)，并注销该账号的其他会话
+pub async fn change_own_password(
     State(state): State<AppState>,
-    Json(payload): Json<UpdateQuotaRequest>
+    Extension(current_user): Extension<User>,
+    session: Option<Extension<AdminSession>>,
+    audit: Auditor,
+    Json(payload): Json<ChangePasswordRequest>,
 ) -> impl IntoResponse {
     let db = state.model_manager.db();
     let user_repo = UserRepo::new(&db);
-    match user_repo.update_quota(&payload.user_id, payload.rpm_limit, payload.token_quota).await {
-        Ok(_) => Json(json!({"status": "success"})).into_response(),
+    match user_repo.password_hash(&current_user.id).await {
+        Ok(Some(hash)) => {
+            let current = payload.current_password.as_deref().unwrap_or_default();
+            if !auth::password::verify_password(current, &hash) {
+                audit.record("account.password_failed", "user", &current_user.id, serde_json::Value::Null, json!({"reason": "invalid_current_password"})).await;
+                return (axum::http::StatusCode::FORBIDDEN, "当前密码错误").into_response();
+            }
+        }
+        Ok(None) => {}
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let hash = match auth::password::hash_password(&payload.new_password) {
+        Ok(hash) => hash,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if let Err(e) = user_repo.set_password_hash(&current_user.id, Some(&hash)).await {
+        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    let current_session = session.map(|Extension(s)| s.id);
+    match AdminSessionRepo::new(&db).revoke_user(&current_user.id, current_session).await {
+        Ok(revoked) => {
+            let after = json!({"password": "changed", "revoked_sessions": revoked});
+            audit.record("account.password", "user", &current_user.id, serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "revoked_sessions": revoked})).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 设置工具治理策略
-pub async fn update_tool_policy(
+/// 生成两步验证密钥 (验证码确认后才启用)
+pub async fn setup_totp(
     State(state): State<AppState>,
-    Json(payload): Json<UpdatePolicyRequest>
+    Extension(current_user): Extension<User>,
+    audit: Auditor,
 ) -> impl IntoResponse {
-    let db_conn = state.model_manager.db();
-    let policy_repo = ToolPolicyRepo::new(&db_conn.pool);
-    
-    match policy_repo.upsert_policy(&payload.tool_name, payload.user_id.as_deref(), &payload.policy).await {
-        Ok(_) => Json(json!({"status": "success"})).into_response(),
+    if current_user.totp_enabled {
+        return (axum::http::StatusCode::CONFLICT, "两步验证已启用，请先停用").into_response();
+    }
+    let secret = match auth::totp::generate_secret() {
+        Ok(secret) => secret,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let url = match auth::totp::provisioning_url(&secret, &current_user.username) {
+        Ok(url) => url,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let db = state.model_manager.db();
+    match UserRepo::new(&db).set_totp(&current_user.id, Some(&secret), false).await {
+        Ok(_) => {
+            // 密钥只在响应中返回，审计记录只标记已重新生成
+            audit.record("account.totp_setup", "user", &current_user.id, serde_json::Value::Null, json!({"totp_secret": "generated"})).await;
+            Json(json!({"status": "success", "secret": secret, "otpauth_url": url})).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 动态注册 MCP 客户端
-pub async fn register_mcp(
+/// 启用或停用两步验证 (均需当前有效的验证码)
+async fn toggle_totp(state: &AppState, audit: &Auditor, user: &User, code: &str, enable: bool) -> axum::response::Response {
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+    let secret = match user_repo.totp_secret(&user.id).await {
+        Ok(Some(secret)) => secret,
+        Ok(None) => return (axum::http::StatusCode::BAD_REQUEST, "尚未生成两步验证密钥").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let action = if enable { "account.totp_enable" } else { "account.totp_disable" };
+    if !auth::totp::verify_code(&secret, code) {
+        audit.record(&format!("{}_failed", action), "user", &user.id, serde_json::Value::Null, json!({"reason": "invalid_totp"})).await;
+        return ApiError::bad_request("两步验证码错误").with_code("invalid_totp").into_response();
+    }
+    let secret = enable.then_some(secret.as_str());
+    match user_repo.set_totp(&user.id, secret, enable).await {
+        Ok(_) => {
+            audit.record(action, "user", &user.id, json!({"totp_enabled": user.totp_enabled}), json!({"totp_enabled": enable})).await;
+            Json(json!({"status": "success", "totp_enabled": enable})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn enable_totp(
     State(state): State<AppState>,
-    Json(payload): Json<RegisterMcpRequest>
+    Extension(current_user): Extension<User>,
+    audit: Auditor,
+    Json(payload): Json<TotpCodeRequest>,
 ) -> impl IntoResponse {
-    use protocols::stdio_mcp_client::StdioMcpClient;
-    use protocols::mcp::{McpServerMeta, McpClient};
-    use std::sync::Arc;
-
-    let args_ref: Vec<&str> = payload.args.iter().map(|s| s.as_str()).collect();
-    match StdioMcpClient::spawn(&payload.command, &args_ref).await {
-        Ok(client) => {
-            let client: Arc<dyn McpClient> = Arc::new(client);
-            // 初始化
-
-            let meta = McpServerMeta {
-                name: payload.name.clone(),
-                version: "1.0.0".to_string(),
-                capabilities: json!({}),
-            };
+    toggle_totp(&state, &audit, &current_user, &payload.code, true).await
+}
 
-            if let Err(e) = client.initialize(meta).await {
-                return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, format!("MCP 初始化失败: {}", e)).into_response();
-            }
-            state.mcp_manager.register_client(payload.name, client).await;
-            Json(json!({"status": "success"})).into_response()
-        }
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, format!("MCP 启动失败: {}", e)).into_response(),
+pub async fn disable_totp(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    audit: Auditor,
+    Json(payload): Json<TotpCodeRequest>,
+) -> impl IntoResponse {
+    if !current_user.totp_enabled {
+        return (axum::http::StatusCode::BAD_REQUEST, "两步验证未启用").into_response();
     }
+    toggle_totp(&state, &audit, &current_user, &payload.code, false).await
 }
 
-/// 动态注销 MCP 客户端
-pub async fn unregister_mcp(
+/// 管理员重置其他用户的密码 (或清除密码) 与两步验证，并注销其全部会话
+pub async fn set_user_password(
     State(state): State<AppState>,
-    Json(payload): Json<UnregisterMcpRequest>
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<SetUserPasswordRequest>,
 ) -> impl IntoResponse {
-    state.mcp_manager.unregister_client(&payload.name).await;
-    Json(json!({"status": "success"})).into_response()
-}
+    if let Err(response) = ensure_manageable(&state, &caller, &payload.user_id).await {
+        return response;
+    }
+    let hash = match payload.password.as_deref().map(auth::password::hash_password).transpose() {
+        Ok(hash) => hash,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
 
-/// 获取所有模型列表
-pub async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
     let db = state.model_manager.db();
-    let config_repo = ConfigRepo::new(&db);
-    match config_repo.list_all().await {
-        Ok(configs) => Json(configs).into_response(),
+    let user_repo = UserRepo::new(&db);
+    let mut result = user_repo.set_password_hash(&payload.user_id, hash.as_deref()).await;
+    if result.is_ok() && payload.reset_totp {
+        result = user_repo.set_totp(&payload.user_id, None, false).await;
+    }
+    if let Err(e) = result {
+        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    let after = json!({"password": hash.is_some().then_some("set"), "totp_reset": payload.reset_totp});
+    audit.record("user.password", "user", &payload.user_id, serde_json::Value::Null, after).await;
+    match AdminSessionRepo::new(&db).revoke_user(&payload.user_id, None).await {
+        Ok(revoked) => Json(json!({"status": "success", "revoked_sessions": revoked})).into_response(),
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 获取最近调用日志
-pub async fn list_stats(State(state): State<AppState>) -> impl IntoResponse {
+/// 有效的管理后台会话列表
+pub async fn list_admin_sessions(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<SessionsQuery>,
+) -> impl IntoResponse {
     let db = state.model_manager.db();
-    let stats_repo = StatsRepo::new(&db);
-    match stats_repo.list_recent(100).await {
-        Ok(stats) => Json(stats).into_response(),
+    match AdminSessionRepo::new(&db).list_active(query.user_id.as_deref()).await {
+        Ok(sessions) => Json(sessions).into_response(),
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 登录验证 (校验 API Key 是否为管理员)
-pub async fn login(
+/// 撤销会话: 指定会话 ID，或撤销某用户的全部会话
+pub async fn revoke_admin_sessions(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<RevokeSessionsRequest>,
 ) -> impl IntoResponse {
     let db = state.model_manager.db();
-    let user_repo = UserRepo::new(&db);
-    
-    tracing::debug!("尝试登录 API Key: {}", payload.api_key);
-    match user_repo.find_by_api_key(&payload.api_key).await {
-        Ok(Some(user)) => {
-            tracing::debug!("找到用户: {}, is_admin: {}, status: {}", user.username, user.is_admin, user.status);
-            if user.is_admin {
-                Json(json!({
-                    "status": "success",
-                    "user": user
-                })).into_response()
-            } else {
-                (axum::http::StatusCode::FORBIDDEN, "仅限管理员登录").into_response()
-            }
+    let session_repo = AdminSessionRepo::new(&db);
+    let user_id = match (payload.id, payload.user_id) {
+        (Some(id), _) => match session_repo.find_by_id(id).await {
+            Ok(Some(session)) => session.user_id,
+            Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "会话不存在").into_response(),
+            Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        (None, Some(user_id)) => user_id,
+        (None, None) => return (axum::http::StatusCode::BAD_REQUEST, "请指定会话 ID 或用户 ID").into_response(),
+    };
+    if let Err(response) = ensure_manageable(&state, &caller, &user_id).await {
+        return response;
+    }
+
+    let result = match payload.id {
+        Some(id) => session_repo.revoke(id).await.map(|_| 1),
+        None => session_repo.revoke_user(&user_id, None).await,
+    };
+    match result {
+        Ok(revoked) => {
+            let after = json!({"session_id": payload.id, "revoked_sessions": revoked});
+            audit.record("session.revoke", "user", &user_id, serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "revoked_sessions": revoked})).into_response()
         }
-        Ok(None) => (axum::http::StatusCode::UNAUTHORIZED, "无效的 API Key").into_response(),
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -226,8 +1406,15 @@ pub async fn login(
 /// 创建新用户
 pub async fn create_user(
     State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<CreateUserRequest>
 ) -> impl IntoResponse {
+    // 管理员标记即超级管理员角色，只能由超级管理员授予
+    if payload.is_admin && !rbac::can_grant(&caller.0, Role::SuperAdmin) {
+        return (axum::http::StatusCode::FORBIDDEN, "仅超级管理员可创建管理员").into_response();
+    }
+
     let db = state.model_manager.db();
     let user_repo = UserRepo::new(&db);
     
@@ -240,7 +1427,11 @@ pub async fn create_user(
 
     let user_id = uuid::Uuid::new_v4().to_string();
     match user_repo.create(&user_id, &payload.username, &payload.api_key, payload.is_admin).await {
-        Ok(_) => Json(json!({"status": "success", "user_id": user_id})).into_response(),
+        Ok(_) => {
+            let after = snapshot(user_repo.find_by_id(&user_id).await);
+            audit.record("user.create", "user", &user_id, serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "user_id": user_id})).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -248,8 +1439,13 @@ pub async fn create_user(
 /// 更新用户信息 (用户名、API Key、状态)
 pub async fn update_user(
     State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<UpdateUserRequest>
 ) -> impl IntoResponse {
+    if let Err(response) = ensure_manageable(&state, &caller, &payload.user_id).await {
+        return response;
+    }
     let db = state.model_manager.db();
     let user_repo = UserRepo::new(&db);
 
@@ -260,8 +1456,14 @@ pub async fn update_user(
         _ => {}
     }
 
-    match user_repo.update_info(&payload.user_id, &payload.username, &payload.api_key, &payload.status).await {
-        Ok(_) => Json(json!({"status": "success"})).into_response(),
+    let api_key = payload.api_key.as_deref().filter(|k| !k.is_empty());
+    let before = snapshot(user_repo.find_by_id(&payload.user_id).await);
+    match user_repo.update_info(&payload.user_id, &payload.username, api_key, &payload.status).await {
+        Ok(_) => {
+            let after = snapshot(user_repo.find_by_id(&payload.user_id).await);
+            audit.record("user.update", "user", &payload.user_id, before, after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -270,6 +1472,8 @@ pub async fn update_user(
 pub async fn delete_user(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<DeleteUserRequest>
 ) -> impl IntoResponse {
     // 保护根管理员
@@ -281,11 +1485,18 @@ pub async fn delete_user(
     if payload.user_id == current_user.id {
         return (axum::http::StatusCode::FORBIDDEN, "不能删除当前登录的账号").into_response();
     }
+    if let Err(response) = ensure_manageable(&state, &caller, &payload.user_id).await {
+        return response;
+    }
 
     let db = state.model_manager.db();
     let user_repo = UserRepo::new(&db);
+    let before = snapshot(user_repo.find_by_id(&payload.user_id).await);
     match user_repo.delete(&payload.user_id).await {
-        Ok(_) => Json(json!({"status": "success"})).into_response(),
+        Ok(_) => {
+            audit.record("user.delete", "user", &payload.user_id, before, serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -293,8 +1504,12 @@ pub async fn delete_user(
 /// 创建新模型配置
 pub async fn create_model(
     State(state): State<AppState>,
+    audit: Auditor,
     Json(payload): Json<CreateModelRequest>
 ) -> impl IntoResponse {
+    if let Err(e) = lowart_core::health::parse_probe(payload.health_probe.as_deref()) {
+        return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
     let db = state.model_manager.db();
     let config_repo = ConfigRepo::new(&db);
     
@@ -306,6 +1521,22 @@ pub async fn create_model(
         base_url: payload.base_url,
         vendor_type: payload.vendor_type,
         cost_per_1k_tokens: payload.cost_per_1k_tokens,
+        input_price_per_1k: payload.input_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        output_price_per_1k: payload.output_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        cached_input_price_per_1k: payload.cached_input_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        model_group: payload.model_group,
+        tpm_limit: payload.tpm_limit,
+        max_concurrency: payload.max_concurrency,
+        max_queue: payload.max_queue,
+        queue_timeout_ms: payload.queue_timeout_ms,
+        breaker_window_secs: payload.breaker_window_secs,
+        breaker_min_requests: payload.breaker_min_requests,
+        breaker_error_rate: payload.breaker_error_rate,
+        breaker_slow_call_ms: payload.breaker_slow_call_ms,
+        breaker_slow_call_rate: payload.breaker_slow_call_rate,
+        breaker_open_secs: payload.breaker_open_secs,
+        breaker_max_open_secs: payload.breaker_max_open_secs,
+        health_probe: payload.health_probe,
         request_script: None,
         response_script: None,
         is_active: payload.is_active,
@@ -314,7 +1545,7 @@ pub async fn create_model(
 
     match config_repo.create(&config).await {
         Ok(_) => {
-            state.model_manager.clear_cache().await;
+            audit.record("model.create", "model", &config.id, serde_json::Value::Null, json!(config)).await;
             Json(json!({"status": "success", "id": config.id})).into_response()
         },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
@@ -324,10 +1555,15 @@ pub async fn create_model(
 /// 更新模型配置
 pub async fn update_model(
     State(state): State<AppState>,
+    audit: Auditor,
     Json(payload): Json<UpdateModelRequest>
 ) -> impl IntoResponse {
+    if let Err(e) = lowart_core::health::parse_probe(payload.health_probe.as_deref()) {
+        return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
     let db = state.model_manager.db();
     let config_repo = ConfigRepo::new(&db);
+    let before = snapshot(config_repo.find_by_id(&payload.id).await);
     
     let config = ModelConfig {
         id: payload.id,
@@ -337,6 +1573,22 @@ pub async fn update_model(
         base_url: payload.base_url,
         vendor_type: payload.vendor_type,
         cost_per_1k_tokens: payload.cost_per_1k_tokens,
+        input_price_per_1k: payload.input_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        output_price_per_1k: payload.output_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        cached_input_price_per_1k: payload.cached_input_price_per_1k.unwrap_or(payload.cost_per_1k_tokens * MICROS_PER_CENT),
+        model_group: payload.model_group,
+        tpm_limit: payload.tpm_limit,
+        max_concurrency: payload.max_concurrency,
+        max_queue: payload.max_queue,
+        queue_timeout_ms: payload.queue_timeout_ms,
+        breaker_window_secs: payload.breaker_window_secs,
+        breaker_min_requests: payload.breaker_min_requests,
+        breaker_error_rate: payload.breaker_error_rate,
+        breaker_slow_call_ms: payload.breaker_slow_call_ms,
+        breaker_slow_call_rate: payload.breaker_slow_call_rate,
+        breaker_open_secs: payload.breaker_open_secs,
+        breaker_max_open_secs: payload.breaker_max_open_secs,
+        health_probe: payload.health_probe,
         request_script: None,
         response_script: None,
         is_active: payload.is_active,
@@ -345,7 +1597,8 @@ pub async fn update_model(
 
     match config_repo.update(&config).await {
         Ok(_) => {
-            state.model_manager.clear_cache().await;
+            let after = snapshot(config_repo.find_by_id(&config.id).await);
+            audit.record("model.update", "model", &config.id, before, after).await;
             Json(json!({"status": "success"})).into_response()
         },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
@@ -355,14 +1608,16 @@ pub async fn update_model(
 /// 删除模型配置
 pub async fn delete_model(
     State(state): State<AppState>,
+    audit: Auditor,
     Json(payload): Json<DeleteModelRequest>
 ) -> impl IntoResponse {
     let db = state.model_manager.db();
     let config_repo = ConfigRepo::new(&db);
+    let before = snapshot(config_repo.find_by_id(&payload.id).await);
     
     match config_repo.delete(&payload.id).await {
         Ok(_) => {
-            state.model_manager.clear_cache().await;
+            audit.record("model.delete", "model", &payload.id, before, serde_json::Value::Null).await;
             Json(json!({"status": "success"})).into_response()
         },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
@@ -385,12 +1640,52 @@ pub async fn list_user_keys(
 /// 为用户创建新的 API Key
 pub async fn create_user_key(
     State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<CreateKeyRequest>,
 ) -> impl IntoResponse {
+    if let Err(response) = ensure_manageable(&state, &caller, &payload.user_id).await {
+        return response;
+    }
     let db = state.model_manager.db();
     let key_repo = db::ApiKeyRepo::new(&db);
     match key_repo.create(&payload.user_id, &payload.label).await {
-        Ok(key) => Json(json!({"status": "success", "key": key})).into_response(),
+        Ok(key) => {
+            let created = key_repo.find_by_key(&key).await;
+            let target = created.as_ref().ok().and_then(|k| k.as_ref()).map(|k| k.id.to_string()).unwrap_or_default();
+            audit.record("key.create", "key", &target, serde_json::Value::Null, snapshot(created)).await;
+            Json(json!({"status": "success", "key": key})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 更新 API Key 的独立限制与访问范围 (RPM/TPM、消费上限、可用模型与接口、有效期)
+pub async fn update_key_limits(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<UpdateKeyLimitsRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
+    if let Err(e) = auth::key_policy::validate_settings(&payload.settings) {
+        return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    let db = state.model_manager.db();
+    let key_repo = db::ApiKeyRepo::new(&db);
+
+    let old_key = key_repo.find_by_id(payload.key_id).await;
+    // 执行更新，消费上限的内存计数随之重新加载
+    match key_repo.update_settings(payload.key_id, &payload.settings).await {
+        Ok(_) => {
+            state.reservations.invalidate_key(payload.key_id);
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.limits", "key", &payload.key_id.to_string(), snapshot(old_key), after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -398,19 +1693,135 @@ pub async fn create_user_key(
 /// 重置 API Key
 pub async fn reset_user_key(
     State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<ResetKeyRequest>,
 ) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
     let db = state.model_manager.db();
     let key_repo = db::ApiKeyRepo::new(&db);
 
-    // 1. 获取旧 Key 用于清除缓存
-    if let Ok(Some(old_key)) = key_repo.find_by_id(payload.key_id).await {
-        state.user_cache.invalidate(&old_key.api_key).await;
+    let old_key = key_repo.find_by_id(payload.key_id).await;
+    match key_repo.reset(payload.key_id).await {
+        Ok(key) => {
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.reset", "key", &payload.key_id.to_string(), snapshot(old_key), after).await;
+            Json(json!({"status": "success", "key": key})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
+}
 
-    // 2. 执行重置
-    match key_repo.reset(payload.key_id).await {
-        Ok(key) => Json(json!({"status": "success", "key": key})).into_response(),
+/// 轮换 API Key: 签发新 Key，旧 Key 在宽限期内继续有效，便于调用方无中断切换
+pub async fn rotate_user_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<RotateKeyRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
+    let grace_seconds = payload.grace_seconds.unwrap_or(DEFAULT_ROTATION_GRACE_SECS);
+    if grace_seconds < 0 {
+        return (axum::http::StatusCode::BAD_REQUEST, "宽限期不能为负数").into_response();
+    }
+    let db = state.model_manager.db();
+    let key_repo = db::ApiKeyRepo::new(&db);
+
+    let old_key = match key_repo.find_by_id(payload.key_id).await {
+        Ok(Some(old_key)) => old_key,
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "Key 不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let grace_until = chrono::Utc::now() + chrono::Duration::seconds(grace_seconds);
+    match key_repo.rotate(payload.key_id, grace_until).await {
+        Ok(key) => {
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.rotate", "key", &payload.key_id.to_string(), json!(old_key), after).await;
+            Json(json!({"status": "success", "key": key, "previous_expires_at": grace_until})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 绑定或解除 API Key 的客户端证书身份 (mTLS 下出示该证书即视为持有此 Key)
+pub async fn set_key_client_cert(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<KeyClientCertRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
+    let db = state.model_manager.db();
+    let key_repo = db::ApiKeyRepo::new(&db);
+    let subject = payload.subject.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    if let Some(subject) = subject {
+        match key_repo.cert_subject_taken(subject, payload.key_id).await {
+            Ok(true) => return (axum::http::StatusCode::CONFLICT, "该证书身份已绑定其他 Key").into_response(),
+            Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            _ => {}
+        }
+    }
+
+    let old_key = key_repo.find_by_id(payload.key_id).await;
+    match key_repo.set_client_cert_subject(payload.key_id, subject).await {
+        Ok(_) => {
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.client_cert", "key", &payload.key_id.to_string(), snapshot(old_key), after).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 绑定或解除 API Key 的 Unix 本地进程身份 (UDS 模式下该 uid / gid 的进程无需出示 Key)
+pub async fn set_key_unix_peer(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<KeyUnixPeerRequest>,
+) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
+    let valid = |id: Option<i64>| id.is_none_or(|id| (0..=u32::MAX as i64).contains(&id));
+    if !valid(payload.uid) || !valid(payload.gid) {
+        return (axum::http::StatusCode::BAD_REQUEST, "uid / gid 超出范围").into_response();
+    }
+    let db = state.model_manager.db();
+    let key_repo = db::ApiKeyRepo::new(&db);
+    match key_repo.unix_peer_taken(payload.uid, payload.gid, payload.key_id).await {
+        Ok(true) => return (axum::http::StatusCode::CONFLICT, "该 uid 或 gid 已绑定其他 Key").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        _ => {}
+    }
+
+    let old_key = key_repo.find_by_id(payload.key_id).await;
+    match key_repo.set_unix_peer(payload.key_id, payload.uid, payload.gid).await {
+        Ok(_) => {
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.unix_peer", "key", &payload.key_id.to_string(), snapshot(old_key), after).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 闲置 Key 列表: 超过指定天数未使用 (从未使用的按创建时间计算)，用于清理或轮换
+pub async fn list_stale_keys(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<StaleKeysQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(query.days.unwrap_or(30).max(0));
+    match db::ApiKeyRepo::new(&db).list_stale(cutoff).await {
+        Ok(keys) => Json(keys).into_response(),
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
@@ -418,19 +1829,116 @@ pub async fn reset_user_key(
 /// 删除 API Key
 pub async fn delete_user_key(
     State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
     Json(payload): Json<DeleteKeyRequest>,
 ) -> impl IntoResponse {
+    if let Err(response) = ensure_key_manageable(&state, &caller, payload.key_id).await {
+        return response;
+    }
     let db = state.model_manager.db();
     let key_repo = db::ApiKeyRepo::new(&db);
 
-    // 1. 获取旧 Key 用于清除缓存
-    if let Ok(Some(old_key)) = key_repo.find_by_id(payload.key_id).await {
-        state.user_cache.invalidate(&old_key.api_key).await;
+    let old_key = key_repo.find_by_id(payload.key_id).await;
+    match key_repo.delete(payload.key_id).await {
+        Ok(_) => {
+            audit.record("key.delete", "key", &payload.key_id.to_string(), snapshot(old_key), serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
+}
 
-    // 2. 执行删除
-    match key_repo.delete(payload.key_id).await {
-        Ok(_) => Json(json!({"status": "success"})).into_response(),
+/// 全部角色及其权限
+pub async fn list_roles() -> impl IntoResponse {
+    let roles: Vec<_> = Role::ALL.iter()
+        .map(|r| json!({"role": r, "permissions": r.permissions()}))
+        .collect();
+    Json(roles).into_response()
+}
+
+/// 获取用户的角色与权限
+pub async fn get_user_roles(
+    State(state): State<AppState>,
+    axum::extract::Path(user_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match load_roles(&state, &user_id).await {
+        Ok(roles) => Json(json!({
+            "user_id": user_id,
+            "roles": roles,
+            "permissions": rbac::permissions_of(&roles)
+        })).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 设置用户的角色 (整体替换)
+/// 实现逻辑: 只校验发生变化的角色，超级管理员角色只能由超级管理员授予或撤销；根管理员始终保留超级管理员角色。
+pub async fn set_user_roles(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AdminRoles>,
+    audit: Auditor,
+    Json(payload): Json<SetUserRolesRequest>,
+) -> impl IntoResponse {
+    let mut roles = Vec::new();
+    for name in &payload.roles {
+        match Role::parse(name) {
+            Some(role) if !roles.contains(&role) => roles.push(role),
+            Some(_) => {}
+            None => return (axum::http::StatusCode::BAD_REQUEST, format!("未知角色: {}", name)).into_response(),
+        }
+    }
+    if payload.user_id == "admin_root_id" && !roles.contains(&Role::SuperAdmin) {
+        return (axum::http::StatusCode::FORBIDDEN, "根管理员不可撤销超级管理员角色").into_response();
+    }
+
+    let db = state.model_manager.db();
+    match UserRepo::new(&db).find_by_id(&payload.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "用户不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+    let current = match load_roles(&state, &payload.user_id).await {
+        Ok(current) => current,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let changed = roles.iter().filter(|r| !current.contains(r)).chain(current.iter().filter(|r| !roles.contains(r)));
+    if let Some(role) = changed.copied().find(|r| !rbac::can_grant(&caller.0, *r)) {
+        return (axum::http::StatusCode::FORBIDDEN, format!("无权变更角色: {}", role.as_str())).into_response();
+    }
+
+    let names: Vec<&str> = roles.iter().map(|r| r.as_str()).collect();
+    match db::RoleRepo::new(&db).set_roles(&payload.user_id, &names).await {
+        Ok(_) => {
+            audit.record("user.roles", "user", &payload.user_id, json!({"roles": current}), json!({"roles": roles})).await;
+            Json(json!({"status": "success", "roles": roles})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 查询审计日志 (按操作者、动作、对象、时间过滤，id 倒序分页)
+pub async fn list_audit_log(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<db::AuditQuery>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    match db::AuditRepo::new(&db).list(&query).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 校验审计日志哈希链 (返回的链头哈希可定期抄存到外部，用于发现整条链被重写)
+pub async fn verify_audit_log(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    match db::AuditRepo::new(&db).verify().await {
+        Ok(result) => {
+            if !result.valid {
+                tracing::error!("审计日志哈希链校验失败: {:?}", result);
+            }
+            Json(result).into_response()
+        }
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
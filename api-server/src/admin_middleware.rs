@@ -1,21 +1,96 @@
 use axum::{
     body::Body,
+    extract::State,
     http::{Request, StatusCode},
     middleware::Next,
     response::Response,
 };
-use db::User;
+use auth::rbac::{self, Permission, Role};
+use db::{AdminSession, RoleRepo, User};
+
+use crate::error::ApiError;
+use crate::router::AppState;
+
+/// 管理接口接受的认证方式
+/// 默认只接受 `/admin/login` 签发的会话令牌 (会话可过期、注销与撤销，并受密码与两步验证保护)；
+/// 以 API Key 登录或直接访问需显式开启，供尚未设置密码的部署或自动化脚本过渡使用。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdminAuthConfig {
+    pub api_key_login: bool,  // 允许以 API Key 登录换取会话 (ADMIN_API_KEY_LOGIN=true)
+    pub api_key_access: bool, // 允许以 API Key、客户端证书或 UDS 对端凭证直接访问管理接口 (ADMIN_API_KEY_AUTH=true)
+}
+
+impl AdminAuthConfig {
+    pub fn from_env() -> Self {
+        let enabled = |name: &str| std::env::var(name).is_ok_and(|v| v == "true" || v == "1");
+        Self {
+            api_key_login: enabled("ADMIN_API_KEY_LOGIN"),
+            api_key_access: enabled("ADMIN_API_KEY_AUTH"),
+        }
+    }
+}
+
+/// 当前管理员的角色 (由 admin_middleware 写入请求扩展)
+#[derive(Debug, Clone)]
+pub struct AdminRoles(pub Vec<Role>);
+
+impl AdminRoles {
+    pub fn has(&self, permission: Permission) -> bool {
+        rbac::has_permission(&self.0, permission)
+    }
+
+    pub fn is_super_admin(&self) -> bool {
+        self.0.contains(&Role::SuperAdmin)
+    }
+}
+
+/// 查询用户的角色 (忽略无法识别的角色)
+pub async fn load_roles(state: &AppState, user_id: &str) -> utils::Result<Vec<Role>> {
+    let db = state.model_manager.db();
+    let roles = RoleRepo::new(&db).list_for_user(user_id).await?;
+    Ok(roles.iter().filter_map(|r| Role::parse(r)).collect())
+}
 
 /// 管理员鉴权中间件
-/// 实现逻辑: 从请求扩展中提取已认证的用户对象，检查其 `is_admin` 标记。
-pub async fn admin_middleware(req: Request<Body>, next: Next) -> Result<Response, StatusCode> {
+/// 实现逻辑: 除非开启了 `api_key_access`，要求请求以会话令牌认证 (API Key、客户端证书与 UDS 凭证均被拒绝)；
+/// 随后从请求扩展中提取已认证的用户对象，查询其角色；没有任何角色的用户不可访问管理接口。
+/// 角色写入请求扩展，具体权限由各路由上的 `permission_middleware` 校验。
+pub async fn admin_middleware(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if !state.admin_auth.api_key_access && req.extensions().get::<AdminSession>().is_none() {
+        return Err(ApiError::unauthorized("管理接口需要使用会话令牌，请先通过 /admin/login 登录").with_code("session_required"));
+    }
+
     // 获取 auth_middleware 已经解析出来的用户
     let user = req.extensions().get::<User>().ok_or(StatusCode::UNAUTHORIZED)?;
 
-    if !user.is_admin {
+    let roles = load_roles(&state, &user.id).await.map_err(|e| {
+        tracing::error!("查询用户 {} 角色失败: {}", user.id, e);
+        ApiError::internal(e.to_string())
+    })?;
+    if roles.is_empty() {
         tracing::warn!("用户 {} 尝试访问管理接口被拒绝", user.id);
-        return Err(StatusCode::FORBIDDEN);
+        return Err(ApiError::forbidden("需要管理员权限"));
     }
 
+    req.extensions_mut().insert(AdminRoles(roles));
+    Ok(next.run(req).await)
+}
+
+/// 路由权限校验中间件 (以所需权限作为中间件状态)
+pub async fn permission_middleware(
+    State(permission): State<Permission>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let roles = req.extensions().get::<AdminRoles>().ok_or(StatusCode::FORBIDDEN)?;
+    if !roles.has(permission) {
+        let user_id = req.extensions().get::<User>().map(|u| u.id.as_str()).unwrap_or_default();
+        tracing::warn!("用户 {} 缺少权限 {:?}，访问 {} 被拒绝", user_id, permission, req.uri().path());
+        return Err(ApiError::forbidden(format!("缺少权限: {}", permission.as_str())));
+    }
     Ok(next.run(req).await)
 }
@@ -0,0 +1,60 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, Extensions, HeaderMap, StatusCode},
+};
+use db::{AuditRepo, NewAuditEntry, User};
+use serde_json::Value;
+
+use crate::auth_middleware::client_ip;
+use crate::error::ApiError;
+use crate::router::AppState;
+
+/// 审计记录器 (提取器)
+/// 实现逻辑: 从请求中取出已认证的操作者与来源 IP，管理接口在操作成功后调用 `record`
+/// 写入操作前后的状态差异 (密钥类字段脱敏)。
+pub struct Auditor {
+    state: AppState,
+    actor_id: String,
+    actor_name: String,
+    ip: Option<String>,
+}
+
+impl FromRequestParts<AppState> for Auditor {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let user = parts.extensions.get::<User>().ok_or(StatusCode::UNAUTHORIZED)?;
+        Ok(Self::new(state, &parts.headers, &parts.extensions, &user.id, &user.username))
+    }
+}
+
+impl Auditor {
+    /// 为尚未认证的请求 (如登录) 创建记录器，操作者由调用方给出
+    pub fn new(state: &AppState, headers: &HeaderMap, extensions: &Extensions, actor_id: &str, actor_name: &str) -> Self {
+        Self {
+            state: state.clone(),
+            actor_id: actor_id.to_string(),
+            actor_name: actor_name.to_string(),
+            ip: client_ip(headers, extensions, &state.trusted_proxies),
+        }
+    }
+
+    /// 记录一次管理操作 (before/after 为操作前后的对象，新建或删除时对应一侧为 null)
+    /// 操作已经完成，写入失败只记录错误日志，不影响响应。
+    pub async fn record(&self, action: &str, target_type: &str, target_id: &str, before: Value, after: Value) {
+        let changes = lowart_core::audit::diff(&before, &after);
+        let db = self.state.model_manager.db();
+        let entry = NewAuditEntry {
+            actor_id: &self.actor_id,
+            actor_name: &self.actor_name,
+            action,
+            target_type,
+            target_id,
+            changes: &changes,
+            ip: self.ip.as_deref(),
+        };
+        if let Err(e) = AuditRepo::new(&db).append(&entry).await {
+            tracing::error!("写入审计日志失败 ({} {} {}): {}", action, target_type, target_id, e);
+        }
+    }
+}
@@ -1,50 +1,227 @@
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    http::{Extensions, HeaderMap, Request},
     middleware::Next,
     response::Response,
-    extract::State,
+    extract::{ConnectInfo, State},
 };
-use auth::AuthManager;
+use auth::{AuthManager, key_policy};
+use db::{AdminSessionRepo, ApiKey, ApiKeyRepo, ChangeEvent, DbConnection, User, UserRepo, SESSION_TOKEN_PREFIX};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use crate::error::ApiError;
+use crate::tls::ClientCertIdentity;
+use crate::uds::PeerCredentials;
 use crate::router::AppState;
 
 /// 身份认证中间件
-/// 实现逻辑: 从 Authorization Header 提取 API Key 并通过 AuthManager 校验。
+/// 实现逻辑: 从 Authorization Header 提取 API Key 并通过 AuthManager 校验 (管理后台会话令牌见 `session_auth`)；
+/// 未携带 Header 时，经 mTLS 出示了客户端证书的按证书身份、经 UDS 连接的按对端进程 uid / gid 查找绑定的 Key
+/// (见 `cert_auth` 与 `peer_auth`)。
+/// 随后检查 Key 是否过期 (含轮换旧 Key 的宽限期) 以及是否被授权访问当前接口范围，
+/// 最后按节流间隔异步记录 Key 的最近使用时间与来源 IP。
 pub async fn auth_middleware(
     State(state): State<AppState>,
     req: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     let auth_header = req.headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer "));
 
-    match auth_header {
-        Some(api_key) => {
-            // 1. 尝试从缓存获取
-            if let Some(user) = state.user_cache.get(api_key).await {
-                let mut req = req;
-                req.extensions_mut().insert(user);
-                return Ok(next.run(req).await);
+    let (user, key) = match auth_header {
+        Some(token) if token.starts_with(SESSION_TOKEN_PREFIX) => {
+            let token = token.to_string();
+            return session_auth(&state, &token, req, next).await;
+        }
+        Some(api_key) => key_auth(&state, api_key).await?,
+        None => if let Some(identity) = req.extensions().get::<ClientCertIdentity>() {
+            cert_auth(&state, identity).await?
+        } else if let Some(peer) = req.extensions().get::<PeerCredentials>() {
+            peer_auth(&state, peer).await?
+        } else {
+            return Err(ApiError::unauthorized("缺少 API Key，请在 Authorization 头中以 Bearer 方式提供"));
+        },
+    };
+
+    // 1. Key 有效期与接口范围
+    let now = chrono::Utc::now();
+    if key_policy::is_expired(&key, now) {
+        tracing::warn!("Key #{} 已过期", key.id);
+        return Err(ApiError::unauthorized("API Key 已过期").with_code("expired_api_key"));
+    }
+    if !key_policy::allows_endpoint(&key, req.method().as_str(), req.uri().path()) {
+        tracing::warn!("Key #{} 无权访问 {} {}", key.id, req.method(), req.uri().path());
+        return Err(ApiError::forbidden("当前 API Key 无权访问该接口").with_code("endpoint_not_allowed"));
+    }
+
+    // 2. 记录最近使用 (同一 Key 在节流间隔内只写一次库)
+    if state.last_used_throttle.get(&key.id).await.is_none() {
+        state.last_used_throttle.insert(key.id, ()).await;
+        let db = state.model_manager.db();
+        let (key_id, ip) = (key.id, client_ip(req.headers(), req.extensions(), &state.trusted_proxies));
+        tokio::spawn(async move {
+            if let Err(e) = ApiKeyRepo::new(&db).update_last_used(key_id, ip.as_deref()).await {
+                tracing::warn!("记录 Key #{} 使用时间失败: {}", key_id, e);
             }
+        });
+    }
+
+    let mut req = req;
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    Ok(next.run(req).await)
+}
 
+/// API Key 认证
+async fn key_auth(state: &AppState, api_key: &str) -> Result<(User, ApiKey), ApiError> {
+    // 1. 尝试从缓存获取 (以哈希为键，内存中同样不保留明文)
+    let key_hash = utils::Crypto::hash_api_key(api_key);
+    let (user, key) = match state.user_cache.get(&key_hash).await {
+        Some(cached) => cached,
+        None => {
             // 2. 缓存未命中，执行数据库校验
             let manager = AuthManager::new(state.model_manager.db());
-            match manager.authenticate(api_key).await {
-                Ok(user) => {
-                    // 写入缓存
-                    state.user_cache.insert(api_key.to_string(), user.clone()).await;
-                    
-                    let mut req = req;
-                    req.extensions_mut().insert(user);
-                    Ok(next.run(req).await)
-                }
-                Err(_) => Err(StatusCode::UNAUTHORIZED),
+            let (user, key) = manager.authenticate_with_key(api_key).await
+                .map_err(|_| ApiError::unauthorized("API Key 无效"))?;
+            // 写入缓存
+            state.user_cache.insert(key_hash.clone(), (user.clone(), key.clone())).await;
+            (user, key)
+        }
+    };
+
+    // 缓存中的旧 Key 在宽限期结束后同样失效
+    if !key_policy::accepts_hash(&key, &key_hash, chrono::Utc::now()) {
+        state.user_cache.invalidate(&key_hash).await;
+        return Err(ApiError::unauthorized("API Key 已轮换，旧 Key 宽限期已结束").with_code("expired_api_key"));
+    }
+    Ok((user, key))
+}
+
+/// 客户端证书认证
+/// 实现逻辑: 证书链已在 TLS 握手时由受信 CA 校验，这里按证书的 CN 与各 SAN 查找绑定的 Key，
+/// 之后与 Bearer Key 一样受 Key 的有效期、接口范围与限额约束。缓存键带 `cert:` 前缀，与 Key 哈希互不冲突。
+async fn cert_auth(state: &AppState, identity: &ClientCertIdentity) -> Result<(User, ApiKey), ApiError> {
+    let cache_key = format!("cert:{}", identity.names.join("\n"));
+    if let Some(cached) = state.user_cache.get(&cache_key).await {
+        return Ok(cached);
+    }
+    let manager = AuthManager::new(state.model_manager.db());
+    let (user, key) = manager.authenticate_with_cert(&identity.names).await.map_err(|e| {
+        tracing::warn!("客户端证书 {:?} 认证失败: {}", identity.names, e);
+        ApiError::unauthorized("客户端证书未绑定有效的 API Key")
+    })?;
+    state.user_cache.insert(cache_key, (user.clone(), key.clone())).await;
+    Ok((user, key))
+}
+
+/// UDS 对端凭证认证
+/// 实现逻辑: 凭证由内核在连接时提供，本地进程无法伪造；按 uid 优先、主 gid 其次查找绑定的 Key，
+/// 认证结果以 `peer:` 前缀缓存。
+async fn peer_auth(state: &AppState, peer: &PeerCredentials) -> Result<(User, ApiKey), ApiError> {
+    let cache_key = format!("peer:{}:{}", peer.uid, peer.gid);
+    if let Some(cached) = state.user_cache.get(&cache_key).await {
+        return Ok(cached);
+    }
+    let manager = AuthManager::new(state.model_manager.db());
+    let (user, key) = manager.authenticate_with_unix_peer(peer.uid, peer.gid).await.map_err(|e| {
+        tracing::warn!("本地进程 (pid {:?}) 认证失败: {}", peer.pid, e);
+        ApiError::unauthorized("本地进程未绑定有效的 API Key")
+    })?;
+    state.user_cache.insert(cache_key, (user.clone(), key.clone())).await;
+    Ok((user, key))
+}
+
+/// 订阅实体变更，精确淘汰鉴权缓存
+/// 实现逻辑: 用户变更 (状态、限流配额、角色、删除等) 淘汰该用户所有 Key 的缓存项，Key 变更只淘汰该 Key
+/// (含轮换宽限期内以旧 Key 哈希缓存的项)。缓存需以 `support_invalidation_closures` 构建，否则退化为清空全部。
+pub fn watch_user_cache(db: &DbConnection, cache: &moka::future::Cache<String, (User, ApiKey)>) {
+    let cache = cache.clone();
+    db.changes.subscribe(move |event| {
+        let result = match event {
+            ChangeEvent::User(user_id) => {
+                let user_id = user_id.clone();
+                cache.invalidate_entries_if(move |_, (user, _)| user.id == user_id)
             }
+            ChangeEvent::ApiKey(key_id) => {
+                let key_id = *key_id;
+                cache.invalidate_entries_if(move |_, (_, key)| key.id == key_id)
+            }
+            ChangeEvent::Model(_) => return,
+        };
+        if let Err(e) = result {
+            tracing::warn!("按实体淘汰鉴权缓存失败，清空全部缓存: {}", e);
+            cache.invalidate_all();
         }
-        None => Err(StatusCode::UNAUTHORIZED),
+    });
+}
+
+/// 管理后台会话认证
+/// 实现逻辑: 会话令牌仅可访问管理接口；令牌过期、被撤销或用户被停用后立即失效 (每次请求查库，不经缓存)。
+/// 会话写入请求扩展，供登出等接口识别当前会话。
+async fn session_auth(state: &AppState, token: &str, mut req: Request<Body>, next: Next) -> Result<Response, ApiError> {
+    if !req.uri().path().starts_with("/admin") {
+        return Err(ApiError::unauthorized("会话令牌仅可用于管理接口").with_code("invalid_session"));
+    }
+
+    let db = state.model_manager.db();
+    let session = AdminSessionRepo::new(&db).find_active(token).await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .ok_or_else(|| ApiError::unauthorized("会话已过期或已注销，请重新登录").with_code("invalid_session"))?;
+    let user = UserRepo::new(&db).find_by_id(&session.user_id).await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .filter(|u| u.status == "Active")
+        .ok_or_else(|| ApiError::unauthorized("用户不存在或已停用").with_code("invalid_session"))?;
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(session);
+    Ok(next.run(req).await)
+}
+
+/// 受信任的反向代理 (环境变量 `TRUSTED_PROXIES`，逗号分隔的 IP 或 CIDR)
+/// 只有 TCP 对端位于其中时才采信 `X-Forwarded-For` / `X-Real-IP`，否则客户端可任意伪造来源 IP。
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Arc<Vec<IpNet>>);
+
+impl TrustedProxies {
+    pub fn parse(spec: &str) -> utils::Result<Self> {
+        let nets = spec.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| utils::anyhow!("TRUSTED_PROXIES 中的地址无效: {}", s)))
+            .collect::<utils::Result<Vec<_>>>()?;
+        Ok(Self(Arc::new(nets)))
+    }
+
+    pub fn from_env() -> utils::Result<Self> {
+        Self::parse(&std::env::var("TRUSTED_PROXIES").unwrap_or_default())
     }
 
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
 }
 
+/// 客户端 IP: 取 TCP 连接的对端地址；对端为受信代理时改取代理传递的头
+/// 实现逻辑: `X-Forwarded-For` 从右向左跳过受信代理，第一个不受信的地址即为客户端 (左侧的值可由客户端伪造)；
+/// 没有该头时取 `X-Real-IP`。经 UDS 连接时没有对端 IP。
+pub fn client_ip(headers: &HeaderMap, extensions: &Extensions, trusted: &TrustedProxies) -> Option<String> {
+    let peer = extensions.get::<ConnectInfo<SocketAddr>>()?.0.ip();
+    if !trusted.contains(&peer) {
+        return Some(peer.to_string());
+    }
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let forwarded: Vec<IpAddr> = header("x-forwarded-for")
+        .map(|v| v.split(',').filter_map(|ip| ip.trim().parse().ok()).collect())
+        .unwrap_or_default();
+    let client = forwarded.iter().rev().find(|ip| !trusted.contains(ip))
+        .or(forwarded.first())
+        .copied()
+        .or_else(|| header("x-real-ip").and_then(|ip| ip.trim().parse().ok()))
+        .unwrap_or(peer);
+    Some(client.to_string())
+}
@@ -0,0 +1,183 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use lowart_core::RateLimitDecision;
+use serde_json::json;
+use std::time::Duration;
+
+/// 错误响应体缓冲上限 (纯文本错误信息通常很短)
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// OpenAI 兼容的错误响应: `{"error": {"message", "type", "code"}}`
+/// 实现逻辑: 错误类型默认由状态码推导，限流等场景可附带 `x-ratelimit-*` 与 `Retry-After` 响应头，
+/// 官方 SDK 据此自动退避重试并展示错误信息。
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    kind: &'static str,
+    code: Option<&'static str>,
+    headers: HeaderMap,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            kind: error_type(status),
+            code: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message).with_code("invalid_api_key")
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    /// 配额或余额不足 (与 OpenAI 一致，类型与错误码均为 insufficient_quota)
+    pub fn insufficient_quota(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::PAYMENT_REQUIRED, message).with_type("insufficient_quota").with_code("insufficient_quota")
+    }
+
+    /// 请求数或 Token 数超限 (`kind` 为 requests 或 tokens)，附带 Retry-After
+    pub fn rate_limited(kind: &'static str, message: impl Into<String>, retry_after: Duration) -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, message)
+            .with_type(kind)
+            .with_code("rate_limit_exceeded")
+            .with_header(header::RETRY_AFTER, HeaderValue::from(retry_after_secs(retry_after)))
+    }
+
+    pub fn with_type(mut self, kind: &'static str) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or("Unknown error"))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": {
+                "message": self.message,
+                "type": self.kind,
+                "code": self.code,
+            }
+        });
+        let mut response = (self.status, Json(body)).into_response();
+        response.headers_mut().extend(self.headers);
+        response
+    }
+}
+
+/// 由状态码推导 OpenAI 错误类型
+fn error_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::PAYMENT_REQUIRED => "insufficient_quota",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        s if s.is_client_error() => "invalid_request_error",
+        _ => "server_error",
+    }
+}
+
+/// 写入一组限流响应头 (`kind` 为 requests 或 tokens)
+pub fn insert_rate_limit_headers(headers: &mut HeaderMap, kind: &str, decision: &RateLimitDecision) {
+    let entries = [
+        ("limit", HeaderValue::from(decision.limit)),
+        ("remaining", HeaderValue::from(decision.remaining)),
+        ("reset", HeaderValue::from_str(&format_reset(decision.reset_after)).expect("ascii")),
+    ];
+    for (name, value) in entries {
+        let name = HeaderName::try_from(format!("x-ratelimit-{}-{}", name, kind)).expect("valid header name");
+        headers.insert(name, value);
+    }
+}
+
+/// Retry-After 的秒数 (向上取整，至少 1 秒)
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0).min(u32::MAX as f64) as u64
+}
+
+/// 按 OpenAI 的格式输出重置时间，如 `20ms`、`1.5s`、`6m0s`
+fn format_reset(d: Duration) -> String {
+    let millis = d.as_millis().min(u64::MAX as u128) as u64;
+    if millis < 1000 {
+        return format!("{}ms", millis);
+    }
+    let secs = d.as_secs_f64();
+    if secs < 60.0 {
+        let secs = format!("{:.1}", secs);
+        return format!("{}s", secs.trim_end_matches(".0"));
+    }
+    let secs = secs.ceil() as u64;
+    format!("{}m{}s", secs / 60, secs % 60)
+}
+
+/// 错误响应规范化中间件
+/// 实现逻辑: 处理函数与框架 (如 JSON 解析失败、路由不存在) 返回的纯文本或空错误响应，
+/// 统一改写为 OpenAI 兼容的 JSON 错误体，原有响应头 (如限流头) 保留；已是 JSON 的错误响应不做处理。
+pub async fn error_middleware(req: Request<Body>, next: Next) -> Response {
+    let response = next.run(req).await;
+    let status = response.status();
+    let is_json = response.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await
+        .map(|b| String::from_utf8_lossy(&b).trim().to_string())
+        .unwrap_or_default();
+    let error = if text.is_empty() { ApiError::from(status) } else { ApiError::new(status, text) };
+
+    let mut converted = error.into_response();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_TYPE);
+    for (name, value) in converted.headers() {
+        parts.headers.insert(name.clone(), value.clone());
+    }
+    *converted.headers_mut() = parts.headers;
+    converted
+}
+
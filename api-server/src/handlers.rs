@@ -3,6 +3,7 @@ use axum::response::sse::{Event, Sse};
 use serde::Deserialize;
 use serde_json::{Value, json};
 use db::{JobRepo, AsyncJob, FallbackRepo};
+use lowart_core::{rate_limiter, BillingService, BucketConfig, ModelPermit, QuotaHold, Reservation, TokenCounter, TokenUsage, UsageRecord};
 
 use utils::Result;
 
@@ -12,19 +13,112 @@ use std::task::{Context, Poll};
 use futures::Stream;
 use metrics::counter;
 
+use crate::error::ApiError;
+
 
 #[derive(Clone)]
 pub struct ModelId(pub String);
 
+/// 异步提交计费: 记录实时 Token 指标并写入计费账本
+fn spawn_billing(billing: Arc<BillingService>, record: UsageRecord) {
+    tokio::spawn(async move {
+        counter!("gateway_tokens_total", "type" => "request", "model" => record.model_id.clone()).increment(record.usage.input_tokens as u64);
+        counter!("gateway_tokens_total", "type" => "response", "model" => record.model_id.clone()).increment(record.usage.output_tokens as u64);
+
+        if let Err(e) = billing.record_usage(record).await {
+            tracing::error!("记录计费失败: {}", e);
+        }
+    });
+}
+
+/// 候选模型开始处理请求后，将其周期配额占用并入配额预占 (没有预占时占用随本次处理结束释放)
+fn keep_quota_hold(reservation: &Option<Reservation>, hold: &mut Option<QuotaHold>) {
+    if let (Some(reservation), Some(hold)) = (reservation, hold.take()) {
+        reservation.hold_quota(hold);
+    }
+}
+
+/// 默认每个用户对单个工具每分钟最多调用的次数
+/// 每个用户对单个工具的默认每分钟调用上限 (环境变量 `TOOL_CALLS_PER_MINUTE` 可覆盖)
+pub const DEFAULT_TOOL_CALLS_PER_MINUTE: i64 = 60;
+
+/// 工具调用限流 (令牌桶，按用户与工具名计数)，超限时返回作为工具结果的提示而不执行
+async fn throttle_tool_call(state: &crate::router::AppState, user_id: &str, tool_name: &str) -> Option<String> {
+    let limit = state.tool_calls_per_minute;
+    if limit <= 0 {
+        return None;
+    }
+    let decision = state.rate_limiter
+        .acquire(&rate_limiter::tool_scope(user_id, tool_name), BucketConfig::per_minute(limit), 1.0)
+        .await;
+    if decision.allowed {
+        return None;
+    }
+    tracing::warn!("用户 {} 调用工具 {} 过快: 每分钟最多 {} 次", user_id, tool_name, limit);
+    Some(format!("执行失败: 工具调用过于频繁，请在 {} 秒后重试", decision.retry_after.as_secs().max(1)))
+}
+
+/// 估算一轮非流式调用的用量: 优先使用厂商返回的 usage，否则本地计数
+fn usage_of_round(payload: &Value, res: &Value) -> TokenUsage {
+    if let Some(usage) = TokenUsage::from_response(res) {
+        return usage;
+    }
+    let req_tokens = payload.get("messages")
+        .map(TokenCounter::count_messages_tokens)
+        .unwrap_or(0);
+    let res_tokens = res.get("choices").and_then(|c| c.get(0))
+        .and_then(|c| c.get("message"))
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+        .map(TokenCounter::count_tokens)
+        .unwrap_or(0);
+    TokenUsage::new(req_tokens as i64, res_tokens as i64)
+}
+
 struct TokenAccountingStream<S> {
     inner: S,
     user: db::User,
+    api_key_id: Option<i64>,
     model_id: String,
     req_tokens: usize,
     accumulated_content: String,
-    db: Arc<db::DbConnection>,
+    reported_usage: Option<TokenUsage>,
+    reservation: Option<Reservation>,
+    billing: Arc<BillingService>,
     first_chunk_logged: bool,
     start_time: std::time::Instant,
+    settled: bool,
+    _permit: ModelPermit, // 模型并发槽位，流结束或连接断开时释放
+}
+
+impl<S> TokenAccountingStream<S> {
+    /// 按已转发的内容结算用量 (流正常结束或客户端中途断开时各结算一次)
+    /// 实现逻辑: 优先使用厂商在分片中上报的 usage，否则按提示词与已累积的回复本地计数
+    fn settle(&mut self) {
+        if self.settled {
+            return;
+        }
+        self.settled = true;
+        let usage = self.reported_usage.unwrap_or_else(|| {
+            let res_tokens = TokenCounter::count_tokens(&self.accumulated_content);
+            TokenUsage::new(self.req_tokens as i64, res_tokens as i64)
+        });
+        spawn_billing(Arc::clone(&self.billing), UsageRecord {
+            user_id: self.user.id.clone(),
+            api_key_id: self.api_key_id,
+            model_id: self.model_id.clone(),
+            usage,
+            duration_ms: self.start_time.elapsed().as_millis() as i64,
+            reservation: self.reservation.take(),
+        });
+    }
+}
+
+impl<S> Drop for TokenAccountingStream<S> {
+    fn drop(&mut self) {
+        // 客户端断开时上游已产生的用量同样计费，而非直接归还预占
+        self.settle();
+    }
 }
 
 impl<S> Stream for TokenAccountingStream<S> 
@@ -49,8 +143,11 @@ where
                         let line = line.trim();
                         if line.starts_with("data: ") && line != "data: [DONE]" {
                             if let Ok(parsed) = serde_json::from_str::<Value>(&line[6..]) {
+                                if let Some(usage) = TokenUsage::from_response(&parsed) {
+                                    self.reported_usage = Some(usage);
+                                }
                                 if let Some(content) = parsed.get("choices").and_then(|v| v.as_array())
-                                    .and_then(|a| a.get(0))
+                                    .and_then(|a| a.first())
                                     .and_then(|c| c.get("delta"))
                                     .and_then(|d| d.get("content"))
                                     .and_then(|t| t.as_str()) {
@@ -62,8 +159,11 @@ where
                     found_content
                 } else {
                     // 处理标准格式
+                    if let Some(usage) = TokenUsage::from_response(&val) {
+                        self.reported_usage = Some(usage);
+                    }
                     val.get("choices").and_then(|v| v.as_array())
-                        .and_then(|a| a.get(0))
+                        .and_then(|a| a.first())
                         .and_then(|c| c.get("delta"))
                         .and_then(|d| d.get("content"))
                         .and_then(|t| t.as_str())
@@ -86,28 +186,7 @@ where
                 Poll::Ready(Some(Ok(Event::default().event("error").data(e.to_string()))))
             }
             Poll::Ready(None) => {
-                let content = self.accumulated_content.clone();
-                let user_id = self.user.id.clone();
-                let model_id = self.model_id.clone();
-                let req_tokens = self.req_tokens;
-                let start_time = self.start_time;
-                let db = self.db.clone();
-
-                tokio::spawn(async move {
-                    use lowart_core::TokenCounter;
-                    let res_tokens = TokenCounter::count_tokens(&content);
-                    let total_tokens = (req_tokens + res_tokens) as i64;
-                    let duration = start_time.elapsed().as_millis() as i64;
-                    
-                    // 增加实时指标记录
-                    counter!("gateway_tokens_total", "type" => "request", "model" => model_id.clone()).increment(req_tokens as u64);
-                    counter!("gateway_tokens_total", "type" => "response", "model" => model_id.clone()).increment(res_tokens as u64);
-
-                    let _ = db::UserRepo::new(&db).increment_token_usage(&user_id, total_tokens).await;
-                    let _ = db::StatsRepo::new(&db).record_usage(&user_id, &model_id, req_tokens as i64, res_tokens as i64, "厂商返回响应", duration).await;
-                });
-
-
+                self.settle();
                 Poll::Ready(None)
             }
             Poll::Pending => Poll::Pending,
@@ -118,12 +197,15 @@ where
 pub async fn chat_completions(
     State(state): State<crate::router::AppState>,
     Extension(user): Extension<db::User>,
+    Extension(key): Extension<db::ApiKey>,
+    reservation: Option<Extension<Reservation>>,
     Json(payload): Json<Value>,
 ) -> impl IntoResponse {
     let request_start_time = std::time::Instant::now();
+    let reservation = reservation.map(|Extension(r)| r);
     let primary_model_id = match payload.get("model").and_then(|m| m.as_str()) {
         Some(m) => m.to_string(),
-        None => return (axum::http::StatusCode::BAD_REQUEST, "Missing model").into_response(),
+        None => return ApiError::bad_request("Missing model").into_response(),
     };
 
     // 1. 获取所有候选模型 (主模型 + 降级模型)
@@ -131,16 +213,43 @@ pub async fn chat_completions(
     let fallback_repo = FallbackRepo::new(&db_conn);
     let mut candidate_models = vec![primary_model_id.clone()];
     if let Ok(mut fallbacks) = fallback_repo.get_fallbacks_for_model(&primary_model_id).await {
+        // 降级模型同样受 Key 的模型白名单约束，不允许借降级调用未授权的模型
+        fallbacks.retain(|m| {
+            let allowed = auth::key_policy::allows_model(&key, m);
+            if !allowed {
+                tracing::debug!("Key #{} 无权调用降级模型 {}，已跳过", key.id, m);
+            }
+            allowed
+        });
         candidate_models.append(&mut fallbacks);
     }
 
     // 2. 依次尝试候选模型
+    let estimate = lowart_core::reservation::estimate_usage(&payload);
+    let mut quota_skipped = 0;
     for (i, current_model_id) in candidate_models.iter().enumerate() {
-        // A. 断路器检查 (主模型除外，或者主模型也检查以保安全)
-        if !state.circuit_breaker.is_allowed(current_model_id).await {
+        // A0. 周期配额 (按候选模型匹配策略): 已用完的候选跳过，放行时按预估用量占用
+        let pricing = state.model_manager.get_pricing(current_model_id).await.unwrap_or_default();
+        let mut quota_hold = match state.quota.reserve(&user.id, current_model_id, estimate.total(), pricing.cost(&estimate)).await {
+            Ok(Ok(hold)) => Some(hold),
+            Ok(Err(v)) => {
+                tracing::warn!("用户 {} 模型 {} 超出{}配额 (策略 #{}): tokens={}, cost={}",
+                    user.username, current_model_id, v.period, v.policy_id, v.tokens_used, v.cost_used);
+                quota_skipped += 1;
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("检查用户 {} 周期配额失败: {}", user.id, e);
+                return ApiError::internal(e.to_string()).into_response();
+            }
+        };
+
+        // A. 断路器检查 (模型配置覆盖全局默认参数；半开期间只放行有限的探测请求)
+        let breaker = state.circuit_breaker.config_for(state.model_manager.get_config(current_model_id).await.ok().as_deref());
+        let Some(breaker_permit) = state.circuit_breaker.try_acquire(current_model_id, &breaker) else {
             tracing::warn!("模型 {} 处于熔断状态，跳过", current_model_id);
             continue;
-        }
+        };
 
         // B. 获取模型适配器
         let (model, request_script, _response_script) = match state.model_manager.get_model_with_scripts(current_model_id).await {
@@ -151,6 +260,18 @@ pub async fn chat_completions(
             }
         };
 
+        // B2. 获取模型并发槽位 (槽位已满时按优先级排队)，持有至本次调用结束
+        let permit = match state.model_manager.acquire_slot(current_model_id, &user.id, user.priority).await {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::warn!("模型 {} 排队失败: {}", current_model_id, e);
+                if i < candidate_models.len() - 1 {
+                    continue;
+                }
+                return ApiError::new(axum::http::StatusCode::SERVICE_UNAVAILABLE, e.to_string()).with_code("model_overloaded").into_response();
+            }
+        };
+
         // C. 应用 Rhai 转换 (每次可能需要基于新的模型重新转换)
         let payload_val: Value = if let Some(script) = request_script {
             match state.rhai_engine.transform(&script, payload.clone()) {
@@ -187,40 +308,47 @@ pub async fn chat_completions(
             };
 
             if let Err(e) = job_repo.create_job(&job).await {
-                return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+                return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
             }
 
             // 后端异步执行 (注: 异步任务内部暂不实现多级降级，仅对当前模型负责)
             let user_id = user.id.clone();
+            let api_key_id = key.id;
             let model_id_str = current_model_id.clone();
+            let billing = Arc::clone(&state.billing);
             let db_clone = Arc::clone(&db_conn);
             let model_clone = Arc::clone(&model);
             let payload_clone = payload_val.clone();
             let job_id_clone = job_id.clone();
-            let cb_clone = Arc::clone(&state.circuit_breaker);
+            let breaker = breaker.clone();
+            keep_quota_hold(&reservation, &mut quota_hold);
+            let reservation = reservation.clone();
 
             tokio::spawn(async move {
+                let _permit = permit;
                 let job_repo = JobRepo::new(&db_clone.pool);
                 let _ = job_repo.update_status(&job_id_clone, "running", None, None).await;
 
+                let call_start = std::time::Instant::now();
                 match model_clone.chat_completions(payload_clone.clone()).await {
 
                     Ok(res) => {
-                        cb_clone.report_result(&model_id_str, true).await;
+                        breaker_permit.record(&breaker, true, call_start.elapsed());
                         let res_str = res.to_string();
                         let _ = job_repo.update_status(&job_id_clone, "completed", Some(&res_str), None).await;
                         
-                        // Token 统计
-                        use lowart_core::TokenCounter;
-                        let req_tokens = TokenCounter::count_messages_tokens(payload_clone.get("messages").unwrap_or(&json!([])));
-                        let res_tokens = TokenCounter::count_tokens(res.get("choices").and_then(|c| c.get(0)).and_then(|c| c.get("message")).and_then(|m| m.get("content")).and_then(|c| c.as_str()).unwrap_or_default());
-
-                        let duration = request_start_time.elapsed().as_millis() as i64;
-                        let _ = db::UserRepo::new(&db_clone).increment_token_usage(&user_id, (req_tokens + res_tokens) as i64).await;
-                        let _ = db::StatsRepo::new(&db_clone).record_usage(&user_id, &model_id_str, req_tokens as i64, res_tokens as i64, "厂商返回响应", duration).await;
+                        // Token 统计与计费
+                        spawn_billing(billing, UsageRecord {
+                            user_id,
+                            api_key_id: Some(api_key_id),
+                            model_id: model_id_str,
+                            usage: usage_of_round(&payload_clone, &res),
+                            duration_ms: request_start_time.elapsed().as_millis() as i64,
+                            reservation,
+                        });
                     }
                     Err(e) => {
-                        cb_clone.report_result(&model_id_str, false).await;
+                        breaker_permit.record(&breaker, false, call_start.elapsed());
                         let _ = job_repo.update_status(&job_id_clone, "failed", None, Some(&e.to_string())).await;
                     }
                 }
@@ -234,56 +362,64 @@ pub async fn chat_completions(
         }
 
         if stream_mode {
+            // 流式调用以建立响应流的耗时计入慢调用
+            let call_start = std::time::Instant::now();
             match model.chat_completions_stream(payload_val.clone()).await {
                 Ok(stream) => {
-                    state.circuit_breaker.report_result(current_model_id, true).await;
-                    use lowart_core::TokenCounter;
+                    breaker_permit.record(&breaker, true, call_start.elapsed());
+                    keep_quota_hold(&reservation, &mut quota_hold);
                     let req_tokens = payload_val.get("messages")
-                        .map(|m| TokenCounter::count_messages_tokens(m))
+                        .map(TokenCounter::count_messages_tokens)
                         .unwrap_or(0);
 
                     let accounting_stream = TokenAccountingStream {
                         inner: stream,
                         user: user.clone(),
+                        api_key_id: Some(key.id),
                         model_id: current_model_id.clone(),
                         req_tokens,
                         accumulated_content: String::new(),
-                        db: state.model_manager.db(),
+                        reported_usage: None,
+                        reservation: reservation.clone(),
+                        billing: Arc::clone(&state.billing),
                         first_chunk_logged: false,
                         start_time: request_start_time,
+                        settled: false,
+                        _permit: permit,
                     };
                     let mut res = Sse::new(accounting_stream).into_response();
                     res.extensions_mut().insert(ModelId(current_model_id.clone()));
                     return res;
                 },
                 Err(e) => {
-                    state.circuit_breaker.report_result(current_model_id, false).await;
+                    breaker_permit.record(&breaker, false, call_start.elapsed());
                     if i < candidate_models.len() - 1 {
                         tracing::warn!("模型 {} 流式调用失败，准备降级: {}", current_model_id, e);
                         continue;
                     }
-                    return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+                    return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
                 }
             }
         } else {
 
             let mut current_payload = payload_val.clone();
-            let mut total_req_tokens = 0;
-            let mut total_res_tokens = 0;
+            let mut total_usage = TokenUsage::default();
             let max_iterations = 5;
+            // 断路器只统计首轮上游调用 (与降级判断一致)
+            let mut breaker_permit = Some(breaker_permit);
 
             for iter in 0..max_iterations {
+                let call_start = std::time::Instant::now();
                 match model.chat_completions(current_payload.clone()).await {
                     Ok(res) => {
-                        state.circuit_breaker.report_result(current_model_id, true).await;
-                        
-                        use lowart_core::TokenCounter;
-                        if let Some(msgs) = current_payload.get("messages") {
-                            total_req_tokens += TokenCounter::count_messages_tokens(msgs);
+                        if let Some(permit) = breaker_permit.take() {
+                            permit.record(&breaker, true, call_start.elapsed());
                         }
+                        keep_quota_hold(&reservation, &mut quota_hold);
+                        total_usage += usage_of_round(&current_payload, &res);
 
                         let choices = res.get("choices").and_then(|v| v.as_array());
-                        let choice = choices.and_then(|a| a.get(0));
+                        let choice = choices.and_then(|a| a.first());
                         let message_obj = choice.and_then(|c| c.get("message"));
                         let tool_calls = message_obj.and_then(|m| m.get("tool_calls")).and_then(|t| t.as_array());
 
@@ -315,9 +451,12 @@ pub async fn chat_completions(
                                             requires_confirm.push(call.clone());
                                         },
                                         _ => {
-                                            let result = match state.mcp_manager.call_tool_any(tool_name, arguments).await {
-                                                Ok(out) => out.to_string(),
-                                                Err(e) => format!("工具调用失败: {}", e),
+                                            let result = match throttle_tool_call(&state, &user.id, tool_name).await {
+                                                Some(throttled) => throttled,
+                                                None => match state.mcp_manager.call_tool_any(tool_name, arguments).await {
+                                                    Ok(out) => out.to_string(),
+                                                    Err(e) => format!("工具调用失败: {}", e),
+                                                },
                                             };
                                             tool_results.push(json!({
                                                 "role": "tool",
@@ -348,9 +487,27 @@ pub async fn chat_completions(
                                         &requires_confirm
                                     ).await {
                                         tracing::error!("保存会话状态失败: {}", e);
-                                        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "保存授权上下文失败").into_response();
+                                        spawn_billing(Arc::clone(&state.billing), UsageRecord {
+                                            user_id: user.id.clone(),
+                                            api_key_id: Some(key.id),
+                                            model_id: current_model_id.clone(),
+                                            usage: total_usage,
+                                            duration_ms: request_start_time.elapsed().as_millis() as i64,
+                                            reservation: reservation.clone(),
+                                        });
+                                        return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, "保存授权上下文失败").into_response();
                                     }
 
+                                    // 已完成的轮次在等待授权前先行计费
+                                    spawn_billing(Arc::clone(&state.billing), UsageRecord {
+                                        user_id: user.id.clone(),
+                                        api_key_id: Some(key.id),
+                                        model_id: current_model_id.clone(),
+                                        usage: total_usage,
+                                        duration_ms: request_start_time.elapsed().as_millis() as i64,
+                                        reservation: reservation.clone(),
+                                    });
+
                                     return Json(json!({
                                         "status": "require_confirmation",
                                         "session_id": session_id,
@@ -370,27 +527,13 @@ pub async fn chat_completions(
                             }
                         }
 
-                        if let Some(choices_arr) = res.get("choices").and_then(|v| v.as_array()) {
-                            if let Some(choice_first) = choices_arr.get(0) {
-                                if let Some(m) = choice_first.get("message") {
-                                    if let Some(content) = m.get("content").and_then(|v| v.as_str()) {
-                                        total_res_tokens += lowart_core::TokenCounter::count_tokens(content);
-                                    }
-                                }
-                            }
-                        }
-
-                        let user_id = user.id.clone();
-                        let model_repo_id = current_model_id.clone();
-                        let duration = request_start_time.elapsed().as_millis() as i64;
-                        let db = state.model_manager.db();
-                        tokio::spawn(async move {
-                             // 增加实时指标记录
-                             counter!("gateway_tokens_total", "type" => "request", "model" => model_repo_id.clone()).increment(total_req_tokens as u64);
-                             counter!("gateway_tokens_total", "type" => "response", "model" => model_repo_id.clone()).increment(total_res_tokens as u64);
-
-                             let _ = db::UserRepo::new(&db).increment_token_usage(&user_id, (total_req_tokens + total_res_tokens) as i64).await;
-                             let _ = db::StatsRepo::new(&db).record_usage(&user_id, &model_repo_id, total_req_tokens as i64, total_res_tokens as i64, "厂商返回响应", duration).await;
+                        spawn_billing(Arc::clone(&state.billing), UsageRecord {
+                            user_id: user.id.clone(),
+                            api_key_id: Some(key.id),
+                            model_id: current_model_id.clone(),
+                            usage: total_usage,
+                            duration_ms: request_start_time.elapsed().as_millis() as i64,
+                            reservation: reservation.clone(),
                         });
 
 
@@ -399,21 +542,48 @@ pub async fn chat_completions(
                         return axum_res;
                     },
                     Err(e) => {
+                        if let Some(permit) = breaker_permit.take() {
+                            permit.record(&breaker, false, call_start.elapsed());
+                        }
                         // 仅在第一轮循环失败时尝试降级
-                        if iter == 0 {
-                            state.circuit_breaker.report_result(current_model_id, false).await;
-                            if i < candidate_models.len() - 1 {
-                                tracing::warn!("模型 {} 调用失败，准备降级: {}", current_model_id, e);
-                                break; // 跳出迭代循环，进入下一候选模型
-                            }
+                        if iter == 0 && i < candidate_models.len() - 1 {
+                            tracing::warn!("模型 {} 调用失败，准备降级: {}", current_model_id, e);
+                            break; // 跳出迭代循环，进入下一候选模型
                         }
-                        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+                        // 工具循环中途失败: 此前各轮的用量仍需计费
+                        if iter > 0 {
+                            spawn_billing(Arc::clone(&state.billing), UsageRecord {
+                                user_id: user.id.clone(),
+                                api_key_id: Some(key.id),
+                                model_id: current_model_id.clone(),
+                                usage: total_usage,
+                                duration_ms: request_start_time.elapsed().as_millis() as i64,
+                                reservation: reservation.clone(),
+                            });
+                        }
+                        return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
                     }
                 }
             }
+
+            // 工具调用轮次耗尽: 已产生的用量计费后结束请求
+            if total_usage.total() > 0 {
+                spawn_billing(Arc::clone(&state.billing), UsageRecord {
+                    user_id: user.id.clone(),
+                    api_key_id: Some(key.id),
+                    model_id: current_model_id.clone(),
+                    usage: total_usage,
+                    duration_ms: request_start_time.elapsed().as_millis() as i64,
+                    reservation: reservation.clone(),
+                });
+                return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, "工具调用轮次超过上限").into_response();
+            }
         }
     }
-    (axum::http::StatusCode::SERVICE_UNAVAILABLE, "所有可用模型均无法处理请求").into_response()
+    if quota_skipped == candidate_models.len() {
+        return ApiError::insufficient_quota(format!("已超出模型 {} 的周期配额", primary_model_id)).into_response();
+    }
+    ApiError::new(axum::http::StatusCode::SERVICE_UNAVAILABLE, "所有可用模型均无法处理请求").into_response()
 }
 
 
@@ -427,12 +597,12 @@ pub async fn confirm_tool_call(
 
     let session = match session_repo.load_session(&payload.session_id).await {
         Ok(Some(s)) => s,
-        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "Session not found").into_response(),
-        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Ok(None) => return ApiError::new(axum::http::StatusCode::NOT_FOUND, "Session not found").into_response(),
+        Err(e) => return ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
     if session.user_id != user.id {
-        return (axum::http::StatusCode::FORBIDDEN, "Forbidden").into_response();
+        return ApiError::new(axum::http::StatusCode::FORBIDDEN, "Forbidden").into_response();
     }
 
     let mut current_payload: Value = serde_json::from_str(&session.payload).unwrap();
@@ -447,9 +617,12 @@ pub async fn confirm_tool_call(
         let arguments = call["function"]["arguments"].clone();
 
         if payload.approved_ids.contains(&call_id.to_string()) {
-            let result = match state.mcp_manager.call_tool_any(tool_name, arguments).await {
-                Ok(out) => out.to_string(),
-                Err(e) => format!("Error: {}", e),
+            let result = match throttle_tool_call(&state, &user.id, tool_name).await {
+                Some(throttled) => throttled,
+                None => match state.mcp_manager.call_tool_any(tool_name, arguments).await {
+                    Ok(out) => out.to_string(),
+                    Err(e) => format!("Error: {}", e),
+                },
             };
             tool_results.push(json!({
                 "role": "tool",
@@ -495,12 +668,12 @@ pub async fn get_job(
     match job_repo.find_by_id(&job_id).await {
         Ok(Some(job)) => {
             if job.user_id != user.id {
-                return (axum::http::StatusCode::FORBIDDEN, "Forbidden").into_response();
+                return ApiError::new(axum::http::StatusCode::FORBIDDEN, "Forbidden").into_response();
             }
             Json(job).into_response()
         }
-        Ok(None) => (axum::http::StatusCode::NOT_FOUND, "Job not found").into_response(),
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Ok(None) => ApiError::new(axum::http::StatusCode::NOT_FOUND, "Job not found").into_response(),
+        Err(e) => ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
@@ -513,7 +686,43 @@ pub async fn list_jobs(
 
     match job_repo.list_by_user(&user.id).await {
         Ok(jobs) => Json(jobs).into_response(),
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TransactionQuery {
+    pub limit: Option<i64>,
+}
+
+/// 查询当前用户的预付费余额
+pub async fn get_balance(
+    State(state): State<crate::router::AppState>,
+    Extension(user): Extension<db::User>,
+) -> impl IntoResponse {
+    let db_conn = state.model_manager.db();
+    match db::CreditRepo::new(&db_conn).get_account(&user.id).await {
+        Ok(Some(account)) => Json(json!({
+            "prepaid": true,
+            "balance": account.balance,
+            "low_balance_threshold": account.low_balance_threshold,
+            "low_balance": account.balance < account.low_balance_threshold,
+        })).into_response(),
+        Ok(None) => Json(json!({"prepaid": false, "balance": 0})).into_response(),
+        Err(e) => ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 查询当前用户的余额流水
+pub async fn list_credit_transactions(
+    State(state): State<crate::router::AppState>,
+    Extension(user): Extension<db::User>,
+    axum::extract::Query(query): axum::extract::Query<TransactionQuery>,
+) -> impl IntoResponse {
+    let db_conn = state.model_manager.db();
+    match db::CreditRepo::new(&db_conn).list_transactions(&user.id, query.limit.unwrap_or(100)).await {
+        Ok(txs) => Json(txs).into_response(),
+        Err(e) => ApiError::new(axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
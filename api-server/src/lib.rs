@@ -5,6 +5,15 @@ pub mod stats_middleware;
 pub mod limit_middleware;
 pub mod admin_handlers;
 pub mod admin_middleware;
+pub mod oidc_handlers;
+pub mod org_handlers;
+pub mod org_middleware;
 pub mod metrics_middleware;
+pub mod error;
+pub mod audit;
+pub mod server;
+pub mod tls;
+pub mod uds;
 
 pub use router::{AppState, create_router};
+pub use error::ApiError;
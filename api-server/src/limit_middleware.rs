@@ -1,54 +1,193 @@
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    http::{Request, StatusCode, HeaderValue, Method},
     middleware::Next,
     response::Response,
     extract::State,
 };
-use db::User;
-use chrono::Utc;
+use db::{ApiKey, User, CreditRepo};
+use auth::key_policy;
+use lowart_core::{rate_limiter, BucketConfig, RateLimitDecision};
 
+use crate::error::{insert_rate_limit_headers, ApiError};
 use crate::router::AppState;
 
 /// 速率限制与配额检查中间件
 /// 实现逻辑: 
-/// 1. 从 Request Extensions 中提取用户信息。
-/// 2. 检查用户的 RPM (每分钟请求数) 是否超限。
-/// 3. 检查用户的 Token 配额是否已用完。
+/// 1. 从 Request Extensions 中提取用户与本次使用的 Key。
+/// 2. 检查用户与 Key 的 RPM (每分钟请求数) 是否超限 (Key 级被拒时归还已扣减的用户级令牌)。
+/// 3. 读取请求体中的模型，检查 Key 是否可调用该模型 (周期配额按实际调用的候选模型在处理器中占用)。
+/// 4. 预付费用户检查余额，并在响应头中返回余额与低余额提醒。
+/// 5. 按预估的最大用量预占 Token 与金额配额 (含 Key 消费上限，计费后结算)。
+/// 6. 按预估的提示词 Token 数检查用户、Key 与模型的 TPM 限制 (超过每分钟限额的请求返回 400；计费后按实际用量修正放行时扣减的作用域)。
+/// 7. 在响应头中返回余量最少的 RPM / TPM 作用域的 `x-ratelimit-*` 信息，超限时附带 `Retry-After`。
 pub async fn limit_middleware(
     State(state): State<AppState>,
     req: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
-    // 1. 提取用户信息
+) -> Result<Response, ApiError> {
+    // 1. 提取用户与 Key 信息
     let user = req.extensions()
         .get::<User>()
+        .cloned()
         .ok_or(StatusCode::UNAUTHORIZED)?;
+    let key = req.extensions().get::<ApiKey>().cloned();
+    let key_id = key.as_ref().map(|k| k.id);
 
-    // 2. RPM 速率限制检查
-    let now = Utc::now();
-    let minute_ts = now.timestamp() / 60; // 当前分钟的时间戳
-    let key = (user.id.clone(), minute_ts);
+    // 2. RPM 速率限制检查 (令牌桶，容量与每分钟请求数相同)
+    let decision = state.rate_limiter
+        .acquire(&rate_limiter::user_scope(&user.id), BucketConfig::per_minute(user.rpm_limit), 1.0)
+        .await;
+    if !decision.allowed {
+        tracing::warn!("用户 {} 请求过快: RPM 限制 {}", user.username, user.rpm_limit);
+        return Err(requests_exceeded(&decision, &format!("用户请求过快: 每分钟最多 {} 次请求", user.rpm_limit)));
+    }
+    let mut requests = decision;
+    if let Some((key_id, rpm_limit)) = key.as_ref().and_then(|k| k.rpm_limit.map(|l| (k.id, l))) {
+        let decision = state.rate_limiter
+            .acquire(&rate_limiter::key_scope(key_id), BucketConfig::per_minute(rpm_limit), 1.0)
+            .await;
+        if !decision.allowed {
+            // 归还用户级令牌，避免被限流的 Key 耗尽同一用户其他 Key 的额度
+            state.rate_limiter.adjust(&rate_limiter::user_scope(&user.id), -1.0).await;
+            tracing::warn!("Key #{} 请求过快: RPM 限制 {}", key_id, rpm_limit);
+            return Err(requests_exceeded(&decision, &format!("API Key 请求过快: 每分钟最多 {} 次请求", rpm_limit)));
+        }
+        if decision.remaining < requests.remaining {
+            requests = decision;
+        }
+    }
+
+    // 3. 模型授权检查
+    let (mut req, body) = peek_json_body(req).await?;
+    let model_id = body.as_ref()
+        .and_then(|b| b.get("model"))
+        .and_then(|m| m.as_str())
+        .map(str::to_string);
+    if let (Some(key), Some(model_id)) = (&key, &model_id) {
+        if !key_policy::allows_model(key, model_id) {
+            tracing::warn!("Key #{} 无权调用模型 {}", key.id, model_id);
+            return Err(ApiError::forbidden(format!("当前 API Key 无权调用模型 {}", model_id)).with_code("model_not_allowed"));
+        }
+    }
 
-    let count = {
-        let mut entry = state.rate_limit_cache.entry(key).or_insert(0);
-        *entry += 1;
-        *entry
+    // 4. 预付费余额检查 (没有余额账户的用户不受限制)
+    let db = state.model_manager.db();
+    let account = CreditRepo::new(&db).get_account(&user.id).await.map_err(|e| {
+        tracing::error!("查询用户 {} 余额失败: {}", user.id, e);
+        ApiError::internal(e.to_string())
+    })?;
+    if let Some(account) = &account {
+        if account.balance <= 0 {
+            tracing::warn!("用户 {} 余额不足: {}", user.username, account.balance);
+            return Err(ApiError::insufficient_quota("账户余额不足，请充值后重试"));
+        }
+    }
+
+    // 5. Token 与金额配额: 按预估最大用量预占，计费后结算 (不调用模型的请求只检查余量)
+    let estimate = body.as_ref().map(lowart_core::reservation::estimate_usage);
+    let reservation = match (&model_id, &estimate) {
+        (Some(model_id), Some(estimate)) => {
+            let pricing = state.model_manager.get_pricing(model_id).await.unwrap_or_default();
+            state.reservations.reserve(&user.id, key_id, estimate, &pricing).await
+                .map(|r| r.map(Some))
+        }
+        _ => state.reservations.has_remaining(&user.id, key_id).await
+            .map(|ok| ok.then_some(None)),
+    }.map_err(|e| {
+        tracing::error!("预占用户 {} 配额失败: {}", user.id, e);
+        ApiError::internal(e.to_string())
+    })?;
+    let Some(reservation) = reservation else {
+        tracing::warn!("用户 {} 配额不足", user.username);
+        return Err(ApiError::insufficient_quota("Token 或金额配额不足"));
     };
 
-    if count > user.rpm_limit {
-        tracing::warn!("用户 {} 请求过快: {}/{}", user.username, count, user.rpm_limit);
-        return Err(StatusCode::TOO_MANY_REQUESTS);
+    // 6. TPM 限制: 只对设置了限制的作用域计数 (被拒绝时预占随之归还)
+    let mut tokens = None;
+    if let (Some(model_id), Some(estimate)) = (&model_id, &estimate) {
+        let mut limits = Vec::new();
+        if let Some(limit) = user.tpm_limit {
+            limits.push((rate_limiter::user_scope(&user.id), limit));
+        }
+        if let Some((key_id, limit)) = key.as_ref().and_then(|k| k.tpm_limit.map(|l| (k.id, l))) {
+            limits.push((rate_limiter::key_scope(key_id), limit));
+        }
+        if let Some(limit) = state.model_manager.get_config(model_id).await.ok().and_then(|c| c.tpm_limit) {
+            limits.push((rate_limiter::model_scope(model_id), limit));
+        }
+        match state.tpm_limiter.admit(&limits, estimate.input_tokens).await {
+            Ok(decision) => {
+                tokens = decision;
+                if let Some(reservation) = &reservation {
+                    reservation.set_tpm_scopes(limits.into_iter().map(|(scope, _)| scope).collect());
+                }
+            }
+            Err(e) if e.exceeds_limit => {
+                tracing::warn!("用户 {} 请求 Token 数超过 TPM 限制 ({}): {}/{}", user.username, e.scope, estimate.input_tokens, e.limit);
+                let message = format!("本次请求约 {} 个 Token，超过每分钟 {} 个 Token 的限制", estimate.input_tokens, e.limit);
+                return Err(ApiError::bad_request(message).with_code("tokens_exceed_tpm_limit"));
+            }
+            Err(e) => {
+                tracing::warn!("用户 {} 超出 TPM 限制 ({}): 剩余 {}/{}", user.username, e.scope, e.remaining, e.limit);
+                let decision = RateLimitDecision {
+                    allowed: false,
+                    limit: e.limit,
+                    remaining: e.remaining,
+                    retry_after: e.retry_after,
+                    reset_after: e.reset_after,
+                };
+                let message = format!("Token 速率超限: 每分钟最多 {} 个 Token，本次请求约 {} 个", e.limit, estimate.input_tokens);
+                let mut error = ApiError::rate_limited("tokens", message, e.retry_after);
+                insert_rate_limit_headers(error.headers_mut(), "requests", &requests);
+                insert_rate_limit_headers(error.headers_mut(), "tokens", &decision);
+                return Err(error);
+            }
+        }
+    }
+
+    if let Some(reservation) = reservation {
+        req.extensions_mut().insert(reservation);
     }
 
-    // 3. 配额检查 (Token Quota)
-    if user.token_used >= user.token_quota {
-        tracing::warn!("用户 {} 配额已用完: {}/{}", user.username, user.token_used, user.token_quota);
-        return Err(StatusCode::PAYMENT_REQUIRED); // 或者使用 403
+    let mut response = next.run(req).await;
+
+    // 7. 限流响应头
+    insert_rate_limit_headers(response.headers_mut(), "requests", &requests);
+    if let Some(tokens) = &tokens {
+        insert_rate_limit_headers(response.headers_mut(), "tokens", tokens);
+    }
+
+    if let Some(account) = account {
+        let headers = response.headers_mut();
+        headers.insert("x-credit-balance", HeaderValue::from(account.balance));
+        if account.balance < account.low_balance_threshold {
+            headers.insert("x-credit-warning", HeaderValue::from_static("low-balance"));
+        }
     }
 
-    // 定期清理过期的缓存 (这里简单处理，实际生产中应有后台任务清理)
-    // if state.rate_limit_cache.len() > 10000 { ... }
+    Ok(response)
+}
+
+/// 请求数超限的错误响应 (附带被拒绝作用域的限流头)
+fn requests_exceeded(decision: &RateLimitDecision, message: &str) -> ApiError {
+    let mut error = ApiError::rate_limited("requests", message, decision.retry_after);
+    insert_rate_limit_headers(error.headers_mut(), "requests", decision);
+    error
+}
+
+/// 请求体缓冲上限
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
 
-    Ok(next.run(req).await)
+/// 解析 JSON 请求体 (用于读取模型与预估用量)，并用缓冲的请求体重建请求供后续处理
+async fn peek_json_body(req: Request<Body>) -> Result<(Request<Body>, Option<serde_json::Value>), StatusCode> {
+    if req.method() != Method::POST {
+        return Ok((req, None));
+    }
+    let (parts, body) = req.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let body = serde_json::from_slice::<serde_json::Value>(&bytes).ok();
+    Ok((Request::from_parts(parts, Body::from(bytes)), body))
 }
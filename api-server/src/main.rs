@@ -7,7 +7,6 @@ use utils::logger;
 use db::DbConnection;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::UnixListener;
 
 
 #[tokio::main]
@@ -23,82 +22,156 @@ async fn main() -> anyhow::Result<()> {
         .expect("无法安装 Prometheus recorder");
     tracing::info!("指标监控系统 (Prometheus) 已启动");
 
-    // 3. 初始化数据库
+    // 3. 校验 API Key 哈希密钥 (未配置时拒绝启动，避免使用可预测的密钥)
+    utils::Crypto::ensure_api_key_secret()?;
+
+    // 4. 初始化数据库
     let db = Arc::new(DbConnection::new().await?);
     tracing::info!("数据库连接已建立");
 
-    // 4. 初始化核心组件
+    // 5. 初始化核心组件
     let model_manager = Arc::new(lowart_core::ModelManager::new(Arc::clone(&db)));
     let rhai_engine = Arc::new(lowart_core::RhaiEngine::new());
     let agent_orchestrator = Arc::new(lowart_core::AgentOrchestrator::new());
     let mcp_manager = Arc::new(lowart_core::McpManager::new(Arc::clone(&agent_orchestrator)));
-    let rate_limit_cache = Arc::new(dashmap::DashMap::new());
     let user_cache = moka::future::Cache::builder()
         .max_capacity(1000)
         .time_to_live(std::time::Duration::from_secs(600)) // 10分钟过期
+        .support_invalidation_closures()
+        .build();
+    api_server::auth_middleware::watch_user_cache(&db, &user_cache);
+    let circuit_breaker = Arc::new(lowart_core::CircuitBreaker::with_config(lowart_core::BreakerConfig::default()));
+    // 主动健康检查 (HEALTH_CHECK_INTERVAL_SECS=0 时关闭)
+    let env_secs = |name: &str, default: u64| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    let health_config = lowart_core::HealthCheckConfig {
+        interval: std::time::Duration::from_secs(env_secs("HEALTH_CHECK_INTERVAL_SECS", 30)),
+        timeout: std::time::Duration::from_secs(env_secs("HEALTH_CHECK_TIMEOUT_SECS", 10).max(1)),
+    };
+    // 断路器状态落库，启动时恢复 (重启不会立即把流量发往仍在故障的上游)；
+    // 多实例共用数据库时定期同步其他实例的状态变更 (CIRCUIT_SYNC_INTERVAL_SECS=0 时不同步)
+    let circuit_store = Arc::new(lowart_core::CircuitStore::new(Arc::clone(&db), Arc::clone(&circuit_breaker)));
+    match circuit_store.restore().await {
+        Ok(restored) => tracing::info!("已恢复断路器状态 ({} 个模型处于熔断)，实例 {}", restored, circuit_store.instance_id()),
+        Err(e) => tracing::error!("恢复断路器状态失败: {}", e),
+    }
+    circuit_store.spawn_writer();
+    let circuit_sync_secs = env_secs("CIRCUIT_SYNC_INTERVAL_SECS", 0);
+    if circuit_sync_secs > 0 {
+        circuit_store.spawn_sync(std::time::Duration::from_secs(circuit_sync_secs));
+    }
+    let health_checker = Arc::new(lowart_core::HealthChecker::new(Arc::clone(&model_manager), Arc::clone(&circuit_breaker), health_config));
+    if let Err(e) = health_checker.restore().await {
+        tracing::error!("恢复模型健康状态失败: {}", e);
+    }
+    if health_config.interval.is_zero() {
+        tracing::info!("模型主动健康检查已关闭");
+    } else {
+        health_checker.spawn();
+    }
+    let quota = Arc::new(lowart_core::QuotaService::new(Arc::clone(&model_manager)));
+    let reservations = Arc::new(lowart_core::QuotaReservations::new(Arc::clone(&db), std::time::Duration::from_secs(900)));
+    // 空闲 10 分钟的令牌桶早已补满，淘汰后不影响限流判断
+    let rate_limiter = Arc::new(lowart_core::RateLimiter::new(100_000, std::time::Duration::from_secs(600)));
+    let tpm_limiter = Arc::new(lowart_core::TpmLimiter::new());
+    // 工具调用限流: 每个用户对单个工具的每分钟调用上限 (0 表示不限制)
+    let default_tool_calls = api_server::handlers::DEFAULT_TOOL_CALLS_PER_MINUTE;
+    let tool_calls_per_minute = match std::env::var("TOOL_CALLS_PER_MINUTE") {
+        Ok(v) => v.trim().parse::<i64>().ok().filter(|n| *n >= 0).unwrap_or_else(|| {
+            tracing::warn!("无效的 TOOL_CALLS_PER_MINUTE: {:?}，使用默认值 {}", v, default_tool_calls);
+            default_tool_calls
+        }),
+        Err(_) => default_tool_calls,
+    };
+    let last_used_throttle = moka::future::Cache::builder()
+        .max_capacity(100_000)
+        .time_to_live(std::time::Duration::from_secs(60)) // 每个 Key 每分钟最多写一次最近使用时间
         .build();
-    let circuit_breaker = Arc::new(lowart_core::CircuitBreaker::new(5, std::time::Duration::from_secs(30)));
+    let billing = Arc::new(lowart_core::BillingService::new(Arc::clone(&model_manager), Arc::clone(&quota), Arc::clone(&reservations), Arc::clone(&tpm_limiter)));
+    let oidc = match auth::oidc::OidcConfig::from_env()? {
+        Some(config) => {
+            tracing::info!("已启用 OIDC 单点登录: {}", config.issuer);
+            Some(Arc::new(auth::oidc::OidcClient::new(config)?))
+        }
+        None => None,
+    };
+
+    // 后台任务: 定期回收过期的预付费额度
+    let billing_task = Arc::clone(&billing);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = billing_task.expire_credits().await {
+                tracing::error!("回收过期额度失败: {}", e);
+            }
+        }
+    });
+
+    // 后台任务: 定期重置已进入新周期的配额计数器
+    let quota_task = Arc::clone(&quota);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = quota_task.roll_over().await {
+                tracing::error!("重置周期配额失败: {}", e);
+            }
+        }
+    });
+
+    // 后台任务: 回收崩溃或卡死请求遗留的配额预占
+    let reservation_task = Arc::clone(&reservations);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = reservation_task.expire_stale().await {
+                tracing::error!("回收配额预占失败: {}", e);
+            }
+        }
+    });
     
     let state = router::AppState {
         model_manager,
         rhai_engine,
         mcp_manager,
         agent_orchestrator,
-        rate_limit_cache,
+        rate_limiter,
+        tool_calls_per_minute,
         user_cache,
         circuit_breaker,
+        health_checker,
+        billing,
+        quota,
+        reservations,
+        tpm_limiter,
+        last_used_throttle,
+        oidc,
+        admin_auth: api_server::admin_middleware::AdminAuthConfig::from_env(),
+        trusted_proxies: api_server::auth_middleware::TrustedProxies::from_env()?,
     };
 
 
 
 
-    // 5. 构建路由
+    // 6. 构建路由
     let app = router::create_router(state, metrics_handle);
 
 
 
-    // 5. 选择启动模式
+    // 7. 选择启动模式
     let listen_mode = std::env::var("LISTEN_MODE").unwrap_or_default();
+    let tls = match api_server::tls::TlsConfig::from_env()? {
+        Some(config) => Some(api_server::tls::TlsServer::new(config)?),
+        None => None,
+    };
 
     if listen_mode == "UDS" {
-        // UDS 模式
-        let path = std::env::var("UDS_PATH").unwrap_or_else(|_| "/tmp/lowart.sock".to_string());
-        let _ = std::fs::remove_file(&path);
-        let listener = UnixListener::bind(&path)?;
-        tracing::info!("正在监听 UDS: {}", path);
-
-        use hyper_util::server::conn::auto::Builder;
-        use hyper_util::rt::TokioExecutor;
-        use tower::Service;
-
-        loop {
-            let (stream, _addr) = listener.accept().await?;
-            let app_clone = app.clone();
-
-            tokio::spawn(async move {
-                let io = hyper_util::rt::TokioIo::new(stream);
-                let service = app_clone.clone();
-                
-                let tower_service = tower::service_fn(move |req: axum::http::Request<hyper::body::Incoming>| {
-                    let mut service = service.clone();
-                    async move {
-                        let req = req.map(axum::body::Body::new);
-                        service.call(req).await
-                    }
-                });
-
-                let hyper_service = hyper_util::service::TowerToHyperService::new(tower_service);
-
-                if let Err(err) = Builder::new(TokioExecutor::new())
-                    .serve_connection(io, hyper_service)
-                    .await
-                {
-                    tracing::error!("UDS 连接处理错误: {}", err);
-                }
-            });
-
-        }
-
+        // UDS 模式 (本地进程可凭 SO_PEERCRED 身份认证)
+        let uds = api_server::uds::UdsConfig::from_env()?;
+        let listener = uds.bind()?;
+        tracing::info!("正在监听 UDS: {}", uds.path.display());
+        api_server::server::serve_uds(listener, app).await?;
     } else {
         // TCP 模式
         let port = std::env::var("PORT")
@@ -108,8 +181,19 @@ async fn main() -> anyhow::Result<()> {
             
         let addr = SocketAddr::from(([0, 0, 0, 0], port));
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        tracing::info!("正在监听 HTTP: {}", addr);
-        axum::serve(listener, app).await?;
+        match tls {
+            Some(tls) => {
+                // 内置 TLS 终止 (可选 mTLS)，证书文件变化后自动重新加载
+                let tls = Arc::new(tls);
+                tls.spawn_reloader();
+                tracing::info!("正在监听 HTTPS: {}", addr);
+                api_server::server::serve_tls(listener, app, tls).await?;
+            }
+            None => {
+                tracing::info!("正在监听 HTTP: {}", addr);
+                axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+            }
+        }
     }
 
 
@@ -0,0 +1,160 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{Extensions, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use auth::oidc::OidcIdentity;
+use auth::rbac::{self, Role};
+use db::{OidcIdentityRepo, RoleRepo, UserRepo, models::User};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::admin_handlers::issue_admin_session;
+use crate::error::ApiError;
+use crate::router::AppState;
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// 单点登录入口: 跳转到 IdP 授权页
+pub async fn oidc_login(State(state): State<AppState>) -> Response {
+    let Some(oidc) = &state.oidc else {
+        return (StatusCode::NOT_FOUND, "未启用 OIDC 单点登录").into_response();
+    };
+    match oidc.authorization_url().await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            tracing::error!("发起 OIDC 登录失败: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+/// 单点登录回调
+/// 实现逻辑: 校验 IdP 返回的授权码与 ID Token 后，按 IdP 组映射管理角色；首次登录且有角色时自动创建本地账号
+/// (随机 API Key，不对外展示)，之后每次登录以 IdP 的组为准覆盖角色。签发与密码登录相同的会话令牌：
+/// 配置了 `OIDC_POST_LOGIN_REDIRECT` 时经 URL 片段带回控制台 (片段不会发送到服务端或写入访问日志)，否则返回 JSON。
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    Query(query): Query<OidcCallbackQuery>,
+    headers: HeaderMap,
+    extensions: Extensions,
+) -> Response {
+    let Some(oidc) = &state.oidc else {
+        return (StatusCode::NOT_FOUND, "未启用 OIDC 单点登录").into_response();
+    };
+    if let Some(error) = &query.error {
+        let detail = query.error_description.as_deref().unwrap_or_default();
+        return ApiError::unauthorized(format!("IdP 拒绝登录: {} {}", error, detail).trim_end().to_string())
+            .with_code("invalid_oidc_login")
+            .into_response();
+    }
+    let (Some(code), Some(login_state)) = (&query.code, &query.state) else {
+        return (StatusCode::BAD_REQUEST, "缺少 code 或 state 参数").into_response();
+    };
+
+    // 1. 换取并校验 ID Token
+    let identity = match oidc.complete(code, login_state).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::warn!("OIDC 登录校验失败: {}", e);
+            return ApiError::unauthorized(format!("单点登录失败: {}", e)).with_code("invalid_oidc_login").into_response();
+        }
+    };
+
+    // 2. 组 -> 角色，关联或创建本地账号
+    let roles = oidc.config().roles_for_groups(&identity.groups);
+    let user = match provision_user(&state, &identity, &roles).await {
+        Ok(Some(user)) if user.status == "Active" => user,
+        Ok(Some(_)) => return (StatusCode::FORBIDDEN, "账号已被禁用").into_response(),
+        Ok(None) => {
+            tracing::warn!("OIDC 用户 {} (组: {:?}) 未映射到任何管理角色", identity.username, identity.groups);
+            return (StatusCode::FORBIDDEN, "该账号未被授予管理后台权限").into_response();
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let roles = match crate::admin_middleware::load_roles(&state, &user.id).await {
+        Ok(roles) if !roles.is_empty() => roles,
+        Ok(_) => return (StatusCode::FORBIDDEN, "该账号未被授予管理后台权限").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // 3. 签发会话
+    let (token, expires_at) = match issue_admin_session(&state, &user.id, &headers, &extensions).await {
+        Ok(session) => session,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    tracing::info!("OIDC 用户 {} 登录管理后台，角色: {:?}", user.username, roles);
+
+    match &oidc.config().post_login_redirect {
+        Some(target) => {
+            let expires = expires_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            Redirect::to(&format!("{}#token={}&expires_at={}", target, token, expires)).into_response()
+        }
+        None => Json(json!({
+            "status": "success",
+            "token": token,
+            "expires_at": expires_at,
+            "user": user,
+            "roles": roles,
+            "permissions": rbac::permissions_of(&roles)
+        })).into_response(),
+    }
+}
+
+/// 查找或创建 IdP 身份对应的本地用户，并同步其管理角色
+/// 没有任何角色的 IdP 用户不会被创建；已关联的用户角色被撤空后返回该用户，由调用方拒绝登录。
+async fn provision_user(state: &AppState, identity: &OidcIdentity, roles: &[Role]) -> utils::Result<Option<User>> {
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+    let identities = OidcIdentityRepo::new(&db);
+
+    let linked = match identities.find_user_id(&identity.issuer, &identity.subject).await? {
+        Some(user_id) => user_repo.find_by_id(&user_id).await?,
+        None => None,
+    };
+    let user = match linked {
+        Some(user) => user,
+        None if roles.is_empty() => return Ok(None),
+        None => {
+            let username = unique_username(&user_repo, &identity.username).await?;
+            let user_id = uuid::Uuid::new_v4().to_string();
+            user_repo.create(&user_id, &username, &db::api_key_repo::generate_key(), false).await?;
+            tracing::info!("OIDC 首次登录，已创建管理员账号 {} ({})", username, identity.subject);
+            user_repo.find_by_id(&user_id).await?.ok_or_else(|| utils::anyhow!("创建用户失败"))?
+        }
+    };
+    identities.upsert(&identity.issuer, &identity.subject, &user.id, identity.email.as_deref()).await?;
+
+    // 以 IdP 的组为准覆盖角色 (根管理员始终保留超级管理员角色)
+    let mut roles = roles.to_vec();
+    if user.id == "admin_root_id" && !roles.contains(&Role::SuperAdmin) {
+        roles.push(Role::SuperAdmin);
+    }
+    let names: Vec<&str> = roles.iter().map(|r| r.as_str()).collect();
+    RoleRepo::new(&db).set_roles(&user.id, &names).await?;
+    Ok(Some(user))
+}
+
+/// 本地用户名被占用时追加数字后缀
+async fn unique_username(user_repo: &UserRepo<'_>, preferred: &str) -> utils::Result<String> {
+    let base = preferred.trim();
+    let base = if base.is_empty() { "sso-user" } else { base };
+    if !user_repo.exists_by_username(base, None).await? {
+        return Ok(base.to_string());
+    }
+    let mut n = 2;
+    loop {
+        let candidate = format!("{}-{}", base, n);
+        if !user_repo.exists_by_username(&candidate, None).await? {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
@@ -0,0 +1,287 @@
+use axum::{Json, response::IntoResponse, extract::{State, Extension, Path, Query}};
+use serde::Deserialize;
+use serde_json::json;
+use db::{ApiKeyRepo, OrgMember, OrgRepo, UserRepo};
+
+use crate::admin_handlers::{org_membership, render_usage_report, snapshot, UsageReportQuery};
+use crate::audit::Auditor;
+use crate::router::AppState;
+
+#[derive(Deserialize)]
+pub struct CreateOrgMemberRequest {
+    pub username: String,
+    #[serde(default = "default_role")]
+    pub role: String, // member, admin
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateOrgMemberRequest {
+    pub user_id: String,
+    #[serde(default = "default_role")]
+    pub role: String,
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveOrgMemberRequest {
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrgKeyRequest {
+    pub user_id: String,
+    pub label: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateOrgKeyLimitsRequest {
+    pub key_id: i64,
+    #[serde(flatten)]
+    pub settings: db::ApiKeySettings,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteOrgKeyRequest {
+    pub key_id: i64,
+}
+
+pub(crate) fn default_role() -> String {
+    "member".to_string()
+}
+
+/// 校验组织角色 (与 org_members 表的 CHECK 约束一致)，不合法时返回 400 响应
+pub(crate) fn reject_invalid_role(role: &str) -> Option<axum::response::Response> {
+    match role {
+        "member" | "admin" => None,
+        _ => Some((axum::http::StatusCode::BAD_REQUEST, format!("无效的组织角色: {}", role)).into_response()),
+    }
+}
+
+/// 校验目标用户属于当前组织管理员所在的组织
+async fn ensure_same_org(state: &AppState, admin: &OrgMember, user_id: &str) -> Result<(), axum::response::Response> {
+    let db = state.model_manager.db();
+    match OrgRepo::new(&db).find_membership(user_id).await {
+        Ok(Some(m)) if m.org_id == admin.org_id => Ok(()),
+        Ok(_) => Err((axum::http::StatusCode::FORBIDDEN, "该用户不属于本组织").into_response()),
+        Err(e) => Err((axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// 获取本组织信息与成员列表
+pub async fn get_org(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let org = match org_repo.find_by_id(&admin.org_id).await {
+        Ok(Some(org)) => org,
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "组织不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    match org_repo.list_members(&admin.org_id).await {
+        Ok(members) => Json(json!({"org": org, "members": members})).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 在本组织内创建新成员 (同时创建用户与默认 API Key)
+pub async fn create_member(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<CreateOrgMemberRequest>,
+) -> impl IntoResponse {
+    if let Some(res) = reject_invalid_role(&payload.role) {
+        return res;
+    }
+    let db = state.model_manager.db();
+    let user_repo = UserRepo::new(&db);
+
+    match user_repo.exists_by_username(&payload.username, None).await {
+        Ok(true) => return (axum::http::StatusCode::CONFLICT, "用户名已存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        _ => {}
+    }
+
+    let user_id = uuid::Uuid::new_v4().to_string();
+    let api_key = db::api_key_repo::generate_key();
+    match user_repo.create_org_member(&user_id, &payload.username, &api_key, &admin.org_id, &payload.role, payload.token_limit, payload.cost_limit).await {
+        Ok(_) => {
+            // 新成员的 API Key 只在响应中返回，审计快照中的 Key 字段已脱敏
+            let mut after = org_membership(&OrgRepo::new(&db), &admin.org_id, &user_id).await;
+            after["username"] = json!(payload.username);
+            let target = format!("{}:{}", admin.org_id, user_id);
+            audit.record("org_member.create", "org_member", &target, serde_json::Value::Null, after).await;
+            Json(json!({"status": "success", "user_id": user_id, "api_key": api_key})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 更新本组织成员的角色与子限额
+pub async fn update_member(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<UpdateOrgMemberRequest>,
+) -> impl IntoResponse {
+    if let Some(res) = reject_invalid_role(&payload.role) {
+        return res;
+    }
+    if let Err(res) = ensure_same_org(&state, &admin, &payload.user_id).await {
+        return res;
+    }
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = org_membership(&org_repo, &admin.org_id, &payload.user_id).await;
+    match org_repo.upsert_member(&admin.org_id, &payload.user_id, &payload.role, payload.token_limit, payload.cost_limit).await {
+        Ok(_) => {
+            state.reservations.invalidate_user(&payload.user_id);
+            let after = org_membership(&org_repo, &admin.org_id, &payload.user_id).await;
+            let target = format!("{}:{}", admin.org_id, payload.user_id);
+            audit.record("org_member.upsert", "org_member", &target, before, after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 将成员移出本组织 (用户本身保留)
+pub async fn remove_member(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<RemoveOrgMemberRequest>,
+) -> impl IntoResponse {
+    if payload.user_id == admin.user_id {
+        return (axum::http::StatusCode::FORBIDDEN, "不能将自己移出组织").into_response();
+    }
+    if let Err(res) = ensure_same_org(&state, &admin, &payload.user_id).await {
+        return res;
+    }
+    let db = state.model_manager.db();
+    let org_repo = OrgRepo::new(&db);
+    let before = org_membership(&org_repo, &admin.org_id, &payload.user_id).await;
+    match org_repo.remove_member(&admin.org_id, &payload.user_id).await {
+        Ok(_) => {
+            state.reservations.invalidate_user(&payload.user_id);
+            let target = format!("{}:{}", admin.org_id, payload.user_id);
+            audit.record("org_member.remove", "org_member", &target, before, serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 获取本组织成员的 API Key
+pub async fn list_member_keys(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(res) = ensure_same_org(&state, &admin, &user_id).await {
+        return res;
+    }
+    let db = state.model_manager.db();
+    match ApiKeyRepo::new(&db).list_by_user(&user_id).await {
+        Ok(keys) => Json(keys).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 为本组织成员创建 API Key
+pub async fn create_member_key(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<CreateOrgKeyRequest>,
+) -> impl IntoResponse {
+    if let Err(res) = ensure_same_org(&state, &admin, &payload.user_id).await {
+        return res;
+    }
+    let db = state.model_manager.db();
+    let key_repo = ApiKeyRepo::new(&db);
+    match key_repo.create(&payload.user_id, &payload.label).await {
+        Ok(key) => {
+            // 明文 Key 只在响应中返回，审计快照中的 Key 字段已脱敏
+            let created = key_repo.find_by_key(&key).await;
+            let target = created.as_ref().ok().and_then(|k| k.as_ref()).map(|k| k.id.to_string()).unwrap_or_default();
+            audit.record("key.create", "key", &target, serde_json::Value::Null, snapshot(created)).await;
+            Json(json!({"status": "success", "key": key})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 更新本组织成员 API Key 的独立限制与访问范围
+pub async fn update_member_key_limits(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<UpdateOrgKeyLimitsRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = auth::key_policy::validate_settings(&payload.settings) {
+        return (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    let db = state.model_manager.db();
+    let key_repo = ApiKeyRepo::new(&db);
+    let key = match key_repo.find_by_id(payload.key_id).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "API Key 不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(res) = ensure_same_org(&state, &admin, &key.user_id).await {
+        return res;
+    }
+
+    match key_repo.update_settings(payload.key_id, &payload.settings).await {
+        Ok(_) => {
+            state.reservations.invalidate_key(payload.key_id);
+            let after = snapshot(key_repo.find_by_id(payload.key_id).await);
+            audit.record("key.limits", "key", &payload.key_id.to_string(), json!(key), after).await;
+            Json(json!({"status": "success"})).into_response()
+        },
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 删除本组织成员的 API Key
+pub async fn delete_member_key(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    audit: Auditor,
+    Json(payload): Json<DeleteOrgKeyRequest>,
+) -> impl IntoResponse {
+    let db = state.model_manager.db();
+    let key_repo = ApiKeyRepo::new(&db);
+    let key = match key_repo.find_by_id(payload.key_id).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return (axum::http::StatusCode::NOT_FOUND, "API Key 不存在").into_response(),
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(res) = ensure_same_org(&state, &admin, &key.user_id).await {
+        return res;
+    }
+
+    match key_repo.delete(payload.key_id).await {
+        Ok(_) => {
+            audit.record("key.delete", "key", &payload.key_id.to_string(), json!(key), serde_json::Value::Null).await;
+            Json(json!({"status": "success"})).into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 本组织的用量报表
+pub async fn org_usage_report(
+    State(state): State<AppState>,
+    Extension(admin): Extension<OrgMember>,
+    Query(query): Query<UsageReportQuery>,
+) -> impl IntoResponse {
+    render_usage_report(&state, &query, Some(&admin.org_id)).await
+}
@@ -0,0 +1,38 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    extract::State,
+};
+use db::{OrgRepo, User};
+
+use crate::error::ApiError;
+use crate::router::AppState;
+
+/// 组织管理员鉴权中间件
+/// 实现逻辑: 查询已认证用户的组织成员关系，仅组织管理员可访问，并将成员关系写入请求扩展供后续处理使用。
+pub async fn org_admin_middleware(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let user = req.extensions().get::<User>().ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let db = state.model_manager.db();
+    let membership = OrgRepo::new(&db).find_membership(&user.id).await.map_err(|e| {
+        tracing::error!("查询用户 {} 组织成员关系失败: {}", user.id, e);
+        ApiError::internal(e.to_string())
+    })?;
+
+    match membership {
+        Some(member) if member.role == "admin" => {
+            req.extensions_mut().insert(member);
+            Ok(next.run(req).await)
+        }
+        _ => {
+            tracing::warn!("用户 {} 尝试访问组织管理接口被拒绝", user.id);
+            Err(ApiError::forbidden("需要组织管理员权限"))
+        }
+    }
+}
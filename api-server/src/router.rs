@@ -5,11 +5,16 @@ use axum::{
 };
 use crate::handlers;
 use crate::admin_handlers;
+use crate::org_handlers;
+use crate::oidc_handlers;
 use crate::auth_middleware::auth_middleware;
 use crate::limit_middleware::limit_middleware;
-use crate::admin_middleware::admin_middleware;
+use crate::admin_middleware::{admin_middleware, permission_middleware};
+use auth::rbac::Permission;
+use crate::org_middleware::org_admin_middleware;
 use crate::stats_middleware::stats_middleware;
 use crate::metrics_middleware::metrics_middleware;
+use crate::error::error_middleware;
 use metrics_exporter_prometheus::PrometheusHandle;
 use tower_http::cors::{CorsLayer, Any};
 use axum::http::Method;
@@ -25,48 +30,153 @@ pub struct AppState {
     pub rhai_engine: Arc<RhaiEngine>,
     pub mcp_manager: Arc<lowart_core::McpManager>,
     pub agent_orchestrator: Arc<lowart_core::AgentOrchestrator>,
-    pub rate_limit_cache: Arc<dashmap::DashMap<(String, i64), i64>>, // (user_id, minute_timestamp) -> count
-    pub user_cache: moka::future::Cache<String, db::User>, // api_key -> user
+    pub rate_limiter: Arc<lowart_core::RateLimiter>, // 用户 RPM 令牌桶
+    pub tool_calls_per_minute: i64, // 每个用户对单个工具的每分钟调用上限 (0 表示不限制)
+    pub user_cache: moka::future::Cache<String, (db::User, db::ApiKey)>, // key_hash -> (user, key)，按变更事件淘汰 (见 `watch_user_cache`)
     pub circuit_breaker: Arc<lowart_core::CircuitBreaker>,
+    pub health_checker: Arc<lowart_core::HealthChecker>,
+    pub billing: Arc<lowart_core::BillingService>,
+    pub quota: Arc<lowart_core::QuotaService>,
+    pub reservations: Arc<lowart_core::QuotaReservations>,
+    pub tpm_limiter: Arc<lowart_core::TpmLimiter>,
+    pub last_used_throttle: moka::future::Cache<i64, ()>, // 近期已记录使用时间的 Key (过期即可再次记录)
+    pub oidc: Option<Arc<auth::oidc::OidcClient>>, // 未配置 OIDC_ISSUER 时为 None
+    pub admin_auth: crate::admin_middleware::AdminAuthConfig, // 管理接口接受的认证方式 (默认仅会话令牌)
+    pub trusted_proxies: crate::auth_middleware::TrustedProxies, // 采信转发头的反向代理地址
 }
 
 
 
 
 
+
 pub fn create_router(state: AppState, metrics_handle: PrometheusHandle) -> Router {
     // 公开接口
     let public_routes = Router::new()
         .route("/health", get(handlers::health_check))
         .route("/admin/login", post(admin_handlers::login))
+        .route("/admin/oidc/login", get(oidc_handlers::oidc_login))
+        .route("/admin/oidc/callback", get(oidc_handlers::oidc_callback))
         .route("/metrics", get(move || async move {
             metrics_handle.render()
         }));
 
-    // 管理接口 (需 Auth + Admin 权限)
-    let admin_routes = Router::new()
-        .route("/users", 
-            get(admin_handlers::list_users)
-            .post(admin_handlers::create_user)
+    // 管理接口 (需 Auth + 管理角色，各组路由分别校验所需权限)
+    let require = |permission: Permission| middleware::from_fn_with_state(permission, permission_middleware);
+    let account_admin_routes = Router::new()
+        .route("/account", get(admin_handlers::get_account))
+        .route("/logout", post(admin_handlers::logout))
+        .route("/account/password", post(admin_handlers::change_own_password))
+        .route("/account/totp/setup", post(admin_handlers::setup_totp))
+        .route("/account/totp/enable", post(admin_handlers::enable_totp))
+        .route("/account/totp/disable", post(admin_handlers::disable_totp));
+    let stats_routes = Router::new()
+        .route("/stats", get(admin_handlers::list_stats))
+        .route("/reports/usage", get(admin_handlers::usage_report))
+        .route("/quota/usage", get(admin_handlers::get_quota_usage))
+        .route_layer(require(Permission::StatsRead));
+    let users_read_routes = Router::new()
+        .route("/users", get(admin_handlers::list_users))
+        .route("/users/{id}/keys", get(admin_handlers::list_user_keys))
+        .route("/users/{id}/roles", get(admin_handlers::get_user_roles))
+        .route("/roles", get(admin_handlers::list_roles))
+        .route("/keys/stale", get(admin_handlers::list_stale_keys))
+        .route("/sessions", get(admin_handlers::list_admin_sessions))
+        .route("/orgs", get(admin_handlers::list_orgs))
+        .route("/orgs/members", get(admin_handlers::list_org_members))
+        .route_layer(require(Permission::UsersRead));
+    let users_write_routes = Router::new()
+        .route("/users",
+            post(admin_handlers::create_user)
             .put(admin_handlers::update_user)
             .delete(admin_handlers::delete_user)
         )
+        .route("/orgs",
+            post(admin_handlers::create_org)
+            .put(admin_handlers::update_org)
+            .delete(admin_handlers::delete_org)
+        )
+        .route("/orgs/members",
+            post(admin_handlers::upsert_org_member)
+            .delete(admin_handlers::remove_org_member)
+        )
+        .route("/users/password", post(admin_handlers::set_user_password))
+        .route("/sessions/revoke", post(admin_handlers::revoke_admin_sessions))
+        .route_layer(require(Permission::UsersWrite));
+    let keys_routes = Router::new()
+        .route("/keys", post(admin_handlers::create_user_key))
+        .route("/keys/limits", post(admin_handlers::update_key_limits))
+        .route("/keys/reset", post(admin_handlers::reset_user_key))
+        .route("/keys/rotate", post(admin_handlers::rotate_user_key))
+        .route("/keys/delete", post(admin_handlers::delete_user_key))
+        .route("/keys/client-cert", post(admin_handlers::set_key_client_cert))
+        .route("/keys/unix-peer", post(admin_handlers::set_key_unix_peer))
+        .route_layer(require(Permission::KeysWrite));
+    let quota_routes = Router::new()
         .route("/users/quota", post(admin_handlers::update_user_quota))
-        .route("/models", 
-            get(admin_handlers::list_models)
-            .post(admin_handlers::create_model)
+        .route("/quota/policies",
+            post(admin_handlers::create_quota_policy)
+            .delete(admin_handlers::delete_quota_policy)
+        )
+        .route_layer(require(Permission::QuotaWrite));
+    let billing_read_routes = Router::new()
+        .route("/billing/ledger", get(admin_handlers::list_ledger))
+        .route("/billing/transactions", get(admin_handlers::list_credit_transactions))
+        .route("/quota/policies", get(admin_handlers::list_quota_policies))
+        .route_layer(require(Permission::BillingRead));
+    let billing_write_routes = Router::new()
+        .route("/billing/topup", post(admin_handlers::top_up_credit))
+        .route("/billing/adjust", post(admin_handlers::adjust_credit))
+        .route("/billing/refund", post(admin_handlers::refund_credit))
+        .route_layer(require(Permission::BillingWrite));
+    let models_read_routes = Router::new()
+        .route("/models", get(admin_handlers::list_models))
+        .route("/models/health", get(admin_handlers::list_model_health))
+        .route("/models/health/history", get(admin_handlers::list_model_health_history))
+        .route("/circuits", get(admin_handlers::list_circuits))
+        .route_layer(require(Permission::ModelsRead));
+    let models_write_routes = Router::new()
+        .route("/models",
+            post(admin_handlers::create_model)
             .put(admin_handlers::update_model)
             .delete(admin_handlers::delete_model)
         )
-        .route("/stats", get(admin_handlers::list_stats))
+        .route("/models/health/check", post(admin_handlers::check_model_health))
+        .route("/circuits/open", post(admin_handlers::force_open_circuit))
+        .route("/circuits/close", post(admin_handlers::force_close_circuit))
+        .route("/circuits/reset", post(admin_handlers::reset_circuit))
+        .route_layer(require(Permission::ModelsWrite));
+    let policy_routes = Router::new()
         .route("/policies", post(admin_handlers::update_tool_policy))
+        .route_layer(require(Permission::PoliciesWrite));
+    let mcp_routes = Router::new()
         .route("/mcp/register", post(admin_handlers::register_mcp))
         .route("/mcp/unregister", post(admin_handlers::unregister_mcp))
-        .route("/users/{id}/keys", get(admin_handlers::list_user_keys))
-        .route("/keys", post(admin_handlers::create_user_key))
-        .route("/keys/reset", post(admin_handlers::reset_user_key))
-        .route("/keys/delete", post(admin_handlers::delete_user_key))
-        .layer(middleware::from_fn(admin_middleware));
+        .route_layer(require(Permission::McpWrite));
+    let role_routes = Router::new()
+        .route("/users/roles", post(admin_handlers::set_user_roles))
+        .route_layer(require(Permission::RolesWrite));
+    let audit_routes = Router::new()
+        .route("/audit", get(admin_handlers::list_audit_log))
+        .route("/audit/verify", get(admin_handlers::verify_audit_log))
+        .route_layer(require(Permission::AuditRead));
+
+    let admin_routes = Router::new()
+        .merge(account_admin_routes)
+        .merge(stats_routes)
+        .merge(users_read_routes)
+        .merge(users_write_routes)
+        .merge(keys_routes)
+        .merge(quota_routes)
+        .merge(billing_read_routes)
+        .merge(billing_write_routes)
+        .merge(models_read_routes)
+        .merge(models_write_routes)
+        .merge(policy_routes)
+        .merge(mcp_routes)
+        .merge(role_routes)
+        .merge(audit_routes)
+        .layer(middleware::from_fn_with_state(state.clone(), admin_middleware));
 
 
     // 标准 API 接口 (需 Auth)
@@ -78,6 +188,26 @@ pub fn create_router(state: AppState, metrics_handle: PrometheusHandle) -> Route
         .layer(middleware::from_fn_with_state(state.clone(), limit_middleware))
         .layer(middleware::from_fn_with_state(state.clone(), stats_middleware));
 
+    // 账户查询接口 (需 Auth，不经过限流与余额检查，便于余额耗尽后仍可查询)
+    let account_routes = Router::new()
+        .route("/billing/balance", get(handlers::get_balance))
+        .route("/billing/transactions", get(handlers::list_credit_transactions));
+
+    // 组织管理接口 (需 Auth + 组织管理员身份，仅可管理本组织成员)
+    let org_routes = Router::new()
+        .route("/", get(org_handlers::get_org))
+        .route("/members",
+            post(org_handlers::create_member)
+            .put(org_handlers::update_member)
+            .delete(org_handlers::remove_member)
+        )
+        .route("/members/{user_id}/keys", get(org_handlers::list_member_keys))
+        .route("/keys", post(org_handlers::create_member_key))
+        .route("/keys/limits", post(org_handlers::update_member_key_limits))
+        .route("/keys/delete", post(org_handlers::delete_member_key))
+        .route("/usage", get(org_handlers::org_usage_report))
+        .layer(middleware::from_fn_with_state(state.clone(), org_admin_middleware));
+
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods([
@@ -93,12 +223,14 @@ pub fn create_router(state: AppState, metrics_handle: PrometheusHandle) -> Route
     // 合并受保护的接口并应用鉴权中间件
     let protected_routes = Router::new()
         .nest("/admin", admin_routes)
-        .nest("/v1", api_routes)
+        .nest("/v1", api_routes.merge(account_routes))
+        .nest("/v1/org", org_routes)
         .layer(middleware::from_fn_with_state(state.clone(), auth_middleware));
 
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .layer(middleware::from_fn(error_middleware))
         .layer(middleware::from_fn_with_state(state.clone(), metrics_middleware))
         .layer(cors)
         .with_state(state)
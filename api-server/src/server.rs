@@ -0,0 +1,102 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::http::Extensions;
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tower::Service;
+
+use crate::tls::{cert_identity, TlsServer};
+use crate::uds::PeerCredentials;
+
+/// TLS 握手超时，避免半开连接长期占用任务
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 在单个连接上提供 HTTP 服务 (HTTP/1.1 与 HTTP/2 自动协商)
+/// 实现逻辑: `extensions` 中的连接级信息 (对端地址、客户端证书身份等) 会附加到该连接的每个请求上。
+pub async fn serve_connection<I>(io: I, app: Router, extensions: Extensions)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let tower_service = tower::service_fn(move |req: axum::http::Request<hyper::body::Incoming>| {
+        let mut service = app.clone();
+        let extensions = extensions.clone();
+        async move {
+            let mut req = req.map(axum::body::Body::new);
+            req.extensions_mut().extend(extensions);
+            service.call(req).await
+        }
+    });
+    let hyper_service = hyper_util::service::TowerToHyperService::new(tower_service);
+
+    if let Err(err) = Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(io), hyper_service)
+        .await
+    {
+        tracing::error!("连接处理错误: {}", err);
+    }
+}
+
+/// 监听 Unix 域套接字
+/// 实现逻辑: 读取每个连接的对端凭证 (SO_PEERCRED) 写入请求扩展，供鉴权中间件在未携带 Bearer Key 时
+/// 按 uid / gid 查找绑定的 Key；读取失败的连接仍可使用 Bearer Key。
+pub async fn serve_uds(listener: UnixListener, app: Router) -> std::io::Result<()> {
+    loop {
+        let (stream, _addr) = listener.accept().await?;
+        let mut extensions = Extensions::new();
+        match stream.peer_cred() {
+            Ok(cred) => {
+                extensions.insert(PeerCredentials { uid: cred.uid(), gid: cred.gid(), pid: cred.pid() });
+            }
+            Err(e) => tracing::warn!("读取 UDS 对端凭证失败: {}", e),
+        }
+        tokio::spawn(serve_connection(stream, app.clone(), extensions));
+    }
+}
+
+/// 以 TLS 监听 TCP 连接
+/// 实现逻辑: 每个连接使用最新的证书配置握手；mTLS 下客户端出示的证书已由 rustls 按受信 CA 校验，
+/// 提取其身份写入请求扩展，供鉴权中间件在未携带 Bearer Key 时使用。
+pub async fn serve_tls(listener: TcpListener, app: Router, tls: Arc<TlsServer>) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("接受 TCP 连接失败: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = tls.acceptor();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    tracing::warn!("来自 {} 的 TLS 握手失败: {}", addr, e);
+                    return;
+                }
+                Err(_) => {
+                    tracing::warn!("来自 {} 的 TLS 握手超时", addr);
+                    return;
+                }
+            };
+
+            let mut extensions = Extensions::new();
+            extensions.insert(ConnectInfo::<SocketAddr>(addr));
+            let identity = stream.get_ref().1.peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| cert_identity(cert));
+            if let Some(identity) = identity {
+                tracing::debug!("客户端 {} 出示证书: {:?}", addr, identity.names);
+                extensions.insert(identity);
+            }
+            serve_connection(stream, app, extensions).await;
+        });
+    }
+}
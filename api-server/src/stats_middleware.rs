@@ -11,7 +11,7 @@ use chrono::Utc;
 use crate::router::AppState;
 
 /// 使用统计中间件
-/// 实现原理: 在请求处理前后记录时间，并异步将统计信息写入 SQLite。
+/// 实现原理: 在请求处理前后记录时间，并异步将统计信息写入 SQLite，同时累加按小时的用量汇总 (请求数、错误数、耗时)。
 pub async fn stats_middleware(
     State(state): State<AppState>,
     req: Request<Body>,
@@ -19,10 +19,12 @@ pub async fn stats_middleware(
 ) -> Response {
     let start = Instant::now();
     let user_id = req.extensions().get::<db::User>().map(|u| u.id.clone()).unwrap_or_else(|| "system".to_string());
+    let api_key_id = req.extensions().get::<db::ApiKey>().map(|k| k.id);
 
     // 处理请求
     let response = next.run(req).await;
     let duration = start.elapsed().as_millis() as i64;
+    let is_error = response.status().is_client_error() || response.status().is_server_error();
 
     // 从响应扩展中提取 ModelId
     let model_id = response.extensions().get::<crate::handlers::ModelId>()
@@ -33,6 +35,10 @@ pub async fn stats_middleware(
     let db = state.model_manager.db();
     tokio::spawn(async move {
         let repo = StatsRepo::new(&db);
+        let now = Utc::now();
+        if let Err(e) = repo.record_request_rollup(now, &user_id, api_key_id, &model_id, is_error, duration).await {
+            tracing::error!("记录用量汇总失败: {}", e);
+        }
         let stat = UsageStat {
             id: 0, // 自动递增
             user_id,
@@ -43,7 +49,7 @@ pub async fn stats_middleware(
             response_count: 1,
             duration_ms: duration,
             stat_type: "用户请求".to_string(),
-            timestamp: Utc::now(),
+            timestamp: now,
         };
         if let Err(e) = repo.record(stat).await {
             tracing::error!("记录统计数据失败: {}", e);
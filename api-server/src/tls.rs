@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use der::asn1::{Ia5StringRef, ObjectIdentifier, PrintableStringRef, TeletexStringRef, Utf8StringRef};
+use der::{AnyRef, Decode, Reader, SliceReader, Tag, Tagged};
+use tokio_rustls::TlsAcceptor;
+use utils::{anyhow, Result};
+
+/// 客户端证书要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// 可选: 出示的证书须由受信 CA 签发，未出示时仍可使用 Bearer Key
+    Optional,
+    /// 必须: 握手时未出示受信证书的连接直接拒绝
+    Required,
+}
+
+/// TLS 配置
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub client_ca_path: Option<PathBuf>, // 设置后启用 mTLS
+    pub client_auth: ClientAuth,
+    pub reload_interval: Duration, // 检查证书文件变化的间隔
+}
+
+impl TlsConfig {
+    /// 从环境变量读取配置 (未设置 TLS_CERT_PATH 时返回 None，使用明文 HTTP)
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(cert_path) = std::env::var("TLS_CERT_PATH").ok().filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        let key_path = std::env::var("TLS_KEY_PATH").map_err(|_| anyhow!("已设置 TLS_CERT_PATH，缺少 TLS_KEY_PATH"))?;
+        let client_ca_path = std::env::var("TLS_CLIENT_CA_PATH").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+        let client_auth = match std::env::var("TLS_CLIENT_AUTH").unwrap_or_default().as_str() {
+            "" | "optional" => ClientAuth::Optional,
+            "required" if client_ca_path.is_some() => ClientAuth::Required,
+            "required" => return Err(anyhow!("TLS_CLIENT_AUTH=required 需要同时设置 TLS_CLIENT_CA_PATH")),
+            other => return Err(anyhow!("无效的 TLS_CLIENT_AUTH: {} (可选 optional / required)", other)),
+        };
+        let reload_secs = std::env::var("TLS_RELOAD_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+        Ok(Some(Self {
+            cert_path: PathBuf::from(cert_path),
+            key_path: PathBuf::from(key_path),
+            client_ca_path,
+            client_auth,
+            reload_interval: Duration::from_secs(reload_secs),
+        }))
+    }
+
+    fn watched_files(&self) -> Vec<&Path> {
+        let mut files = vec![self.cert_path.as_path(), self.key_path.as_path()];
+        files.extend(self.client_ca_path.as_deref());
+        files
+    }
+}
+
+/// 客户端证书身份 (mTLS 握手成功后写入该连接上每个请求的扩展)
+/// `names` 依次为 Subject CN 与 SAN 中的 DNS、URI 名称，用于匹配 API Key 绑定的证书身份。
+#[derive(Debug, Clone)]
+pub struct ClientCertIdentity {
+    pub names: Vec<String>,
+}
+
+/// 可热更新的 TLS 服务端
+/// 实现原理: 当前生效的 rustls 配置保存在读写锁中，每个新连接取最新配置握手，已建立的连接不受影响。
+/// 后台任务按间隔比较证书、私钥与 CA 文件的修改时间和大小，有变化即重新加载；
+/// 新文件无效 (如证书与私钥只更新了一半) 时记录错误并继续使用旧配置，待文件再次变化时重试。
+pub struct TlsServer {
+    config: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+    stamps: Mutex<Vec<Option<(SystemTime, u64)>>>,
+}
+
+impl TlsServer {
+    pub fn new(config: TlsConfig) -> Result<Self> {
+        let stamps = file_stamps(&config);
+        let server_config = build_server_config(&config)?;
+        Ok(Self {
+            config,
+            current: RwLock::new(Arc::new(server_config)),
+            stamps: Mutex::new(stamps),
+        })
+    }
+
+    /// 以当前配置创建握手器
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner())))
+    }
+
+    /// 证书文件有变化时重新加载，返回是否已切换到新配置
+    pub fn reload_if_changed(&self) -> bool {
+        let stamps = file_stamps(&self.config);
+        {
+            let mut previous = self.stamps.lock().unwrap_or_else(|e| e.into_inner());
+            if *previous == stamps {
+                return false;
+            }
+            *previous = stamps;
+        }
+        match build_server_config(&self.config) {
+            Ok(server_config) => {
+                *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(server_config);
+                tracing::info!("TLS 证书已重新加载: {}", self.config.cert_path.display());
+                true
+            }
+            Err(e) => {
+                tracing::error!("重新加载 TLS 证书失败，继续使用旧证书: {}", e);
+                false
+            }
+        }
+    }
+
+    /// 启动后台任务定期检查证书文件
+    pub fn spawn_reloader(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(server.config.reload_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                server.reload_if_changed();
+            }
+        });
+    }
+}
+
+fn file_stamps(config: &TlsConfig) -> Vec<Option<(SystemTime, u64)>> {
+    config.watched_files().into_iter()
+        .map(|path| std::fs::metadata(path).ok().and_then(|m| Some((m.modified().ok()?, m.len()))))
+        .collect()
+}
+
+/// 读取证书、私钥与客户端 CA，构建 rustls 服务端配置
+fn build_server_config(config: &TlsConfig) -> Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|iter| iter.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("读取证书 {} 失败: {}", config.cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("证书文件 {} 中没有证书", config.cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| anyhow!("读取私钥 {} 失败: {}", config.key_path.display(), e))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            let cas = CertificateDer::pem_file_iter(ca_path)
+                .and_then(|iter| iter.collect::<std::result::Result<Vec<_>, _>>())
+                .map_err(|e| anyhow!("读取客户端 CA {} 失败: {}", ca_path.display(), e))?;
+            for ca in cas {
+                roots.add(ca).map_err(|e| anyhow!("无效的客户端 CA 证书: {}", e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = match config.client_auth {
+                ClientAuth::Optional => verifier.allow_unauthenticated(),
+                ClientAuth::Required => verifier,
+            };
+            builder.with_client_cert_verifier(verifier.build().map_err(|e| anyhow!("构建客户端证书校验器失败: {}", e))?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder.with_single_cert(certs, key)
+        .map_err(|e| anyhow!("证书与私钥无效或不匹配: {}", e))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+/// 从已校验的客户端证书 (DER) 中提取身份: Subject CN 与 SAN 中的 DNS、URI 名称
+/// 实现逻辑: 证书结构与 SAN 扩展由 webpki 解析 (与握手时校验证书链的是同一实现)，
+/// Subject 中的 CN 由 der 按 RFC 5280 的 Name 结构解码。解析失败或没有任何名称时返回 None。
+pub fn cert_identity(der: &[u8]) -> Option<ClientCertIdentity> {
+    let der = CertificateDer::from(der);
+    let cert = webpki::EndEntityCert::try_from(&der).ok()?;
+    let mut names = subject_common_names(cert.subject()).ok()?;
+    names.extend(cert.valid_dns_names().map(str::to_string));
+    names.extend(cert.valid_uri_names().map(str::to_string));
+    (!names.is_empty()).then_some(ClientCertIdentity { names })
+}
+
+/// 解码 Subject (不含外层 SEQUENCE) 中的 commonName
+/// Name ::= SEQUENCE OF RelativeDistinguishedName，RDN ::= SET OF { type OID, value DirectoryString }
+fn subject_common_names(subject: &[u8]) -> der::Result<Vec<String>> {
+    const COMMON_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+    let mut names = Vec::new();
+    let mut rdns = SliceReader::new(subject)?;
+    while !rdns.is_finished() {
+        let rdn = AnyRef::decode(&mut rdns)?;
+        rdn.tag().assert_eq(Tag::Set)?;
+        let mut attrs = SliceReader::new(rdn.value())?;
+        while !attrs.is_finished() {
+            attrs.sequence(|attr| {
+                let oid = ObjectIdentifier::decode(attr)?;
+                let value = AnyRef::decode(attr)?;
+                if oid == COMMON_NAME {
+                    names.push(directory_string(value)?);
+                }
+                Ok(())
+            })?;
+        }
+    }
+    Ok(names)
+}
+
+fn directory_string(value: AnyRef<'_>) -> der::Result<String> {
+    Ok(match value.tag() {
+        Tag::Utf8String => value.decode_as::<Utf8StringRef<'_>>()?.to_string(),
+        Tag::PrintableString => value.decode_as::<PrintableStringRef<'_>>()?.to_string(),
+        Tag::Ia5String => value.decode_as::<Ia5StringRef<'_>>()?.to_string(),
+        Tag::TeletexString => value.decode_as::<TeletexStringRef<'_>>()?.to_string(),
+        tag => return Err(tag.value_error()),
+    })
+}
@@ -0,0 +1,74 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use tokio::net::UnixListener;
+use utils::{anyhow, Result};
+
+/// UDS 监听配置
+#[derive(Debug, Clone)]
+pub struct UdsConfig {
+    pub path: PathBuf,
+    pub mode: Option<u32>,  // 套接字文件权限 (如 0o660)，未设置时由 umask 决定
+    pub owner: Option<u32>, // 套接字文件属主 uid
+    pub group: Option<u32>, // 套接字文件属组 gid
+}
+
+impl UdsConfig {
+    /// 从环境变量读取配置: UDS_PATH、UDS_MODE (八进制，如 660)、UDS_OWNER (uid:gid，任一侧可留空)
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("UDS_PATH").unwrap_or_else(|_| "/tmp/lowart.sock".to_string());
+        let mode = match std::env::var("UDS_MODE").ok().filter(|v| !v.is_empty()) {
+            Some(mode) => Some(u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                .ok()
+                .filter(|m| *m <= 0o777)
+                .ok_or_else(|| anyhow!("无效的 UDS_MODE: {} (应为八进制权限，如 660)", mode))?),
+            None => None,
+        };
+        let (owner, group) = match std::env::var("UDS_OWNER").ok().filter(|v| !v.is_empty()) {
+            Some(spec) => parse_owner(&spec).ok_or_else(|| anyhow!("无效的 UDS_OWNER: {} (应为 uid:gid 数字，任一侧可留空)", spec))?,
+            None => (None, None),
+        };
+        Ok(Self { path: PathBuf::from(path), mode, owner, group })
+    }
+
+    /// 绑定套接字并设置权限与属主
+    /// 实现逻辑: 先在同目录的临时路径上绑定并设置权限、属主，再原子重命名到目标路径，
+    /// 避免套接字以默认权限短暂暴露给其他本地用户。
+    pub fn bind(&self) -> Result<UnixListener> {
+        let _ = std::fs::remove_file(&self.path);
+        let mut staging = self.path.clone().into_os_string();
+        staging.push(format!(".{}.tmp", std::process::id()));
+        let staging = PathBuf::from(staging);
+        let _ = std::fs::remove_file(&staging);
+
+        let listener = UnixListener::bind(&staging)?;
+        let prepared = (|| -> std::io::Result<()> {
+            if let Some(mode) = self.mode {
+                std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(mode))?;
+            }
+            if self.owner.is_some() || self.group.is_some() {
+                std::os::unix::fs::chown(&staging, self.owner, self.group)?;
+            }
+            std::fs::rename(&staging, &self.path)
+        })();
+        if let Err(e) = prepared {
+            let _ = std::fs::remove_file(&staging);
+            return Err(anyhow!("设置 UDS 套接字 {} 权限失败: {}", self.path.display(), e));
+        }
+        Ok(listener)
+    }
+}
+
+fn parse_owner(spec: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let (owner, group) = spec.split_once(':').unwrap_or((spec, ""));
+    let parse = |v: &str| if v.is_empty() { Some(None) } else { v.parse().ok().map(Some) };
+    Some((parse(owner)?, parse(group)?))
+}
+
+/// UDS 对端进程凭证 (SO_PEERCRED，由内核在连接建立时提供，写入该连接上每个请求的扩展)
+#[derive(Debug, Clone, Copy)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
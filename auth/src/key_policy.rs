@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use db::{ApiKey, ApiKeySettings};
+use utils::{Result, anyhow};
+
+/// 可授予 Key 的接口范围
+pub const ENDPOINT_SCOPES: [&str; 6] = ["chat", "embeddings", "jobs:read", "account", "org", "admin"];
+
+/// 请求所属的接口范围 (不属于任何范围的请求只对不限范围的 Key 开放)
+pub fn endpoint_scope(method: &str, path: &str) -> Option<&'static str> {
+    if path.starts_with("/admin") {
+        Some("admin")
+    } else if path.starts_with("/v1/org") {
+        Some("org")
+    } else if path.starts_with("/v1/billing") {
+        Some("account")
+    } else if path.starts_with("/v1/jobs") {
+        (method == "GET").then_some("jobs:read")
+    } else if path.starts_with("/v1/embeddings") {
+        Some("embeddings")
+    } else if path.starts_with("/v1/chat") || path.starts_with("/v1/tools") {
+        Some("chat")
+    } else {
+        None
+    }
+}
+
+/// 逗号分隔列表 (None 表示不限)
+fn parse_list(value: Option<&str>) -> Option<Vec<&str>> {
+    value.map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).collect())
+}
+
+/// 通配匹配: `*` 匹配任意长度字符，`?` 匹配单个字符
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // 回退到上一个 `*`，让它多匹配一个字符
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Key 已过期
+pub fn is_expired(key: &ApiKey, now: DateTime<Utc>) -> bool {
+    key.expires_at.is_some_and(|at| at <= now)
+}
+
+/// 出示的 Key (哈希) 是否仍有效: 当前 Key，或轮换宽限期内的旧 Key
+pub fn accepts_hash(key: &ApiKey, key_hash: &str, now: DateTime<Utc>) -> bool {
+    key.key_hash == key_hash
+        || (key.previous_key_hash.as_deref() == Some(key_hash) && key.previous_expires_at.is_some_and(|at| at > now))
+}
+
+/// Key 是否可访问该接口
+pub fn allows_endpoint(key: &ApiKey, method: &str, path: &str) -> bool {
+    match parse_list(key.allowed_endpoints.as_deref()) {
+        None => true,
+        Some(scopes) => endpoint_scope(method, path).is_some_and(|scope| scopes.contains(&scope)),
+    }
+}
+
+/// Key 是否可调用该模型
+pub fn allows_model(key: &ApiKey, model_id: &str) -> bool {
+    match parse_list(key.allowed_models.as_deref()) {
+        None => true,
+        Some(patterns) => patterns.iter().any(|p| glob_match(p, model_id)),
+    }
+}
+
+/// 校验设置中的接口范围均为已知范围
+pub fn validate_settings(settings: &ApiKeySettings) -> Result<()> {
+    for scope in parse_list(settings.allowed_endpoints.as_deref()).unwrap_or_default() {
+        if !ENDPOINT_SCOPES.contains(&scope) {
+            return Err(anyhow!("未知的接口范围: {} (可选: {})", scope, ENDPOINT_SCOPES.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("gpt-4*", "gpt-4o-mini"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("claude-?-haiku", "claude-3-haiku"));
+        assert!(glob_match("*-mini", "gpt-4o-mini"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("gpt-4*", "gpt-3.5-turbo"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(!glob_match("gpt-4", "gpt-4o"));
+    }
+
+    #[test]
+    fn test_endpoint_scope() {
+        assert_eq!(endpoint_scope("POST", "/v1/chat/completions"), Some("chat"));
+        assert_eq!(endpoint_scope("GET", "/v1/jobs/abc"), Some("jobs:read"));
+        assert_eq!(endpoint_scope("POST", "/v1/jobs"), None);
+        assert_eq!(endpoint_scope("GET", "/admin/users"), Some("admin"));
+    }
+}
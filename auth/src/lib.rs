@@ -1,5 +1,11 @@
 pub mod user;
 pub mod manager;
+pub mod key_policy;
+pub mod rbac;
+pub mod password;
+pub mod totp;
+pub mod oidc;
 
 pub use user::*;
 pub use manager::AuthManager;
+pub use rbac::{Role, Permission};
@@ -1,4 +1,4 @@
-use db::{DbConnection, UserRepo};
+use db::{DbConnection, UserRepo, ApiKeyRepo};
 
 use utils::{Result, anyhow};
 
@@ -29,5 +29,37 @@ impl AuthManager {
         Ok(user)
     }
 
+    /// 校验 API Key 并返回用户及本次使用的 Key 记录 (用于按 Key 计费)
+    pub async fn authenticate_with_key(&self, api_key: &str) -> Result<(db::User, db::ApiKey)> {
+        let user = self.authenticate(api_key).await?;
+        let key = ApiKeyRepo::new(&self.db).find_by_key(api_key).await?
+            .ok_or_else(|| anyhow!("无效的 API Key"))?;
+        Ok((user, key))
+    }
+
+    /// 按客户端证书身份 (CN 与各 SAN) 查找绑定的 Key 并返回用户及 Key 记录 (证书链已由 TLS 层校验)
+    pub async fn authenticate_with_cert(&self, names: &[String]) -> Result<(db::User, db::ApiKey)> {
+        let key = ApiKeyRepo::new(&self.db).find_by_cert_names(names).await?
+            .ok_or_else(|| anyhow!("客户端证书未绑定 API Key"))?;
+        self.active_owner(key).await
+    }
+
+    /// 按 UDS 对端进程的 uid / gid 查找绑定的 Key 并返回用户及 Key 记录 (凭证由内核提供，无法伪造)
+    pub async fn authenticate_with_unix_peer(&self, uid: u32, gid: u32) -> Result<(db::User, db::ApiKey)> {
+        let key = ApiKeyRepo::new(&self.db).find_by_unix_peer(uid as i64, gid as i64).await?
+            .ok_or_else(|| anyhow!("本地进程 uid={} gid={} 未绑定 API Key", uid, gid))?;
+        self.active_owner(key).await
+    }
+
+    /// 查询 Key 所属用户并校验其状态
+    async fn active_owner(&self, key: db::ApiKey) -> Result<(db::User, db::ApiKey)> {
+        let user = UserRepo::new(&self.db).find_by_id(&key.user_id).await?
+            .ok_or_else(|| anyhow!("用户不存在"))?;
+        if user.status != "Active" {
+            return Err(anyhow!("用户状态异常: {}", user.status));
+        }
+        Ok((user, key))
+    }
+
 }
 
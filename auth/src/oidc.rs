@@ -0,0 +1,335 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use utils::{Result, anyhow};
+
+use crate::rbac::Role;
+
+/// 发现文档与 JWKS 的缓存时间
+const METADATA_TTL: Duration = Duration::from_secs(3600);
+/// 登录流程 (state) 的有效期
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(600);
+/// 遇到未知 kid 时重新拉取 JWKS 的最小间隔 (防止伪造 kid 的请求反复打到 IdP)
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// 允许的 ID Token 签名算法 (只接受非对称算法)
+const ALLOWED_ALGORITHMS: [Algorithm; 6] = [
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::ES256, Algorithm::ES384,
+];
+
+/// OIDC 单点登录配置
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+    /// 组信息所在的声明，支持以 `.` 分隔的嵌套路径 (如 `realm_access.roles`)
+    pub groups_claim: String,
+    /// IdP 组 -> 管理角色 (同一个组可映射多个角色)
+    pub role_mapping: Vec<(String, Role)>,
+    /// 登录成功后跳转的控制台地址 (未配置时回调直接返回 JSON)
+    pub post_login_redirect: Option<String>,
+}
+
+impl OidcConfig {
+    /// 从环境变量读取配置，未设置 `OIDC_ISSUER` 时视为未启用
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(issuer) = env("OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let required = |name: &str| env(name).ok_or_else(|| anyhow!("已设置 OIDC_ISSUER，但缺少 {}", name));
+        Ok(Some(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: required("OIDC_CLIENT_ID")?,
+            client_secret: env("OIDC_CLIENT_SECRET"),
+            redirect_uri: required("OIDC_REDIRECT_URI")?,
+            scopes: env("OIDC_SCOPES").unwrap_or_else(|| "openid profile email groups".to_string()),
+            groups_claim: env("OIDC_GROUPS_CLAIM").unwrap_or_else(|| "groups".to_string()),
+            role_mapping: parse_role_mapping(&env("OIDC_ROLE_MAPPING").unwrap_or_default())?,
+            post_login_redirect: env("OIDC_POST_LOGIN_REDIRECT"),
+        }))
+    }
+
+    /// 根据用户所在的组计算管理角色 (去重，按角色定义顺序)
+    pub fn roles_for_groups(&self, groups: &[String]) -> Vec<Role> {
+        Role::ALL
+            .into_iter()
+            .filter(|role| {
+                self.role_mapping
+                    .iter()
+                    .any(|(group, mapped)| mapped == role && groups.iter().any(|g| g == group))
+            })
+            .collect()
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// 解析组角色映射，格式为 `组=角色,组=角色`，例如 `lowart-admins=super-admin,finance=billing`
+pub fn parse_role_mapping(spec: &str) -> Result<Vec<(String, Role)>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (group, role) = item
+                .rsplit_once('=')
+                .ok_or_else(|| anyhow!("OIDC 角色映射格式错误: {}", item))?;
+            let role = Role::parse(role.trim()).ok_or_else(|| anyhow!("OIDC 角色映射中的未知角色: {}", role))?;
+            Ok((group.trim().to_string(), role))
+        })
+        .collect()
+}
+
+/// IdP 发现文档 (只取用到的字段)
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// 进行中的登录 (以 state 为键)
+#[derive(Clone)]
+struct PendingLogin {
+    nonce: String,
+    code_verifier: String,
+}
+
+/// 经 ID Token 验证的 IdP 用户身份
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    /// 建议的用户名: preferred_username > email > sub
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// OIDC 授权码流程客户端
+/// 实现原理: 登录时生成 state、nonce 与 PKCE 校验码 (S256) 并暂存，跳转到 IdP 授权端点；
+/// 回调时按 state 取回并作废暂存记录，用授权码换取 ID Token，再用 IdP 的 JWKS 校验签名、
+/// 签发方、受众、有效期与 nonce。发现文档与 JWKS 缓存一小时，遇到未知 kid (密钥轮换) 时重新拉取。
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    discovery: moka::future::Cache<(), Arc<Discovery>>,
+    jwks: moka::future::Cache<(), Arc<JwkSet>>,
+    jwks_refreshed_at: Mutex<Option<Instant>>,
+    pending: moka::future::Cache<String, PendingLogin>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            config,
+            http,
+            discovery: moka::future::Cache::builder().time_to_live(METADATA_TTL).build(),
+            jwks: moka::future::Cache::builder().time_to_live(METADATA_TTL).build(),
+            jwks_refreshed_at: Mutex::new(None),
+            pending: moka::future::Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(PENDING_LOGIN_TTL)
+                .build(),
+        })
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let resp = self.http.get(url).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("请求 {} 失败: HTTP {}", url, resp.status()));
+        }
+        Ok(resp.json().await?)
+    }
+
+    async fn discovery(&self) -> Result<Arc<Discovery>> {
+        self.discovery
+            .try_get_with((), async {
+                let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+                let doc: Discovery = self.fetch_json(&url).await?;
+                if doc.issuer.trim_end_matches('/') != self.config.issuer {
+                    return Err(anyhow!("发现文档的 issuer ({}) 与配置不一致", doc.issuer));
+                }
+                Ok(Arc::new(doc))
+            })
+            .await
+            .map_err(|e: Arc<utils::Error>| anyhow!("获取 OIDC 发现文档失败: {}", e))
+    }
+
+    async fn jwks(&self) -> Result<Arc<JwkSet>> {
+        let discovery = self.discovery().await?;
+        self.jwks
+            .try_get_with((), async { Ok(Arc::new(self.fetch_json::<JwkSet>(&discovery.jwks_uri).await?)) })
+            .await
+            .map_err(|e: Arc<utils::Error>| anyhow!("获取 JWKS 失败: {}", e))
+    }
+
+    /// 开始登录: 返回 IdP 授权地址
+    pub async fn authorization_url(&self) -> Result<String> {
+        let discovery = self.discovery().await?;
+        let (state, nonce, code_verifier) = (random_token()?, random_token()?, random_token()?);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        self.pending.insert(state, PendingLogin { nonce, code_verifier }).await;
+        Ok(url.to_string())
+    }
+
+    /// 完成登录: 校验 state，用授权码换取并验证 ID Token
+    pub async fn complete(&self, code: &str, state: &str) -> Result<OidcIdentity> {
+        let pending = self.pending.remove(state).await.ok_or_else(|| anyhow!("登录请求无效或已过期"))?;
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let resp = self.http.post(&discovery.token_endpoint).form(&form).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("换取令牌失败: HTTP {} {}", status, body));
+        }
+        let token: TokenResponse = resp.json().await?;
+        let claims = self.verify_id_token(&token.id_token, &pending.nonce).await?;
+        self.identity(&claims)
+    }
+
+    /// 校验 ID Token 并返回其声明
+    async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<Value> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(anyhow!("不支持的 ID Token 签名算法: {:?}", header.alg));
+        }
+        let kid = header.kid.ok_or_else(|| anyhow!("ID Token 缺少 kid"))?;
+
+        let mut jwks = self.jwks().await?;
+        if jwks.find(&kid).is_none() && self.may_refresh_jwks() {
+            self.jwks.invalidate(&()).await;
+            jwks = self.jwks().await?;
+        }
+        let jwk = jwks.find(&kid).ok_or_else(|| anyhow!("未知的签名密钥: {}", kid))?;
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<Value>(id_token, &key, &validation)?.claims;
+
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(anyhow!("ID Token 的 nonce 不匹配"));
+        }
+        Ok(claims)
+    }
+
+    fn may_refresh_jwks(&self) -> bool {
+        let mut last = self.jwks_refreshed_at.lock().unwrap_or_else(|e| e.into_inner());
+        if last.is_some_and(|t| t.elapsed() < JWKS_REFRESH_INTERVAL) {
+            return false;
+        }
+        *last = Some(Instant::now());
+        true
+    }
+
+    fn identity(&self, claims: &Value) -> Result<OidcIdentity> {
+        let text = |name: &str| claims.get(name).and_then(Value::as_str).map(str::to_string).filter(|s| !s.is_empty());
+        let subject = text("sub").ok_or_else(|| anyhow!("ID Token 缺少 sub"))?;
+        let email = text("email");
+        let username = text("preferred_username").or_else(|| email.clone()).unwrap_or_else(|| subject.clone());
+        Ok(OidcIdentity {
+            issuer: self.config.issuer.clone(),
+            subject,
+            email,
+            username,
+            groups: extract_groups(claims, &self.config.groups_claim),
+        })
+    }
+}
+
+/// 按声明路径取出组列表 (兼容字符串数组与单个字符串)
+fn extract_groups(claims: &Value, path: &str) -> Vec<String> {
+    let value = path.split('.').try_fold(claims, |v, key| v.get(key));
+    match value {
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        Some(Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// 256 位随机值 (Base64URL)，用作 state、nonce 与 PKCE 校验码
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("生成随机数失败: {}", e))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_role_mapping() {
+        let mapping = parse_role_mapping("lowart-admins=super-admin, finance=billing,finance=viewer,ops=model-operator").unwrap();
+        assert_eq!(mapping.len(), 4);
+        assert!(parse_role_mapping("finance").is_err());
+        assert!(parse_role_mapping("finance=admin").is_err());
+        assert!(parse_role_mapping("").unwrap().is_empty());
+
+        let config = OidcConfig {
+            issuer: "https://idp.example.com".into(),
+            client_id: "lowart".into(),
+            client_secret: None,
+            redirect_uri: "http://localhost/admin/oidc/callback".into(),
+            scopes: "openid".into(),
+            groups_claim: "realm_access.roles".into(),
+            role_mapping: mapping,
+            post_login_redirect: None,
+        };
+        let groups = vec!["ops".to_string(), "finance".to_string(), "unrelated".to_string()];
+        assert_eq!(config.roles_for_groups(&groups), vec![Role::Viewer, Role::Billing, Role::ModelOperator]);
+        assert!(config.roles_for_groups(&["unrelated".to_string()]).is_empty());
+
+        let claims = serde_json::json!({"realm_access": {"roles": ["ops", 1]}, "groups": "finance"});
+        assert_eq!(extract_groups(&claims, "realm_access.roles"), vec!["ops".to_string()]);
+        assert_eq!(extract_groups(&claims, "groups"), vec!["finance".to_string()]);
+        assert!(extract_groups(&claims, "missing.path").is_empty());
+    }
+}
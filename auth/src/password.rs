@@ -0,0 +1,58 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use utils::{Result, anyhow};
+
+/// 管理员密码最短长度
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// 计算密码哈希 (Argon2id，PHC 字符串格式，盐值与参数随哈希一并保存)
+pub fn hash_password(password: &str) -> Result<String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(anyhow!("密码长度不能少于 {} 位", MIN_PASSWORD_LEN));
+    }
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| anyhow!("生成随机盐失败: {}", e))?;
+    let salt = SaltString::encode_b64(&salt).map_err(|e| anyhow!("编码盐值失败: {}", e))?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("计算密码哈希失败: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// 与真实哈希参数相同的占位哈希 (首次使用时计算)
+static DUMMY_HASH: std::sync::LazyLock<Option<String>> = std::sync::LazyLock::new(|| hash_password("lowart-dummy-password").ok());
+
+/// 对不存在或未设置密码的账号执行一次同等开销的校验 (结果丢弃)
+/// 使登录耗时不随用户名是否存在而变化，避免据此枚举管理员账号。
+pub fn verify_dummy(password: &str) {
+    if let Some(hash) = DUMMY_HASH.as_deref() {
+        let _ = verify_password(password, hash);
+    }
+}
+
+/// 校验密码 (哈希格式无效时视为不匹配)
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+        Err(e) => {
+            tracing::warn!("密码哈希格式无效: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not-a-hash"));
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+        assert!(hash_password("short").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Serialize, Serializer};
+
+/// 管理后台角色 (一个用户可拥有多个角色，权限取并集)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Viewer,
+    Billing,
+    ModelOperator,
+    SecurityAdmin,
+    SuperAdmin,
+}
+
+/// 管理接口权限点 (按资源与读写划分)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    StatsRead,
+    UsersRead,
+    UsersWrite,
+    KeysWrite,
+    QuotaWrite,
+    BillingRead,
+    BillingWrite,
+    ModelsRead,
+    ModelsWrite,
+    PoliciesWrite,
+    McpWrite,
+    RolesWrite,
+    AuditRead,
+}
+
+impl Role {
+    pub const ALL: [Role; 5] = [Role::Viewer, Role::Billing, Role::ModelOperator, Role::SecurityAdmin, Role::SuperAdmin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Billing => "billing",
+            Self::ModelOperator => "model-operator",
+            Self::SecurityAdmin => "security-admin",
+            Self::SuperAdmin => "super-admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    /// 角色拥有的权限
+    /// 实现逻辑: 各角色仅拥有职责范围内的读写权限；注册 MCP 进程 (可执行任意命令) 仅超级管理员可用，
+    /// 安全管理员可分配角色但不能授予超级管理员 (见 `can_grant`)。
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Self::Viewer => &[StatsRead, UsersRead, BillingRead, ModelsRead],
+            Self::Billing => &[StatsRead, UsersRead, BillingRead, BillingWrite, QuotaWrite],
+            Self::ModelOperator => &[StatsRead, ModelsRead, ModelsWrite],
+            Self::SecurityAdmin => &[StatsRead, UsersRead, UsersWrite, KeysWrite, PoliciesWrite, RolesWrite, AuditRead],
+            Self::SuperAdmin => &[
+                StatsRead, UsersRead, UsersWrite, KeysWrite, QuotaWrite, BillingRead, BillingWrite,
+                ModelsRead, ModelsWrite, PoliciesWrite, McpWrite, RolesWrite, AuditRead,
+            ],
+        }
+    }
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StatsRead => "stats:read",
+            Self::UsersRead => "users:read",
+            Self::UsersWrite => "users:write",
+            Self::KeysWrite => "keys:write",
+            Self::QuotaWrite => "quota:write",
+            Self::BillingRead => "billing:read",
+            Self::BillingWrite => "billing:write",
+            Self::ModelsRead => "models:read",
+            Self::ModelsWrite => "models:write",
+            Self::PoliciesWrite => "policies:write",
+            Self::McpWrite => "mcp:write",
+            Self::RolesWrite => "roles:write",
+            Self::AuditRead => "audit:read",
+        }
+    }
+}
+
+impl Serialize for Role {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl Serialize for Permission {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// 一组角色是否拥有某项权限
+pub fn has_permission(roles: &[Role], permission: Permission) -> bool {
+    roles.iter().any(|r| r.permissions().contains(&permission))
+}
+
+/// 一组角色拥有的全部权限 (去重，按角色顺序)
+pub fn permissions_of(roles: &[Role]) -> Vec<Permission> {
+    let mut all = Vec::new();
+    for p in roles.iter().flat_map(|r| r.permissions()) {
+        if !all.contains(p) {
+            all.push(*p);
+        }
+    }
+    all
+}
+
+/// 是否可以授予或撤销某个角色: 超级管理员角色只能由超级管理员变更
+pub fn can_grant(granter: &[Role], role: Role) -> bool {
+    has_permission(granter, Permission::RolesWrite) && (role != Role::SuperAdmin || granter.contains(&Role::SuperAdmin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        assert_eq!(Role::parse("model-operator"), Some(Role::ModelOperator));
+        assert_eq!(Role::parse("admin"), None);
+
+        let viewer = [Role::Viewer];
+        assert!(has_permission(&viewer, Permission::StatsRead));
+        assert!(!has_permission(&viewer, Permission::UsersWrite));
+        assert!(!has_permission(&[Role::SecurityAdmin, Role::ModelOperator], Permission::McpWrite));
+        assert!(Role::ALL.iter().flat_map(|r| r.permissions()).all(|p| has_permission(&[Role::SuperAdmin], *p)));
+
+        assert!(can_grant(&[Role::SecurityAdmin], Role::Billing));
+        assert!(!can_grant(&[Role::SecurityAdmin], Role::SuperAdmin));
+        assert!(!can_grant(&[Role::Billing], Role::Viewer));
+        assert!(can_grant(&[Role::SuperAdmin], Role::SuperAdmin));
+        assert_eq!(permissions_of(&[Role::Viewer, Role::Billing]).len(), 6);
+    }
+}
@@ -0,0 +1,77 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+use utils::{Result, anyhow};
+
+/// 验证器应用中显示的签发方
+const ISSUER: &str = "LowArt";
+
+/// 两步验证 (RFC 6238 TOTP: SHA1、6 位、30 秒步长，允许前后各一个步长的时钟偏差)
+fn totp(secret_base32: &str, account: &str) -> Result<TOTP> {
+    let secret = Secret::Encoded(secret_base32.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow!("TOTP 密钥格式无效: {}", e))?;
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(ISSUER.to_string()), account.replace(':', "_"))
+        .map_err(|e| anyhow!("TOTP 参数无效: {}", e))
+}
+
+/// 生成新的 TOTP 密钥 (160 位，Base32 编码)
+pub fn generate_secret() -> Result<String> {
+    let mut bytes = [0u8; 20];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("生成 TOTP 密钥失败: {}", e))?;
+    Ok(Secret::Raw(bytes.to_vec()).to_encoded().to_string())
+}
+
+/// 供验证器应用扫码导入的 otpauth:// 链接
+pub fn provisioning_url(secret_base32: &str, account: &str) -> Result<String> {
+    Ok(totp(secret_base32, account)?.get_url())
+}
+
+/// 校验指定时间 (Unix 秒) 的验证码
+pub fn verify_code_at(secret_base32: &str, code: &str, unix_time: u64) -> bool {
+    totp(secret_base32, "").map(|t| t.check(code.trim(), unix_time)).unwrap_or(false)
+}
+
+/// 校验指定时间的验证码，返回其所属的时间步 (Unix 秒 / 步长)，不匹配时返回 None
+/// 调用方记录已接受的时间步并拒绝不大于它的验证码，防止同一验证码在有效期内被重放。
+pub fn verify_code_step_at(secret_base32: &str, code: &str, unix_time: u64) -> Option<u64> {
+    let totp = totp(secret_base32, "").ok()?;
+    let step = unix_time / totp.step;
+    let exact = TOTP { skew: 0, ..totp };
+    (step.saturating_sub(1)..=step + 1).rev()
+        .find(|s| exact.check(code.trim(), s * exact.step))
+}
+
+/// 校验当前验证码，返回其所属的时间步
+pub fn verify_code_step(secret_base32: &str, code: &str) -> Option<u64> {
+    verify_code_step_at(secret_base32, code, chrono::Utc::now().timestamp().max(0) as u64)
+}
+
+/// 校验当前验证码
+pub fn verify_code(secret_base32: &str, code: &str) -> bool {
+    verify_code_at(secret_base32, code, chrono::Utc::now().timestamp().max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp_codes() {
+        let secret = generate_secret().unwrap();
+        let now = 1_700_000_000;
+        let code = totp(&secret, "admin").unwrap().generate(now);
+        assert!(verify_code_at(&secret, &code, now));
+        assert!(verify_code_at(&secret, &code, now + 30)); // 允许一个步长的偏差
+        assert!(!verify_code_at(&secret, &code, now + 300));
+        assert!(!verify_code_at(&secret, "000000x", now));
+        assert!(!verify_code_at("not base32!", &code, now));
+        assert!(provisioning_url(&secret, "ops:admin").unwrap().starts_with("otpauth://totp/LowArt:ops_admin"));
+
+        // 返回验证码所属的时间步 (而不是校验时刻的时间步)
+        let step = now / 30;
+        assert_eq!(verify_code_step_at(&secret, &code, now), Some(step));
+        assert_eq!(verify_code_step_at(&secret, &code, now + 30), Some(step));
+        let next = totp(&secret, "admin").unwrap().generate(now + 30);
+        assert_eq!(verify_code_step_at(&secret, &next, now), Some(step + 1));
+        assert_eq!(verify_code_step_at(&secret, &code, now + 300), None);
+    }
+}
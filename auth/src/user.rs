@@ -17,6 +17,7 @@ pub enum UserStatus {
 }
 
 impl UserStatus {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s {
             "Active" => Self::Active,
@@ -0,0 +1,47 @@
+-- 计费账本与分项定价
+-- 金额统一使用微单位 (1 货币单位 = 1,000,000 微单位)，避免小额请求的精度丢失。
+
+-- 1. 模型分项单价 (每千 Token，微单位)
+ALTER TABLE model_configs ADD COLUMN input_price_per_1k INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE model_configs ADD COLUMN output_price_per_1k INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE model_configs ADD COLUMN cached_input_price_per_1k INTEGER NOT NULL DEFAULT 0;
+
+-- 数据迁移：旧的 cost_per_1k_tokens 以"分"为单位，1 分 = 10,000 微单位
+UPDATE model_configs SET
+    input_price_per_1k = cost_per_1k_tokens * 10000,
+    output_price_per_1k = cost_per_1k_tokens * 10000,
+    cached_input_price_per_1k = cost_per_1k_tokens * 10000;
+
+-- 2. 用户金额配额 (token_quota / token_used 仅表示 Token 数量)
+ALTER TABLE users ADD COLUMN cost_quota INTEGER;                 -- 金额配额 (微单位)，NULL 表示不限
+ALTER TABLE users ADD COLUMN cost_used INTEGER NOT NULL DEFAULT 0; -- 已消费金额 (微单位)
+
+-- 3. 计费账本 (只追加)
+CREATE TABLE IF NOT EXISTS billing_ledger (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    api_key_id INTEGER,
+    model_id TEXT NOT NULL,
+    input_tokens INTEGER NOT NULL DEFAULT 0,        -- 输入 Token 总数 (含缓存命中部分)
+    cached_input_tokens INTEGER NOT NULL DEFAULT 0, -- 其中命中缓存的输入 Token
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    input_price_per_1k INTEGER NOT NULL DEFAULT 0,  -- 计费时刻的单价快照
+    cached_input_price_per_1k INTEGER NOT NULL DEFAULT 0,
+    output_price_per_1k INTEGER NOT NULL DEFAULT 0,
+    amount INTEGER NOT NULL DEFAULT 0,              -- 本次费用 (微单位)
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_ledger_user ON billing_ledger(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_ledger_model ON billing_ledger(model_id);
+
+CREATE TRIGGER IF NOT EXISTS trg_billing_ledger_no_update
+BEFORE UPDATE ON billing_ledger
+BEGIN
+    SELECT RAISE(ABORT, 'billing_ledger 为只追加表，禁止修改');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_billing_ledger_no_delete
+BEFORE DELETE ON billing_ledger
+BEGIN
+    SELECT RAISE(ABORT, 'billing_ledger 为只追加表，禁止删除');
+END;
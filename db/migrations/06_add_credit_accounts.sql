@@ -0,0 +1,26 @@
+-- 预付费余额账户
+-- 存在账户记录的用户进入预付费模式，余额不足时拒绝请求。金额单位与账本一致 (微单位)。
+CREATE TABLE IF NOT EXISTS credit_accounts (
+    user_id TEXT PRIMARY KEY,
+    balance INTEGER NOT NULL DEFAULT 0,
+    low_balance_threshold INTEGER NOT NULL DEFAULT 0, -- 低于该值时在响应头中提示
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- 余额流水: 充值、用量扣费、退款、过期、人工调整
+CREATE TABLE IF NOT EXISTS credit_transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('topup', 'usage', 'refund', 'expiration', 'adjustment')),
+    amount INTEGER NOT NULL,             -- 正数入账，负数扣减
+    balance_after INTEGER NOT NULL,
+    remaining INTEGER NOT NULL DEFAULT 0, -- 入账批次尚未消耗的额度 (用于按批次过期)
+    expires_at DATETIME,                 -- 入账批次的过期时间，NULL 表示永久有效
+    ledger_id INTEGER,                   -- 关联的 billing_ledger 条目
+    note TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_credit_tx_user ON credit_transactions(user_id, id);
+CREATE INDEX IF NOT EXISTS idx_credit_tx_expiry ON credit_transactions(expires_at) WHERE remaining > 0;
@@ -0,0 +1,31 @@
+-- 周期配额策略
+-- 支持按日/周/月重置 (自然周期或滚动窗口)，可限定到单个模型或模型分组。
+
+-- 1. 模型分组 (如 "gpt-4" 系列)，用于按组设置配额
+ALTER TABLE model_configs ADD COLUMN model_group TEXT;
+
+-- 2. 配额策略
+CREATE TABLE IF NOT EXISTS quota_policies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT,                -- NULL 表示对所有用户生效；同作用域同周期下用户策略覆盖全局策略
+    scope_type TEXT NOT NULL DEFAULT 'all' CHECK(scope_type IN ('all', 'model', 'group')),
+    scope_value TEXT,            -- model_id 或分组名称 (scope_type 为 all 时为 NULL)
+    period TEXT NOT NULL CHECK(period IN ('daily', 'weekly', 'monthly')),
+    window_type TEXT NOT NULL DEFAULT 'calendar' CHECK(window_type IN ('calendar', 'rolling')),
+    token_limit INTEGER,         -- NULL 表示不限
+    cost_limit INTEGER,          -- 金额上限 (微单位)，NULL 表示不限
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_quota_policies_user ON quota_policies(user_id);
+
+-- 3. 自然周期窗口的用量计数 (滚动窗口直接由 billing_ledger 汇总)
+CREATE TABLE IF NOT EXISTS quota_counters (
+    policy_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    window_start DATETIME NOT NULL,
+    tokens_used INTEGER NOT NULL DEFAULT 0,
+    cost_used INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (policy_id, user_id),
+    FOREIGN KEY(policy_id) REFERENCES quota_policies(id) ON DELETE CASCADE
+);
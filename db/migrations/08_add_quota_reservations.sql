@@ -0,0 +1,14 @@
+-- 配额预占
+-- 请求放行前按预估的最大用量预占配额，结束后按实际用量结算并删除预占记录。
+-- 进程崩溃遗留的预占在 expires_at 之后自动失效。
+CREATE TABLE IF NOT EXISTS quota_reservations (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    tokens INTEGER NOT NULL,     -- 预占 Token 数
+    cost INTEGER NOT NULL,       -- 预占金额 (微单位)
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_quota_reservations_user ON quota_reservations(user_id);
+CREATE INDEX IF NOT EXISTS idx_quota_reservations_expires ON quota_reservations(expires_at);
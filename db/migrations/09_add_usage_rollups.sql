@@ -0,0 +1,63 @@
+-- 用量汇总表 (按小时)
+-- 请求数、错误数与耗时由统计中间件累加，Token 与金额由计费服务累加。
+-- 耗时以固定区间直方图保存，报表查询时合并直方图估算分位数。
+CREATE TABLE IF NOT EXISTS usage_rollups_hourly (
+    bucket DATETIME NOT NULL,            -- 小时起点 (UTC)
+    user_id TEXT NOT NULL,
+    api_key_id INTEGER NOT NULL DEFAULT 0, -- 0 表示无 API Key 信息 (如历史数据)
+    model_id TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    errors INTEGER NOT NULL DEFAULT 0,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    cost INTEGER NOT NULL DEFAULT 0,     -- 微单位
+    latency_ms_total INTEGER NOT NULL DEFAULT 0,
+    latency_le_100 INTEGER NOT NULL DEFAULT 0,
+    latency_le_250 INTEGER NOT NULL DEFAULT 0,
+    latency_le_500 INTEGER NOT NULL DEFAULT 0,
+    latency_le_1000 INTEGER NOT NULL DEFAULT 0,
+    latency_le_2500 INTEGER NOT NULL DEFAULT 0,
+    latency_le_5000 INTEGER NOT NULL DEFAULT 0,
+    latency_le_10000 INTEGER NOT NULL DEFAULT 0,
+    latency_le_30000 INTEGER NOT NULL DEFAULT 0,
+    latency_gt_30000 INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (bucket, user_id, api_key_id, model_id)
+);
+CREATE INDEX IF NOT EXISTS idx_usage_rollups_user ON usage_rollups_hourly(user_id, bucket);
+CREATE INDEX IF NOT EXISTS idx_usage_rollups_model ON usage_rollups_hourly(model_id, bucket);
+
+-- 回填历史数据: 请求与耗时来自用户请求记录，Token 来自厂商响应记录 (历史数据无 API Key 信息)
+INSERT INTO usage_rollups_hourly (bucket, user_id, api_key_id, model_id, requests, latency_ms_total,
+    latency_le_100, latency_le_250, latency_le_500, latency_le_1000, latency_le_2500,
+    latency_le_5000, latency_le_10000, latency_le_30000, latency_gt_30000)
+SELECT substr(timestamp, 1, 13) || ':00:00+00:00', user_id, 0, model_id,
+    SUM(request_count), SUM(duration_ms),
+    SUM(duration_ms <= 100),
+    SUM(duration_ms > 100 AND duration_ms <= 250),
+    SUM(duration_ms > 250 AND duration_ms <= 500),
+    SUM(duration_ms > 500 AND duration_ms <= 1000),
+    SUM(duration_ms > 1000 AND duration_ms <= 2500),
+    SUM(duration_ms > 2500 AND duration_ms <= 5000),
+    SUM(duration_ms > 5000 AND duration_ms <= 10000),
+    SUM(duration_ms > 10000 AND duration_ms <= 30000),
+    SUM(duration_ms > 30000)
+FROM usage_stats
+WHERE stat_type = '用户请求'
+GROUP BY 1, user_id, model_id;
+
+INSERT INTO usage_rollups_hourly (bucket, user_id, api_key_id, model_id, input_tokens, output_tokens)
+SELECT substr(timestamp, 1, 13) || ':00:00+00:00', user_id, 0, model_id, SUM(request_tokens), SUM(response_tokens)
+FROM usage_stats
+WHERE stat_type = '厂商返回响应'
+GROUP BY 1, user_id, model_id
+ON CONFLICT(bucket, user_id, api_key_id, model_id) DO UPDATE SET
+    input_tokens = input_tokens + excluded.input_tokens,
+    output_tokens = output_tokens + excluded.output_tokens;
+
+-- 金额来自计费账本
+INSERT INTO usage_rollups_hourly (bucket, user_id, api_key_id, model_id, cost)
+SELECT substr(created_at, 1, 13) || ':00:00+00:00', user_id, 0, model_id, SUM(amount)
+FROM billing_ledger
+GROUP BY 1, user_id, model_id
+ON CONFLICT(bucket, user_id, api_key_id, model_id) DO UPDATE SET
+    cost = cost + excluded.cost;
@@ -0,0 +1,26 @@
+-- 组织 (团队)
+-- 组织预算由全体成员共享，成员可另设子限额；组织管理员可管理本组织成员与 Key。
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY,          -- UUID
+    name TEXT NOT NULL UNIQUE,
+    token_budget INTEGER,         -- NULL 表示不限
+    cost_budget INTEGER,          -- 微单位，NULL 表示不限
+    token_used INTEGER NOT NULL DEFAULT 0,
+    cost_used INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 组织成员 (每个用户最多属于一个组织)
+CREATE TABLE IF NOT EXISTS org_members (
+    org_id TEXT NOT NULL,
+    user_id TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL DEFAULT 'member' CHECK(role IN ('member', 'admin')),
+    token_limit INTEGER,          -- 成员子限额，NULL 表示仅受组织预算约束
+    cost_limit INTEGER,
+    token_used INTEGER NOT NULL DEFAULT 0,
+    cost_used INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (org_id, user_id),
+    FOREIGN KEY(org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+-- 每分钟 Token 数 (TPM) 限制，NULL 表示不限
+-- 用户与 API Key 的限制约束单个调用方，模型的限制约束全部调用方对该模型的总吞吐。
+ALTER TABLE users ADD COLUMN tpm_limit INTEGER;
+ALTER TABLE api_keys ADD COLUMN tpm_limit INTEGER;
+ALTER TABLE model_configs ADD COLUMN tpm_limit INTEGER;
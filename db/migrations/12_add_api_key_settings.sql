@@ -0,0 +1,17 @@
+-- API Key 独立设置 (NULL 表示不限)
+-- allowed_models 为逗号分隔的通配模式 (如 gpt-4*,claude-*)；
+-- allowed_endpoints 为逗号分隔的接口范围 (chat, embeddings, jobs:read, account, org, admin)。
+ALTER TABLE api_keys ADD COLUMN rpm_limit INTEGER;
+ALTER TABLE api_keys ADD COLUMN spend_cap INTEGER;                      -- 微单位
+ALTER TABLE api_keys ADD COLUMN spend_used INTEGER NOT NULL DEFAULT 0;  -- 微单位
+ALTER TABLE api_keys ADD COLUMN allowed_models TEXT;
+ALTER TABLE api_keys ADD COLUMN allowed_endpoints TEXT;
+ALTER TABLE api_keys ADD COLUMN expires_at DATETIME;
+
+-- 历史消费从账本回填
+UPDATE api_keys SET spend_used = (
+    SELECT COALESCE(SUM(amount), 0) FROM billing_ledger WHERE billing_ledger.api_key_id = api_keys.id
+);
+
+-- 预占记录所属的 Key (用于重启后恢复 Key 消费上限的预占)
+ALTER TABLE quota_reservations ADD COLUMN api_key_id INTEGER;
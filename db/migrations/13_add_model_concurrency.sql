@@ -0,0 +1,8 @@
+-- 模型上游并发限制与排队参数
+-- max_concurrency 为 NULL 表示不限并发；max_queue / queue_timeout_ms 为 NULL 时使用默认值 (100 / 30000ms)。
+ALTER TABLE model_configs ADD COLUMN max_concurrency INTEGER;
+ALTER TABLE model_configs ADD COLUMN max_queue INTEGER;
+ALTER TABLE model_configs ADD COLUMN queue_timeout_ms INTEGER;
+
+-- 用户排队优先级，数值越大越先获得模型槽位
+ALTER TABLE users ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- API Key 只保存带密钥哈希，不再保存明文
+-- users.api_key 与 api_keys.api_key 此后保存 HMAC-SHA256 哈希 (沿用原有唯一约束用于查找)，key_prefix 保存展示用前缀。
+-- 哈希依赖服务端密钥，无法在 SQL 中计算: key_prefix 为 NULL 的行即尚未转换的明文 Key，
+-- 由启动时的 `ApiKeyRepo::hash_legacy_keys` 转换。
+ALTER TABLE api_keys ADD COLUMN key_prefix TEXT;
+ALTER TABLE users ADD COLUMN key_prefix TEXT;
@@ -0,0 +1,6 @@
+-- API Key 轮换宽限期与最近使用信息
+-- 轮换后旧 Key 的哈希保存在 previous_key_hash，在 previous_expires_at 之前仍可鉴权。
+ALTER TABLE api_keys ADD COLUMN previous_key_hash TEXT;
+ALTER TABLE api_keys ADD COLUMN previous_expires_at DATETIME;
+ALTER TABLE api_keys ADD COLUMN last_used_ip TEXT;
+CREATE INDEX IF NOT EXISTS idx_api_keys_previous_key_hash ON api_keys(previous_key_hash);
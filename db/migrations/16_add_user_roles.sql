@@ -0,0 +1,11 @@
+-- 管理后台角色 (一个用户可拥有多个角色，权限取并集)
+-- 角色取值见 auth::rbac::Role；原管理员标记迁移为超级管理员。
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('viewer', 'billing', 'model-operator', 'security-admin', 'super-admin')),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, role),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+INSERT OR IGNORE INTO user_roles (user_id, role) SELECT id, 'super-admin' FROM users WHERE is_admin = 1;
@@ -0,0 +1,40 @@
+-- 管理操作审计日志 (只追加)
+-- 每条记录的 hash = SHA-256(上一条的 hash + 本条内容)，首条的 prev_hash 为 64 个 0；
+-- audit_chain 保存链头与条数，用于发现末尾记录被截断。changes 中的密钥类字段已脱敏。
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor_id TEXT NOT NULL,
+    actor_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    changes TEXT NOT NULL,
+    ip TEXT,
+    created_at DATETIME NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor_id, id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_target ON audit_log(target_type, target_id, id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at);
+
+CREATE TABLE IF NOT EXISTS audit_chain (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    head_hash TEXT NOT NULL,
+    length INTEGER NOT NULL
+);
+
+INSERT OR IGNORE INTO audit_chain (id, head_hash, length)
+VALUES (1, '0000000000000000000000000000000000000000000000000000000000000000', 0);
+
+-- 禁止修改或删除审计记录
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
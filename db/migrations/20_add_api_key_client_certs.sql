@@ -0,0 +1,4 @@
+-- API Key 绑定的客户端证书身份 (mTLS)
+-- 值与客户端证书的 Subject CN 或任一 SAN (DNS / URI / Email / IP) 相同即视为持有该 Key。
+ALTER TABLE api_keys ADD COLUMN client_cert_subject TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_client_cert_subject ON api_keys(client_cert_subject);
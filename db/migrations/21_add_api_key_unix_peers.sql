@@ -0,0 +1,6 @@
+-- API Key 绑定的 Unix 本地进程身份 (UDS 模式下按 SO_PEERCRED 认证)
+-- 连接进程的 uid 与 unix_uid 相同，或其主 gid 与 unix_gid 相同即视为持有该 Key (uid 优先)。
+ALTER TABLE api_keys ADD COLUMN unix_uid INTEGER;
+ALTER TABLE api_keys ADD COLUMN unix_gid INTEGER;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_unix_uid ON api_keys(unix_uid);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_unix_gid ON api_keys(unix_gid);
@@ -0,0 +1,11 @@
+-- 模型断路器参数 (均为 NULL 时使用全局默认值)
+-- breaker_window_secs: 滑动窗口长度；breaker_min_requests: 窗口内最少请求数，达到后才判断是否熔断
+-- breaker_error_rate / breaker_slow_call_rate: 失败率 / 慢调用率阈值 (百分比)；breaker_slow_call_ms: 慢调用耗时阈值
+-- breaker_open_secs: 首次熔断时长，连续熔断时按指数退避，不超过 breaker_max_open_secs
+ALTER TABLE model_configs ADD COLUMN breaker_window_secs INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_min_requests INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_error_rate INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_slow_call_ms INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_slow_call_rate INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_open_secs INTEGER;
+ALTER TABLE model_configs ADD COLUMN breaker_max_open_secs INTEGER;
@@ -0,0 +1,3 @@
+-- 模型主动健康检查的探测方式
+-- NULL 使用厂商默认探测 (列出模型或 ComfyUI /system_stats)；off 表示不检查；其余为 JSON 格式的最小对话请求体
+ALTER TABLE model_configs ADD COLUMN health_probe TEXT;
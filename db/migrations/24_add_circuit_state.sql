@@ -0,0 +1,25 @@
+-- 断路器状态 (每个模型一行，保存最近一次状态转换)
+-- 进程启动时据此恢复熔断状态；多个实例共用数据库时按 seq (全局递增) 增量拉取其他实例写入的转换。
+CREATE TABLE IF NOT EXISTS circuit_states (
+    model_id TEXT PRIMARY KEY,
+    state TEXT NOT NULL,                          -- closed, open, half_open
+    forced_open INTEGER NOT NULL DEFAULT 0,       -- 管理员强制熔断
+    consecutive_trips INTEGER NOT NULL DEFAULT 0, -- 连续熔断次数 (退避)
+    open_until DATETIME,                          -- 熔断结束时间 (强制熔断或非熔断状态为 NULL)
+    instance_id TEXT NOT NULL,                    -- 写入该状态的网关实例
+    seq INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_circuit_states_seq ON circuit_states(seq);
+
+-- 主动健康检查样本 (每个模型只保留最近若干条)
+CREATE TABLE IF NOT EXISTS model_health_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    model_id TEXT NOT NULL,
+    healthy INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error TEXT,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    checked_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_model_health_samples_model ON model_health_samples(model_id, id);
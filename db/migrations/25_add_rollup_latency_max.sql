@@ -0,0 +1,13 @@
+-- 用量汇总记录窗口内的最大耗时
+-- 落入溢出区间 (>30000ms) 的分位数以最大耗时报告，而不是截断为最大区间上界
+ALTER TABLE usage_rollups_hourly ADD COLUMN latency_ms_max INTEGER NOT NULL DEFAULT 0;
+
+-- 回填历史数据 (与 09 一致，来自用户请求记录，API Key 为 0 的行)
+UPDATE usage_rollups_hourly SET latency_ms_max = COALESCE((
+    SELECT MAX(s.duration_ms) FROM usage_stats s
+    WHERE s.stat_type = '用户请求'
+      AND s.user_id = usage_rollups_hourly.user_id
+      AND s.model_id = usage_rollups_hourly.model_id
+      AND substr(s.timestamp, 1, 13) || ':00:00+00:00' = usage_rollups_hourly.bucket
+), 0)
+WHERE api_key_id = 0 AND latency_gt_30000 > 0;
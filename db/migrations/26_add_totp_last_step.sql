@@ -0,0 +1,2 @@
+-- 最近一次登录时接受的两步验证时间步 (Unix 秒 / 30)，不大于它的验证码视为重放
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
@@ -0,0 +1,106 @@
+use crate::models::AdminSession;
+use crate::connection::DbConnection;
+use chrono::{DateTime, Utc};
+use utils::{Crypto, Result};
+
+/// 会话令牌前缀 (鉴权时据此区分会话令牌与 API Key)
+pub const SESSION_TOKEN_PREFIX: &str = "las-";
+
+/// 管理后台会话仓库
+/// 实现逻辑: 登录时签发随机令牌，数据库只保存其哈希；令牌在过期时间前且未被撤销时有效。
+pub struct AdminSessionRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> AdminSessionRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 创建会话，返回明文令牌
+    pub async fn create(&self, user_id: &str, ip: Option<&str>, user_agent: Option<&str>, expires_at: DateTime<Utc>) -> Result<String> {
+        let token = format!(
+            "{}{}{}",
+            SESSION_TOKEN_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        sqlx::query(
+            "INSERT INTO admin_sessions (user_id, token_hash, ip, user_agent, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(user_id)
+        .bind(Crypto::hash_api_key(&token))
+        .bind(ip)
+        .bind(user_agent)
+        .bind(Utc::now())
+        .bind(expires_at)
+        .execute(&self.db.pool).await?;
+        Ok(token)
+    }
+
+    /// 根据明文令牌获取有效会话 (未过期且未撤销)
+    pub async fn find_active(&self, token: &str) -> Result<Option<AdminSession>> {
+        let session = sqlx::query_as::<_, AdminSession>(
+            "SELECT * FROM admin_sessions WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > ?"
+        )
+        .bind(Crypto::hash_api_key(token))
+        .bind(Utc::now())
+        .fetch_optional(&self.db.pool)
+        .await?;
+        Ok(session)
+    }
+
+    pub async fn find_by_id(&self, id: i64) -> Result<Option<AdminSession>> {
+        let session = sqlx::query_as::<_, AdminSession>("SELECT * FROM admin_sessions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(session)
+    }
+
+    /// 有效会话列表 (user_id 为 None 时列出全部用户)
+    pub async fn list_active(&self, user_id: Option<&str>) -> Result<Vec<AdminSession>> {
+        let sessions = sqlx::query_as::<_, AdminSession>(
+            "SELECT * FROM admin_sessions
+             WHERE revoked_at IS NULL AND expires_at > ? AND (? IS NULL OR user_id = ?)
+             ORDER BY created_at DESC"
+        )
+        .bind(Utc::now())
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    /// 撤销单个会话
+    pub async fn revoke(&self, id: i64) -> Result<()> {
+        sqlx::query("UPDATE admin_sessions SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    /// 撤销用户的全部会话 (可保留当前会话)，返回撤销数量
+    pub async fn revoke_user(&self, user_id: &str, except: Option<i64>) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE admin_sessions SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL AND (? IS NULL OR id != ?)"
+        )
+        .bind(Utc::now())
+        .bind(user_id)
+        .bind(except)
+        .bind(except)
+        .execute(&self.db.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// 清理已过期或已撤销的会话记录
+    pub async fn delete_expired(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM admin_sessions WHERE expires_at < ? OR revoked_at < ?")
+            .bind(before)
+            .bind(before)
+            .execute(&self.db.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
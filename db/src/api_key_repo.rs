@@ -1,9 +1,12 @@
-use crate::models::ApiKey;
+use crate::models::{ApiKey, ApiKeySettings};
 use crate::connection::DbConnection;
-use utils::Result;
-use chrono::Utc;
+use crate::events::ChangeEvent;
+use utils::{Crypto, Result};
+use chrono::{DateTime, Utc};
 
 /// API Key 资源仓库
+/// 实现逻辑: 数据库只保存 Key 的 HMAC 哈希与展示前缀，查找时先对传入的明文计算哈希；
+/// 新生成的明文 Key 只在创建或重置时返回一次。
 pub struct ApiKeyRepo<'a> {
     pub db: &'a DbConnection,
 }
@@ -22,26 +25,48 @@ impl<'a> ApiKeyRepo<'a> {
         Ok(keys)
     }
 
-    /// 创建新的 API Key
+    /// 创建新的 API Key (返回明文，仅此一次)
     pub async fn create(&self, user_id: &str, label: &str) -> Result<String> {
-        let new_key = format!("la-{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
+        let new_key = generate_key();
         sqlx::query(
-            "INSERT INTO api_keys (user_id, api_key, label, status) VALUES (?, ?, ?, 'Active')"
+            "INSERT INTO api_keys (user_id, api_key, key_prefix, label, status) VALUES (?, ?, ?, ?, 'Active')"
         )
         .bind(user_id)
-        .bind(&new_key)
+        .bind(Crypto::hash_api_key(&new_key))
+        .bind(Crypto::api_key_prefix(&new_key))
         .bind(label)
         .execute(&self.db.pool).await?;
         Ok(new_key)
     }
 
-    /// 重置 (重新生成) 指定 ID 的 API Key
+    /// 重置 (重新生成) 指定 ID 的 API Key，旧 Key 立即失效 (返回明文，仅此一次)
     pub async fn reset(&self, id: i64) -> Result<String> {
-        let new_key = format!("la-{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
-        sqlx::query("UPDATE api_keys SET api_key = ?, created_at = CURRENT_TIMESTAMP WHERE id = ?")
-            .bind(&new_key)
-            .bind(id)
-            .execute(&self.db.pool).await?;
+        let new_key = generate_key();
+        sqlx::query(
+            "UPDATE api_keys SET api_key = ?, key_prefix = ?, previous_key_hash = NULL, previous_expires_at = NULL,
+                created_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(Crypto::hash_api_key(&new_key))
+        .bind(Crypto::api_key_prefix(&new_key))
+        .bind(id)
+        .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
+        Ok(new_key)
+    }
+
+    /// 轮换 API Key: 生成新 Key，旧 Key 在宽限期截止前仍可使用 (返回新 Key 明文，仅此一次)
+    pub async fn rotate(&self, id: i64, grace_until: DateTime<Utc>) -> Result<String> {
+        let new_key = generate_key();
+        sqlx::query(
+            "UPDATE api_keys SET previous_key_hash = api_key, previous_expires_at = ?, api_key = ?, key_prefix = ?,
+                created_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(grace_until)
+        .bind(Crypto::hash_api_key(&new_key))
+        .bind(Crypto::api_key_prefix(&new_key))
+        .bind(id)
+        .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
         Ok(new_key)
     }
 
@@ -50,15 +75,22 @@ impl<'a> ApiKeyRepo<'a> {
         sqlx::query("DELETE FROM api_keys WHERE id = ?")
             .bind(id)
             .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
         Ok(())
     }
 
-    /// 根据 Key 查找 (用于鉴权)
+    /// 根据明文 Key 查找 (用于鉴权，轮换宽限期内的旧 Key 同样有效)
     pub async fn find_by_key(&self, api_key: &str) -> Result<Option<ApiKey>> {
-        let key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE api_key = ? AND status = 'Active'")
-            .bind(api_key)
-            .fetch_optional(&self.db.pool)
-            .await?;
+        let key_hash = Crypto::hash_api_key(api_key);
+        let key = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE status = 'Active'
+             AND (api_key = ? OR (previous_key_hash = ? AND previous_expires_at > ?))"
+        )
+        .bind(&key_hash)
+        .bind(&key_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.db.pool)
+        .await?;
         Ok(key)
     }
 
@@ -71,12 +103,147 @@ impl<'a> ApiKeyRepo<'a> {
         Ok(key)
     }
 
-    /// 更新最后使用时间
-    pub async fn update_last_used(&self, id: i64) -> Result<()> {
-        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+    /// 更新 Key 的独立限制与访问范围
+    pub async fn update_settings(&self, id: i64, settings: &ApiKeySettings) -> Result<()> {
+        sqlx::query(
+            "UPDATE api_keys SET rpm_limit = ?, tpm_limit = ?, spend_cap = ?, allowed_models = ?, allowed_endpoints = ?, expires_at = ?
+             WHERE id = ?"
+        )
+        .bind(settings.rpm_limit)
+        .bind(settings.tpm_limit)
+        .bind(settings.spend_cap)
+        .bind(&settings.allowed_models)
+        .bind(&settings.allowed_endpoints)
+        .bind(settings.expires_at)
+        .bind(id)
+        .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
+        Ok(())
+    }
+
+    /// 绑定或解除客户端证书身份 (同一身份只能绑定一个 Key)
+    pub async fn set_client_cert_subject(&self, id: i64, subject: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE api_keys SET client_cert_subject = ? WHERE id = ?")
+            .bind(subject)
+            .bind(id)
+            .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
+        Ok(())
+    }
+
+    /// 证书身份是否已绑定到其他 Key
+    pub async fn cert_subject_taken(&self, subject: &str, exclude_id: i64) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE client_cert_subject = ? AND id != ?")
+            .bind(subject)
+            .bind(exclude_id)
+            .fetch_one(&self.db.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// 按客户端证书的身份名 (CN 与各 SAN) 查找已绑定的有效 Key，按名称顺序取第一个匹配
+    pub async fn find_by_cert_names(&self, names: &[String]) -> Result<Option<ApiKey>> {
+        for name in names {
+            let key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE client_cert_subject = ? AND status = 'Active'")
+                .bind(name)
+                .fetch_optional(&self.db.pool)
+                .await?;
+            if key.is_some() {
+                return Ok(key);
+            }
+        }
+        Ok(None)
+    }
+
+    /// 绑定或解除 Unix 本地进程身份 (uid / gid 各自只能绑定一个 Key)
+    pub async fn set_unix_peer(&self, id: i64, uid: Option<i64>, gid: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE api_keys SET unix_uid = ?, unix_gid = ? WHERE id = ?")
+            .bind(uid)
+            .bind(gid)
+            .bind(id)
+            .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::ApiKey(id));
+        Ok(())
+    }
+
+    /// uid 或 gid 是否已绑定到其他 Key
+    pub async fn unix_peer_taken(&self, uid: Option<i64>, gid: Option<i64>, exclude_id: i64) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE (unix_uid = ? OR unix_gid = ?) AND id != ?")
+            .bind(uid)
+            .bind(gid)
+            .bind(exclude_id)
+            .fetch_one(&self.db.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// 按对端进程的 uid / gid 查找已绑定的有效 Key (uid 绑定优先)
+    pub async fn find_by_unix_peer(&self, uid: i64, gid: i64) -> Result<Option<ApiKey>> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE status = 'Active' AND (unix_uid = ? OR unix_gid = ?)
+             ORDER BY unix_uid = ? DESC LIMIT 1"
+        )
+        .bind(uid)
+        .bind(gid)
+        .bind(uid)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        Ok(key)
+    }
+
+    /// 更新最后使用时间与来源 IP
+    pub async fn update_last_used(&self, id: i64, ip: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE api_keys SET last_used_at = ?, last_used_ip = COALESCE(?, last_used_ip) WHERE id = ?")
             .bind(Utc::now())
+            .bind(ip)
             .bind(id)
             .execute(&self.db.pool).await?;
         Ok(())
     }
+
+    /// 查询闲置的 Key: 在截止时间之后未使用过 (从未使用的按创建时间判断)
+    pub async fn list_stale(&self, cutoff: DateTime<Utc>) -> Result<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE status = 'Active' AND COALESCE(last_used_at, created_at) < ?
+             ORDER BY COALESCE(last_used_at, created_at)"
+        )
+        .bind(cutoff)
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    /// 将尚未转换的明文 Key (key_prefix 为 NULL) 替换为哈希，包括 users 表中的旧字段
+    /// 先在事务外读取待转换的行，事务内只写入：读事务升级为写事务时若有其他连接正在写 (如记录最近使用时间)，
+    /// SQLite 会直接返回 "database is locked" 而不等待。
+    pub async fn hash_legacy_keys(&self) -> Result<()> {
+        let mut pending = Vec::new();
+        for table in ["api_keys", "users"] {
+            let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+                "SELECT CAST(id AS TEXT), api_key FROM {} WHERE key_prefix IS NULL", table
+            ))
+            .fetch_all(&self.db.pool).await?;
+            pending.extend(rows.into_iter().map(|(id, plain)| (table, id, plain)));
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.db.pool.begin().await?;
+        for (table, id, plain) in &pending {
+            sqlx::query(&format!("UPDATE {} SET api_key = ?, key_prefix = ? WHERE CAST(id AS TEXT) = ? AND key_prefix IS NULL", table))
+                .bind(Crypto::hash_api_key(plain))
+                .bind(Crypto::api_key_prefix(plain))
+                .bind(id)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        tracing::info!("已将 {} 个明文 API Key 转换为哈希存储", pending.len());
+        Ok(())
+    }
+}
+
+/// 生成新的明文 API Key
+pub fn generate_key() -> String {
+    format!("la-{}", uuid::Uuid::new_v4().to_string().replace("-", ""))
 }
@@ -0,0 +1,181 @@
+use crate::models::AuditEntry;
+use crate::connection::DbConnection;
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use utils::{Crypto, Result};
+
+/// 哈希链起点 (首条记录的 prev_hash)
+pub const AUDIT_GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// 待写入的审计记录
+pub struct NewAuditEntry<'a> {
+    pub actor_id: &'a str,
+    pub actor_name: &'a str,
+    pub action: &'a str,
+    pub target_type: &'a str,
+    pub target_id: &'a str,
+    pub changes: &'a serde_json::Value,
+    pub ip: Option<&'a str>,
+}
+
+/// 审计日志查询条件 (均可选，按 id 倒序分页)
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub before_id: Option<i64>, // 翻页: 只返回 id 小于该值的记录
+    pub limit: Option<i64>,
+}
+
+/// 哈希链校验结果
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    pub checked: i64,
+    pub head_hash: String,
+    pub broken_at: Option<i64>, // 第一条校验失败的记录 id
+    pub reason: Option<String>,
+}
+
+/// 审计日志仓库
+/// 实现逻辑: 追加时在同一事务内先更新链头 (取得写锁，避免并发追加读到同一个上一条哈希)，
+/// 再以上一条哈希与本条内容计算新哈希 (以服务端密钥做 HMAC) 并写入。表上的触发器禁止修改与删除；
+/// 绕过触发器直接改库会使该条及之后的哈希无法通过校验 (没有服务端密钥无法重算)，删除末尾记录则与链头的条数不一致。
+pub struct AuditRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> AuditRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 追加一条审计记录，返回其 id
+    pub async fn append(&self, entry: &NewAuditEntry<'_>) -> Result<i64> {
+        // 时间截断到微秒，保证读回后重新计算的哈希一致
+        let created_at = Utc::now().trunc_subsecs(6);
+        let changes = entry.changes.to_string();
+
+        let mut tx = self.db.pool.begin().await?;
+        let prev_hash: String = sqlx::query_scalar("UPDATE audit_chain SET length = length + 1 WHERE id = 1 RETURNING head_hash")
+            .fetch_one(&mut *tx).await?;
+        let hash = entry_hash(
+            &prev_hash, entry.actor_id, entry.actor_name, entry.action, entry.target_type, entry.target_id,
+            &changes, entry.ip, &created_at,
+        );
+        let id = sqlx::query(
+            "INSERT INTO audit_log (actor_id, actor_name, action, target_type, target_id, changes, ip, created_at, prev_hash, hash)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(entry.actor_id)
+        .bind(entry.actor_name)
+        .bind(entry.action)
+        .bind(entry.target_type)
+        .bind(entry.target_id)
+        .bind(&changes)
+        .bind(entry.ip)
+        .bind(created_at)
+        .bind(&prev_hash)
+        .bind(&hash)
+        .execute(&mut *tx).await?
+        .last_insert_rowid();
+        sqlx::query("UPDATE audit_chain SET head_hash = ? WHERE id = 1")
+            .bind(&hash)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// 按条件查询 (id 倒序，默认 100 条，最多 1000 条)
+    pub async fn list(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let entries = sqlx::query_as::<_, AuditEntry>(
+            "SELECT * FROM audit_log
+             WHERE (? IS NULL OR actor_id = ?) AND (? IS NULL OR action = ?)
+               AND (? IS NULL OR target_type = ?) AND (? IS NULL OR target_id = ?)
+               AND (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?)
+               AND (? IS NULL OR id < ?)
+             ORDER BY id DESC LIMIT ?"
+        )
+        .bind(&query.actor_id).bind(&query.actor_id)
+        .bind(&query.action).bind(&query.action)
+        .bind(&query.target_type).bind(&query.target_type)
+        .bind(&query.target_id).bind(&query.target_id)
+        .bind(query.since).bind(query.since)
+        .bind(query.until).bind(query.until)
+        .bind(query.before_id).bind(query.before_id)
+        .bind(query.limit.unwrap_or(100).clamp(1, 1000))
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(entries)
+    }
+
+    /// 从头校验整条哈希链
+    pub async fn verify(&self) -> Result<AuditVerification> {
+        let (head_hash, length): (String, i64) = sqlx::query_as("SELECT head_hash, length FROM audit_chain WHERE id = 1")
+            .fetch_one(&self.db.pool)
+            .await?;
+        let broken = |checked: i64, id: Option<i64>, reason: String| AuditVerification {
+            valid: false,
+            checked,
+            head_hash: head_hash.clone(),
+            broken_at: id,
+            reason: Some(reason),
+        };
+
+        let (mut expected_prev, mut checked, mut last_id) = (AUDIT_GENESIS_HASH.to_string(), 0i64, 0i64);
+        loop {
+            let batch = sqlx::query_as::<_, AuditEntry>("SELECT * FROM audit_log WHERE id > ? ORDER BY id LIMIT 1000")
+                .bind(last_id)
+                .fetch_all(&self.db.pool)
+                .await?;
+            if batch.is_empty() {
+                break;
+            }
+            for entry in &batch {
+                if entry.prev_hash != expected_prev {
+                    return Ok(broken(checked, Some(entry.id), "prev_hash 与上一条记录不一致 (记录被删除或插入)".into()));
+                }
+                let hash = entry_hash(
+                    &entry.prev_hash, &entry.actor_id, &entry.actor_name, &entry.action, &entry.target_type,
+                    &entry.target_id, &entry.changes, entry.ip.as_deref(), &entry.created_at,
+                );
+                if hash != entry.hash {
+                    return Ok(broken(checked, Some(entry.id), "记录内容与哈希不符 (记录被篡改)".into()));
+                }
+                expected_prev = hash;
+                checked += 1;
+                last_id = entry.id;
+            }
+        }
+
+        if checked != length || expected_prev != head_hash {
+            return Ok(broken(checked, None, format!("链头记录 {} 条，实际校验 {} 条 (末尾记录被删除或链头被篡改)", length, checked)));
+        }
+        Ok(AuditVerification { valid: true, checked, head_hash, broken_at: None, reason: None })
+    }
+}
+
+/// 记录哈希: HMAC-SHA256(服务端密钥, 上一条哈希与本条各字段组成的 JSON 数组)
+/// 以服务端密钥为键，仅有数据库写权限者改写记录后无法重新计算出有效的后续哈希。
+#[allow(clippy::too_many_arguments)]
+fn entry_hash(
+    prev_hash: &str,
+    actor_id: &str,
+    actor_name: &str,
+    action: &str,
+    target_type: &str,
+    target_id: &str,
+    changes: &str,
+    ip: Option<&str>,
+    created_at: &DateTime<Utc>,
+) -> String {
+    let content = serde_json::json!([
+        prev_hash, actor_id, actor_name, action, target_type, target_id, changes, ip,
+        created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+    ]);
+    Crypto::keyed_hash_hex("audit-log", content.to_string().as_bytes())
+}
@@ -0,0 +1,81 @@
+use crate::models::LedgerEntry;
+use crate::connection::DbConnection;
+use utils::Result;
+
+/// 计费账本仓库
+/// 实现逻辑: 账本只追加，写入账本、累加用户与组织用量、预付费余额扣减在同一事务内完成，保证数据一致。
+pub struct BillingRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> BillingRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 记录一次扣费 (忽略 entry 中的 id 与 created_at)，返回账本条目 ID
+    pub async fn record_charge(&self, entry: &LedgerEntry) -> Result<i64> {
+        let mut tx = self.db.pool.begin().await?;
+
+        // 1. 追加账本
+        let id = sqlx::query(
+            "INSERT INTO billing_ledger (user_id, api_key_id, model_id, input_tokens, cached_input_tokens, output_tokens,
+                input_price_per_1k, cached_input_price_per_1k, output_price_per_1k, amount, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&entry.user_id)
+        .bind(entry.api_key_id)
+        .bind(&entry.model_id)
+        .bind(entry.input_tokens)
+        .bind(entry.cached_input_tokens)
+        .bind(entry.output_tokens)
+        .bind(entry.input_price_per_1k)
+        .bind(entry.cached_input_price_per_1k)
+        .bind(entry.output_price_per_1k)
+        .bind(entry.amount)
+        .bind(chrono::Utc::now())
+        .execute(&mut *tx).await?
+        .last_insert_rowid();
+
+        // 2. 累加用户 Token 与金额用量
+        sqlx::query("UPDATE users SET token_used = token_used + ?, cost_used = cost_used + ? WHERE id = ?")
+            .bind(entry.input_tokens + entry.output_tokens)
+            .bind(entry.amount)
+            .bind(&entry.user_id)
+            .execute(&mut *tx).await?;
+
+        // 3. 累加 Key 消费 (用于 Key 消费上限)
+        if let Some(key_id) = entry.api_key_id {
+            sqlx::query("UPDATE api_keys SET spend_used = spend_used + ? WHERE id = ?")
+                .bind(entry.amount)
+                .bind(key_id)
+                .execute(&mut *tx).await?;
+        }
+
+        // 4. 组织成员同步累加组织与成员用量
+        crate::org_repo::add_usage(&mut tx, &entry.user_id, entry.input_tokens + entry.output_tokens, entry.amount).await?;
+
+        // 5. 预付费用户同步扣减余额
+        crate::credit_repo::debit_usage(&mut tx, &entry.user_id, entry.amount, id).await?;
+
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// 查询账本 (可按用户过滤)，按时间倒序
+    pub async fn list(&self, user_id: Option<&str>, limit: i64) -> Result<Vec<LedgerEntry>> {
+        let entries = if let Some(uid) = user_id {
+            sqlx::query_as::<_, LedgerEntry>("SELECT * FROM billing_ledger WHERE user_id = ? ORDER BY id DESC LIMIT ?")
+                .bind(uid)
+                .bind(limit)
+                .fetch_all(&self.db.pool)
+                .await?
+        } else {
+            sqlx::query_as::<_, LedgerEntry>("SELECT * FROM billing_ledger ORDER BY id DESC LIMIT ?")
+                .bind(limit)
+                .fetch_all(&self.db.pool)
+                .await?
+        };
+        Ok(entries)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::models::CircuitStateRecord;
+use crate::connection::DbConnection;
+use utils::Result;
+
+/// 断路器状态仓库
+/// 实现逻辑: 每个模型只保留最近一次状态转换，写入时分配全局递增的 seq，
+/// 其他实例按 seq 增量读取即可得知自上次同步以来的全部变更。
+pub struct CircuitRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> CircuitRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 写入模型的最新状态 (seq 在同一语句内分配，SQLite 写锁保证全局递增)
+    pub async fn upsert(&self, record: &CircuitStateRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO circuit_states (model_id, state, forced_open, consecutive_trips, open_until, instance_id, seq, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(seq), 0) + 1 FROM circuit_states), ?)
+             ON CONFLICT(model_id) DO UPDATE SET
+                state = excluded.state,
+                forced_open = excluded.forced_open,
+                consecutive_trips = excluded.consecutive_trips,
+                open_until = excluded.open_until,
+                instance_id = excluded.instance_id,
+                seq = excluded.seq,
+                updated_at = excluded.updated_at"
+        )
+        .bind(&record.model_id)
+        .bind(&record.state)
+        .bind(record.forced_open)
+        .bind(record.consecutive_trips)
+        .bind(record.open_until)
+        .bind(&record.instance_id)
+        .bind(record.updated_at)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 查询 seq 大于指定值的状态 (按 seq 升序；传 0 即全部)
+    pub async fn list_since(&self, seq: i64) -> Result<Vec<CircuitStateRecord>> {
+        let records = sqlx::query_as::<_, CircuitStateRecord>("SELECT * FROM circuit_states WHERE seq > ? ORDER BY seq")
+            .bind(seq)
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(records)
+    }
+}
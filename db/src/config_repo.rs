@@ -1,5 +1,6 @@
 use crate::models::ModelConfig;
 use crate::connection::DbConnection;
+use crate::events::ChangeEvent;
 use utils::Result;
 
 /// 模型配置资源仓库
@@ -28,6 +29,15 @@ impl<'a> ConfigRepo<'a> {
         Ok(configs)
     }
 
+    /// 根据配置 ID 获取配置 (含未启用的)
+    pub async fn find_by_id(&self, id: &str) -> Result<Option<ModelConfig>> {
+        let config = sqlx::query_as::<_, ModelConfig>("SELECT * FROM model_configs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(config)
+    }
+
     /// 根据 model_id 获取配置
     pub async fn find_by_model_id(&self, model_id: &str) -> Result<Option<ModelConfig>> {
         let config = sqlx::query_as::<_, ModelConfig>("SELECT * FROM model_configs WHERE model_id = ? AND is_active = 1")
@@ -40,7 +50,11 @@ impl<'a> ConfigRepo<'a> {
     /// 创建或重置模型配置
     pub async fn create(&self, config: &ModelConfig) -> Result<()> {
         sqlx::query(
-            "INSERT INTO model_configs (id, title, model_id, api_key, base_url, vendor_type, cost_per_1k_tokens, is_active, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            "INSERT INTO model_configs (id, title, model_id, api_key, base_url, vendor_type, cost_per_1k_tokens,
+                input_price_per_1k, output_price_per_1k, cached_input_price_per_1k, model_group, tpm_limit,
+                max_concurrency, max_queue, queue_timeout_ms, breaker_window_secs, breaker_min_requests, breaker_error_rate,
+                breaker_slow_call_ms, breaker_slow_call_rate, breaker_open_secs, breaker_max_open_secs, health_probe, is_active, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(&config.id)
         .bind(&config.title)
@@ -49,16 +63,37 @@ impl<'a> ConfigRepo<'a> {
         .bind(&config.base_url)
         .bind(&config.vendor_type)
         .bind(config.cost_per_1k_tokens)
+        .bind(config.input_price_per_1k)
+        .bind(config.output_price_per_1k)
+        .bind(config.cached_input_price_per_1k)
+        .bind(&config.model_group)
+        .bind(config.tpm_limit)
+        .bind(config.max_concurrency)
+        .bind(config.max_queue)
+        .bind(config.queue_timeout_ms)
+        .bind(config.breaker_window_secs)
+        .bind(config.breaker_min_requests)
+        .bind(config.breaker_error_rate)
+        .bind(config.breaker_slow_call_ms)
+        .bind(config.breaker_slow_call_rate)
+        .bind(config.breaker_open_secs)
+        .bind(config.breaker_max_open_secs)
+        .bind(&config.health_probe)
         .bind(config.is_active)
         .bind(config.created_at)
         .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::Model(config.id.clone()));
         Ok(())
     }
 
     /// 更新模型配置
     pub async fn update(&self, config: &ModelConfig) -> Result<()> {
         sqlx::query(
-            "UPDATE model_configs SET title = ?, model_id = ?, api_key = ?, base_url = ?, vendor_type = ?, cost_per_1k_tokens = ?, is_active = ? WHERE id = ?"
+            "UPDATE model_configs SET title = ?, model_id = ?, api_key = ?, base_url = ?, vendor_type = ?, cost_per_1k_tokens = ?,
+                input_price_per_1k = ?, output_price_per_1k = ?, cached_input_price_per_1k = ?, model_group = ?, tpm_limit = ?,
+                max_concurrency = ?, max_queue = ?, queue_timeout_ms = ?, breaker_window_secs = ?, breaker_min_requests = ?,
+                breaker_error_rate = ?, breaker_slow_call_ms = ?, breaker_slow_call_rate = ?, breaker_open_secs = ?,
+                breaker_max_open_secs = ?, health_probe = ?, is_active = ? WHERE id = ?"
         )
         .bind(&config.title)
         .bind(&config.model_id)
@@ -66,9 +101,26 @@ impl<'a> ConfigRepo<'a> {
         .bind(&config.base_url)
         .bind(&config.vendor_type)
         .bind(config.cost_per_1k_tokens)
+        .bind(config.input_price_per_1k)
+        .bind(config.output_price_per_1k)
+        .bind(config.cached_input_price_per_1k)
+        .bind(&config.model_group)
+        .bind(config.tpm_limit)
+        .bind(config.max_concurrency)
+        .bind(config.max_queue)
+        .bind(config.queue_timeout_ms)
+        .bind(config.breaker_window_secs)
+        .bind(config.breaker_min_requests)
+        .bind(config.breaker_error_rate)
+        .bind(config.breaker_slow_call_ms)
+        .bind(config.breaker_slow_call_rate)
+        .bind(config.breaker_open_secs)
+        .bind(config.breaker_max_open_secs)
+        .bind(&config.health_probe)
         .bind(config.is_active)
         .bind(&config.id)
         .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::Model(config.id.clone()));
         Ok(())
     }
 
@@ -77,6 +129,7 @@ impl<'a> ConfigRepo<'a> {
         sqlx::query("DELETE FROM model_configs WHERE id = ?")
             .bind(id)
             .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::Model(id.to_string()));
         Ok(())
     }
 }
@@ -1,11 +1,15 @@
 use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 use utils::Result;
+use crate::ApiKeyRepo;
+use crate::events::ChangeBus;
 use std::env;
 
 /// 数据库连接管理
-/// 实现原理: 使用 SQLx 的 SqlitePool 维护连接池，支持并发访问。
+/// 实现原理: 使用 SQLx 的 SqlitePool 维护连接池，支持并发访问；
+/// 仓库写入成功后经 `changes` 通知各缓存失效。
 pub struct DbConnection {
     pub pool: SqlitePool,
+    pub changes: ChangeBus,
 }
 
 impl DbConnection {
@@ -30,7 +34,11 @@ impl DbConnection {
             .await?;
         tracing::info!("数据库迁移完成");
 
-        Ok(Self { pool })
+        // 明文 API Key 的哈希依赖服务端密钥，无法在 SQL 迁移中完成
+        let conn = Self { pool, changes: ChangeBus::default() };
+        ApiKeyRepo::new(&conn).hash_legacy_keys().await?;
+
+        Ok(conn)
     }
 
 }
@@ -0,0 +1,233 @@
+use crate::models::{CreditAccount, CreditTransaction};
+use crate::connection::DbConnection;
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+use utils::{Result, anyhow};
+
+/// 预付费余额仓库
+/// 实现逻辑: 每笔入账 (充值/退款/正向调整) 都是一个带剩余额度的批次，扣减时按过期时间先后消耗批次，
+/// 过期任务只回收批次中尚未消耗的部分。始终保持 `sum(remaining) == max(balance, 0)`。
+pub struct CreditRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> CreditRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 获取用户的余额账户 (不存在表示非预付费用户)
+    pub async fn get_account(&self, user_id: &str) -> Result<Option<CreditAccount>> {
+        let account = sqlx::query_as::<_, CreditAccount>("SELECT * FROM credit_accounts WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(account)
+    }
+
+    /// 充值 (账户不存在时自动开户)，返回充值后的余额
+    pub async fn top_up(&self, user_id: &str, amount: i64, expires_at: Option<DateTime<Utc>>, note: Option<&str>) -> Result<i64> {
+        if amount <= 0 {
+            return Err(anyhow!("充值金额必须大于 0"));
+        }
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query("INSERT INTO credit_accounts (user_id, balance) VALUES (?, 0) ON CONFLICT(user_id) DO NOTHING")
+            .bind(user_id)
+            .execute(&mut *tx).await?;
+        let balance = credit(&mut tx, user_id, "topup", amount, expires_at, None, note).await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    /// 退款 (可关联账本条目)，返回退款后的余额
+    pub async fn refund(&self, user_id: &str, amount: i64, ledger_id: Option<i64>, note: Option<&str>) -> Result<i64> {
+        if amount <= 0 {
+            return Err(anyhow!("退款金额必须大于 0"));
+        }
+        let mut tx = self.db.pool.begin().await?;
+        let balance = credit(&mut tx, user_id, "refund", amount, None, ledger_id, note).await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    /// 人工调整 (正数入账，负数扣减)，返回调整后的余额
+    pub async fn adjust(&self, user_id: &str, amount: i64, note: Option<&str>) -> Result<i64> {
+        let mut tx = self.db.pool.begin().await?;
+        let balance = if amount >= 0 {
+            credit(&mut tx, user_id, "adjustment", amount, None, None, note).await?
+        } else {
+            debit(&mut tx, user_id, "adjustment", -amount, None, note).await?
+                .ok_or_else(|| anyhow!("用户 {} 没有余额账户", user_id))?
+        };
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    /// 设置低余额提醒阈值
+    pub async fn set_low_balance_threshold(&self, user_id: &str, threshold: i64) -> Result<()> {
+        sqlx::query("UPDATE credit_accounts SET low_balance_threshold = ?, updated_at = ? WHERE user_id = ?")
+            .bind(threshold)
+            .bind(Utc::now())
+            .bind(user_id)
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    /// 回收所有已过期批次的剩余额度，返回处理的批次数
+    pub async fn expire_due(&self, now: DateTime<Utc>) -> Result<u64> {
+        let lots: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, user_id FROM credit_transactions WHERE remaining > 0 AND expires_at IS NOT NULL AND expires_at <= ?"
+        )
+        .bind(now)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let mut expired = 0;
+        for (lot_id, user_id) in lots {
+            let mut tx = self.db.pool.begin().await?;
+            // 先写账户行取得写锁，再在事务内重新读取剩余额度，避免与并发扣费冲突
+            sqlx::query("UPDATE credit_accounts SET updated_at = ? WHERE user_id = ?")
+                .bind(Utc::now())
+                .bind(&user_id)
+                .execute(&mut *tx).await?;
+            let remaining: i64 = sqlx::query_scalar("SELECT remaining FROM credit_transactions WHERE id = ?")
+                .bind(lot_id)
+                .fetch_one(&mut *tx).await?;
+            if remaining > 0 {
+                sqlx::query("UPDATE credit_transactions SET remaining = 0 WHERE id = ?")
+                    .bind(lot_id)
+                    .execute(&mut *tx).await?;
+                let balance: i64 = sqlx::query_scalar(
+                    "UPDATE credit_accounts SET balance = balance - ?, updated_at = ? WHERE user_id = ? RETURNING balance"
+                )
+                .bind(remaining)
+                .bind(Utc::now())
+                .bind(&user_id)
+                .fetch_one(&mut *tx).await?;
+                let note = format!("入账批次 #{} 过期", lot_id);
+                insert_transaction(&mut tx, &user_id, "expiration", -remaining, balance, 0, None, None, Some(&note)).await?;
+                expired += 1;
+            }
+            tx.commit().await?;
+        }
+        Ok(expired)
+    }
+
+    /// 查询用户的余额流水，按时间倒序
+    pub async fn list_transactions(&self, user_id: &str, limit: i64) -> Result<Vec<CreditTransaction>> {
+        let txs = sqlx::query_as::<_, CreditTransaction>(
+            "SELECT * FROM credit_transactions WHERE user_id = ? ORDER BY id DESC LIMIT ?"
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(txs)
+    }
+}
+
+/// 用量扣费 (供计费事务内部调用)。用户没有余额账户时不做任何处理。
+pub(crate) async fn debit_usage(conn: &mut SqliteConnection, user_id: &str, amount: i64, ledger_id: i64) -> Result<()> {
+    if amount > 0 {
+        debit(conn, user_id, "usage", amount, Some(ledger_id), None).await?;
+    }
+    Ok(())
+}
+
+/// 入账: 新批次的剩余额度只计入使余额转正的部分 (先抵扣透支)
+async fn credit(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    kind: &str,
+    amount: i64,
+    expires_at: Option<DateTime<Utc>>,
+    ledger_id: Option<i64>,
+    note: Option<&str>,
+) -> Result<i64> {
+    let balance: i64 = sqlx::query_scalar(
+        "UPDATE credit_accounts SET balance = balance + ?, updated_at = ? WHERE user_id = ? RETURNING balance"
+    )
+    .bind(amount)
+    .bind(Utc::now())
+    .bind(user_id)
+    .fetch_optional(&mut *conn).await?
+    .ok_or_else(|| anyhow!("用户 {} 没有余额账户", user_id))?;
+
+    let previous = balance - amount;
+    let remaining = balance.max(0) - previous.max(0);
+    insert_transaction(conn, user_id, kind, amount, balance, remaining, expires_at, ledger_id, note).await?;
+    Ok(balance)
+}
+
+/// 扣减: 按过期时间先后消耗入账批次，余额允许透支 (请求在扣费前已放行)
+async fn debit(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    kind: &str,
+    amount: i64,
+    ledger_id: Option<i64>,
+    note: Option<&str>,
+) -> Result<Option<i64>> {
+    let balance: Option<i64> = sqlx::query_scalar(
+        "UPDATE credit_accounts SET balance = balance - ?, updated_at = ? WHERE user_id = ? RETURNING balance"
+    )
+    .bind(amount)
+    .bind(Utc::now())
+    .bind(user_id)
+    .fetch_optional(&mut *conn).await?;
+    let Some(balance) = balance else {
+        return Ok(None);
+    };
+
+    let lots: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT id, remaining FROM credit_transactions WHERE user_id = ? AND remaining > 0
+         ORDER BY expires_at IS NULL, expires_at, id"
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn).await?;
+
+    let mut left = amount;
+    for (lot_id, remaining) in lots {
+        if left == 0 {
+            break;
+        }
+        let used = remaining.min(left);
+        sqlx::query("UPDATE credit_transactions SET remaining = remaining - ? WHERE id = ?")
+            .bind(used)
+            .bind(lot_id)
+            .execute(&mut *conn).await?;
+        left -= used;
+    }
+
+    insert_transaction(conn, user_id, kind, -amount, balance, 0, None, ledger_id, note).await?;
+    Ok(Some(balance))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn insert_transaction(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    kind: &str,
+    amount: i64,
+    balance_after: i64,
+    remaining: i64,
+    expires_at: Option<DateTime<Utc>>,
+    ledger_id: Option<i64>,
+    note: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO credit_transactions (user_id, kind, amount, balance_after, remaining, expires_at, ledger_id, note, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(kind)
+    .bind(amount)
+    .bind(balance_after)
+    .bind(remaining)
+    .bind(expires_at)
+    .bind(ledger_id)
+    .bind(note)
+    .bind(Utc::now())
+    .execute(conn).await?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::sync::RwLock;
+
+/// 实体变更事件 (由仓库在写入成功后发布)
+/// 用量累加 (token_used / cost_used / spend_used) 不发布事件: 配额判断以预占账本为准，账本自行结算，
+/// 鉴权缓存中的用量字段不参与任何判断。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// 用户资料、状态、限流配额或角色变更 (含删除)，携带用户 ID
+    User(String),
+    /// API Key 重置、轮换、限额变更或删除，携带 Key ID
+    ApiKey(i64),
+    /// 模型配置新增、修改或删除，携带配置 ID (`model_configs.id`)
+    Model(String),
+}
+
+type Subscriber = Box<dyn Fn(&ChangeEvent) + Send + Sync>;
+
+/// 进程内变更通知总线
+/// 实现原理: 订阅者以回调形式注册，发布时在写入方的调用栈内同步执行，
+/// 写接口返回前各缓存已完成失效，下一个请求不会读到旧数据。回调只应做内存操作，不可阻塞。
+#[derive(Default)]
+pub struct ChangeBus {
+    subscribers: RwLock<Vec<Subscriber>>,
+}
+
+impl ChangeBus {
+    /// 注册订阅者 (与连接同生命周期，不支持退订)
+    pub fn subscribe(&self, subscriber: impl Fn(&ChangeEvent) + Send + Sync + 'static) {
+        self.subscribers.write().unwrap_or_else(|e| e.into_inner()).push(Box::new(subscriber));
+    }
+
+    /// 发布变更事件
+    pub fn publish(&self, event: ChangeEvent) {
+        tracing::debug!("实体变更: {:?}", event);
+        for subscriber in self.subscribers.read().unwrap_or_else(|e| e.into_inner()).iter() {
+            subscriber(&event);
+        }
+    }
+}
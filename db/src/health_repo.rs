@@ -0,0 +1,75 @@
+use crate::models::HealthSample;
+use crate::connection::DbConnection;
+use chrono::{DateTime, Utc};
+use utils::Result;
+
+/// 每个模型保留的健康检查样本数
+pub const HEALTH_SAMPLES_PER_MODEL: i64 = 100;
+
+/// 健康检查样本仓库
+pub struct HealthRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> HealthRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 写入一条样本，并删除该模型超出保留数量的旧样本
+    pub async fn insert(
+        &self,
+        model_id: &str,
+        healthy: bool,
+        latency_ms: i64,
+        error: Option<&str>,
+        consecutive_failures: i64,
+        checked_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO model_health_samples (model_id, healthy, latency_ms, error, consecutive_failures, checked_at) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(model_id)
+        .bind(healthy)
+        .bind(latency_ms)
+        .bind(error)
+        .bind(consecutive_failures)
+        .bind(checked_at)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "DELETE FROM model_health_samples WHERE model_id = ? AND id NOT IN
+             (SELECT id FROM model_health_samples WHERE model_id = ? ORDER BY id DESC LIMIT ?)"
+        )
+        .bind(model_id)
+        .bind(model_id)
+        .bind(HEALTH_SAMPLES_PER_MODEL)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 每个模型最近一条样本 (用于进程启动后恢复健康状态)
+    pub async fn latest_per_model(&self) -> Result<Vec<HealthSample>> {
+        let samples = sqlx::query_as::<_, HealthSample>(
+            "SELECT * FROM model_health_samples WHERE id IN (SELECT MAX(id) FROM model_health_samples GROUP BY model_id)"
+        )
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(samples)
+    }
+
+    /// 模型最近的样本 (按时间倒序)
+    pub async fn list_recent(&self, model_id: &str, limit: i64) -> Result<Vec<HealthSample>> {
+        let samples = sqlx::query_as::<_, HealthSample>(
+            "SELECT * FROM model_health_samples WHERE model_id = ? ORDER BY id DESC LIMIT ?"
+        )
+        .bind(model_id)
+        .bind(limit)
+        .fetch_all(&self.db.pool)
+        .await?;
+        Ok(samples)
+    }
+}
@@ -8,14 +8,38 @@ pub mod session_repo;
 pub mod job_repo;
 pub mod fallback_repo;
 pub mod api_key_repo;
+pub mod billing_repo;
+pub mod credit_repo;
+pub mod quota_repo;
+pub mod reservation_repo;
+pub mod org_repo;
+pub mod role_repo;
+pub mod admin_session_repo;
+pub mod oidc_identity_repo;
+pub mod audit_repo;
+pub mod circuit_repo;
+pub mod health_repo;
+pub mod events;
 
 
 pub use connection::DbConnection;
-pub use models::{User, ModelConfig, UsageStat, ApiKey};
+pub use events::{ChangeBus, ChangeEvent};
+pub use models::{User, ModelConfig, UsageStat, ApiKey, ApiKeySettings, LedgerEntry, CreditAccount, CreditTransaction, QuotaPolicy, QuotaCounter, QuotaReservation, UsageReportRow, Organization, OrgMember, UserRole, AdminSession, AuditEntry, CircuitStateRecord, HealthSample};
 pub use user_repo::UserRepo;
 pub use config_repo::ConfigRepo;
 pub use api_key_repo::ApiKeyRepo;
-pub use stats_repo::StatsRepo;
+pub use billing_repo::BillingRepo;
+pub use credit_repo::CreditRepo;
+pub use quota_repo::QuotaRepo;
+pub use reservation_repo::ReservationRepo;
+pub use org_repo::OrgRepo;
+pub use role_repo::{RoleRepo, SUPER_ADMIN_ROLE};
+pub use admin_session_repo::{AdminSessionRepo, SESSION_TOKEN_PREFIX};
+pub use oidc_identity_repo::OidcIdentityRepo;
+pub use audit_repo::{AuditRepo, AuditQuery, AuditVerification, NewAuditEntry, AUDIT_GENESIS_HASH};
+pub use circuit_repo::CircuitRepo;
+pub use health_repo::HealthRepo;
+pub use stats_repo::{StatsRepo, ReportGroupBy};
 pub use tool_policy_repo::{ToolPolicyRepo, ToolPolicy};
 pub use session_repo::{SessionRepo, ToolSession};
 pub use job_repo::{JobRepo, AsyncJob};
@@ -7,12 +7,20 @@ use chrono::{DateTime, Utc};
 pub struct User {
     pub id: String,
     pub username: String,
-    pub api_key: String, // TODO: Deprecate after migration
+    #[sqlx(rename = "api_key")]
+    #[serde(skip)]
+    pub key_hash: String, // Default Key 的哈希 (TODO: Deprecate after migration)
+    pub key_prefix: Option<String>,
     pub status: String,
     pub rpm_limit: i64,
+    pub tpm_limit: Option<i64>, // 每分钟 Token 数，None 表示不限
     pub token_quota: i64,
     pub token_used: i64,
+    pub cost_quota: Option<i64>, // 金额配额 (微单位)，None 表示不限
+    pub cost_used: i64,
     pub is_admin: bool,
+    pub priority: i64, // 模型排队优先级，越大越优先
+    pub totp_enabled: bool, // 管理后台登录是否需要两步验证
     pub created_at: DateTime<Utc>,
 }
 
@@ -20,13 +28,47 @@ pub struct User {
 pub struct ApiKey {
     pub id: i64,
     pub user_id: String,
-    pub api_key: String,
+    #[sqlx(rename = "api_key")]
+    #[serde(skip)]
+    pub key_hash: String, // HMAC 哈希，明文只在创建或重置时返回一次
+    pub key_prefix: Option<String>, // 展示用前缀
     pub label: String,
     pub status: String,
+    pub rpm_limit: Option<i64>,
+    pub tpm_limit: Option<i64>,
+    pub spend_cap: Option<i64>, // 消费上限 (微单位)
+    pub spend_used: i64,
+    pub allowed_models: Option<String>,    // 逗号分隔的通配模式
+    pub allowed_endpoints: Option<String>, // 逗号分隔的接口范围
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub previous_key_hash: Option<String>,          // 轮换前的旧 Key 哈希
+    pub previous_expires_at: Option<DateTime<Utc>>, // 旧 Key 宽限期截止时间
     pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub client_cert_subject: Option<String>, // 绑定的客户端证书身份 (CN 或 SAN)
+    pub unix_uid: Option<i64>, // 绑定的 Unix 用户 (UDS 对端凭证)
+    pub unix_gid: Option<i64>, // 绑定的 Unix 组 (对端进程的主 gid)
     pub created_at: DateTime<Utc>,
 }
 
+/// API Key 的独立限制与访问范围 (均为 None 表示不限)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiKeySettings {
+    #[serde(default)]
+    pub rpm_limit: Option<i64>,
+    #[serde(default)]
+    pub tpm_limit: Option<i64>,
+    #[serde(default)]
+    pub spend_cap: Option<i64>,
+    #[serde(default)]
+    pub allowed_models: Option<String>,
+    #[serde(default)]
+    pub allowed_endpoints: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -38,6 +80,22 @@ pub struct ModelConfig {
     pub base_url: String,
     pub vendor_type: String,
     pub cost_per_1k_tokens: i64,
+    pub input_price_per_1k: i64,        // 微单位
+    pub output_price_per_1k: i64,       // 微单位
+    pub cached_input_price_per_1k: i64, // 微单位
+    pub model_group: Option<String>,
+    pub tpm_limit: Option<i64>, // 该模型全部调用方的每分钟 Token 总数
+    pub max_concurrency: Option<i64>,  // 上游最大并发请求数，None 表示不限
+    pub max_queue: Option<i64>,        // 排队队列长度上限
+    pub queue_timeout_ms: Option<i64>, // 排队超时 (毫秒)
+    pub breaker_window_secs: Option<i64>,    // 断路器滑动窗口 (秒)
+    pub breaker_min_requests: Option<i64>,   // 窗口内最少请求数
+    pub breaker_error_rate: Option<i64>,     // 失败率阈值 (百分比)
+    pub breaker_slow_call_ms: Option<i64>,   // 慢调用耗时阈值 (毫秒)，None 表示不统计
+    pub breaker_slow_call_rate: Option<i64>, // 慢调用率阈值 (百分比)
+    pub breaker_open_secs: Option<i64>,      // 首次熔断时长 (秒)
+    pub breaker_max_open_secs: Option<i64>,  // 连续熔断退避上限 (秒)
+    pub health_probe: Option<String>,        // 健康检查探测方式: None 为厂商默认，"off" 不检查，其余为 JSON 对话请求体
     pub request_script: Option<String>,
     pub response_script: Option<String>,
     pub is_active: bool,
@@ -58,3 +116,194 @@ pub struct UsageStat {
     pub stat_type: String,
     pub timestamp: DateTime<Utc>,
 }
+
+/// 计费账本条目 (只追加)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub user_id: String,
+    pub api_key_id: Option<i64>,
+    pub model_id: String,
+    pub input_tokens: i64,
+    pub cached_input_tokens: i64,
+    pub output_tokens: i64,
+    pub input_price_per_1k: i64,
+    pub cached_input_price_per_1k: i64,
+    pub output_price_per_1k: i64,
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 预付费余额账户
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CreditAccount {
+    pub user_id: String,
+    pub balance: i64,
+    pub low_balance_threshold: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 余额流水
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CreditTransaction {
+    pub id: i64,
+    pub user_id: String,
+    pub kind: String, // topup, usage, refund, expiration, adjustment
+    pub amount: i64,
+    pub balance_after: i64,
+    pub remaining: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ledger_id: Option<i64>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 周期配额策略
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuotaPolicy {
+    pub id: i64,
+    pub user_id: Option<String>,
+    pub scope_type: String, // all, model, group
+    pub scope_value: Option<String>,
+    pub period: String,      // daily, weekly, monthly
+    pub window_type: String, // calendar, rolling
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 自然周期窗口的用量计数
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuotaCounter {
+    pub policy_id: i64,
+    pub user_id: String,
+    pub window_start: DateTime<Utc>,
+    pub tokens_used: i64,
+    pub cost_used: i64,
+}
+
+/// 配额预占记录
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuotaReservation {
+    pub id: String,
+    pub user_id: String,
+    pub api_key_id: Option<i64>,
+    pub tokens: i64,
+    pub cost: i64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 用量报表的一行 (按分组汇总)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReportRow {
+    pub group: String,
+    pub requests: i64,
+    pub errors: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cost: i64, // 微单位
+    pub avg_latency_ms: i64,
+    // 分位数为直方图近似值: 取所在区间的上界，超过 30000ms 的溢出区间取窗口内的最大耗时
+    pub p50_latency_ms: i64,
+    pub p95_latency_ms: i64,
+    pub p99_latency_ms: i64,
+}
+
+/// 组织 (团队)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub token_budget: Option<i64>,
+    pub cost_budget: Option<i64>, // 微单位
+    pub token_used: i64,
+    pub cost_used: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 组织成员
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OrgMember {
+    pub org_id: String,
+    pub user_id: String,
+    pub role: String, // member, admin
+    pub token_limit: Option<i64>,
+    pub cost_limit: Option<i64>,
+    pub token_used: i64,
+    pub cost_used: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 用户的管理后台角色
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserRole {
+    pub user_id: String,
+    pub role: String, // viewer, billing, model-operator, security-admin, super-admin
+    pub created_at: DateTime<Utc>,
+}
+
+/// 管理后台会话
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AdminSession {
+    pub id: i64,
+    pub user_id: String,
+    #[serde(skip)]
+    pub token_hash: String, // 会话令牌的哈希，明文只在登录时返回一次
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// 管理操作审计记录 (只追加，按 id 顺序构成哈希链)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: String,
+    pub actor_name: String,
+    pub action: String,      // 如 user.update、model.delete、key.reset
+    pub target_type: String, // user, model, key, tool_policy, mcp ...
+    pub target_id: String,
+    #[serde(serialize_with = "serialize_json_text")]
+    pub changes: String, // JSON: {字段: {before, after}}，密钥类字段已脱敏
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// 持久化的断路器状态 (模型最近一次状态转换)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CircuitStateRecord {
+    pub model_id: String,
+    pub state: String, // closed, open, half_open
+    pub forced_open: bool,
+    pub consecutive_trips: i64,
+    pub open_until: Option<DateTime<Utc>>,
+    pub instance_id: String,
+    pub seq: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 主动健康检查样本
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HealthSample {
+    pub id: i64,
+    pub model_id: String,
+    pub healthy: bool,
+    pub latency_ms: i64,
+    pub error: Option<String>,
+    pub consecutive_failures: i64,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// 以 JSON 值输出数据库中保存的 JSON 文本
+fn serialize_json_text<S: serde::Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => value.serialize(serializer),
+        Err(_) => serializer.serialize_str(text),
+    }
+}
@@ -0,0 +1,42 @@
+use crate::connection::DbConnection;
+use chrono::Utc;
+use utils::Result;
+
+/// OIDC 外部身份仓库
+/// 实现逻辑: 以 (issuer, subject) 关联本地用户，不依赖 IdP 中可变的用户名或邮箱。
+pub struct OidcIdentityRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> OidcIdentityRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 查找外部身份关联的本地用户 ID
+    pub async fn find_user_id(&self, issuer: &str, subject: &str) -> Result<Option<String>> {
+        let user_id = sqlx::query_scalar("SELECT user_id FROM oidc_identities WHERE issuer = ? AND subject = ?")
+            .bind(issuer)
+            .bind(subject)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(user_id)
+    }
+
+    /// 关联外部身份，已关联时刷新邮箱与最近登录时间
+    pub async fn upsert(&self, issuer: &str, subject: &str, user_id: &str, email: Option<&str>) -> Result<()> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO oidc_identities (issuer, subject, user_id, email, created_at, last_login_at) VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(issuer, subject) DO UPDATE SET email = excluded.email, last_login_at = excluded.last_login_at"
+        )
+        .bind(issuer)
+        .bind(subject)
+        .bind(user_id)
+        .bind(email)
+        .bind(now)
+        .bind(now)
+        .execute(&self.db.pool).await?;
+        Ok(())
+    }
+}
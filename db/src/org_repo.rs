@@ -0,0 +1,124 @@
+use crate::models::{Organization, OrgMember};
+use crate::connection::DbConnection;
+use chrono::Utc;
+use sqlx::SqliteConnection;
+use utils::Result;
+
+/// 组织资源仓库
+/// 实现逻辑: 维护组织与成员关系；用量由计费事务同步累加到组织与成员 (见 `add_usage`)。
+pub struct OrgRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> OrgRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 创建组织
+    pub async fn create(&self, org_id: &str, name: &str, token_budget: Option<i64>, cost_budget: Option<i64>) -> Result<()> {
+        sqlx::query("INSERT INTO organizations (id, name, token_budget, cost_budget, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(org_id)
+            .bind(name)
+            .bind(token_budget)
+            .bind(cost_budget)
+            .bind(Utc::now())
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    /// 更新组织名称与预算
+    pub async fn update(&self, org_id: &str, name: &str, token_budget: Option<i64>, cost_budget: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE organizations SET name = ?, token_budget = ?, cost_budget = ? WHERE id = ?")
+            .bind(name)
+            .bind(token_budget)
+            .bind(cost_budget)
+            .bind(org_id)
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    /// 删除组织 (成员关系级联删除，用户保留)
+    pub async fn delete(&self, org_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM organizations WHERE id = ?")
+            .bind(org_id)
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_id(&self, org_id: &str) -> Result<Option<Organization>> {
+        let org = sqlx::query_as::<_, Organization>("SELECT * FROM organizations WHERE id = ?")
+            .bind(org_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(org)
+    }
+
+    pub async fn list_all(&self) -> Result<Vec<Organization>> {
+        let orgs = sqlx::query_as::<_, Organization>("SELECT * FROM organizations ORDER BY created_at")
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(orgs)
+    }
+
+    /// 添加或更新成员 (用户已属于其他组织时失败)
+    pub async fn upsert_member(&self, org_id: &str, user_id: &str, role: &str, token_limit: Option<i64>, cost_limit: Option<i64>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO org_members (org_id, user_id, role, token_limit, cost_limit, created_at) VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(org_id, user_id) DO UPDATE SET role = excluded.role, token_limit = excluded.token_limit, cost_limit = excluded.cost_limit"
+        )
+        .bind(org_id)
+        .bind(user_id)
+        .bind(role)
+        .bind(token_limit)
+        .bind(cost_limit)
+        .bind(Utc::now())
+        .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    pub async fn remove_member(&self, org_id: &str, user_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM org_members WHERE org_id = ? AND user_id = ?")
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&self.db.pool).await?;
+        Ok(())
+    }
+
+    pub async fn list_members(&self, org_id: &str) -> Result<Vec<OrgMember>> {
+        let members = sqlx::query_as::<_, OrgMember>("SELECT * FROM org_members WHERE org_id = ? ORDER BY created_at")
+            .bind(org_id)
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(members)
+    }
+
+    /// 查询用户所属组织的成员关系
+    pub async fn find_membership(&self, user_id: &str) -> Result<Option<OrgMember>> {
+        let member = sqlx::query_as::<_, OrgMember>("SELECT * FROM org_members WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(member)
+    }
+}
+
+/// 累加组织与成员用量 (供计费事务内部调用)。用户不属于任何组织时不做任何处理。
+pub(crate) async fn add_usage(conn: &mut SqliteConnection, user_id: &str, tokens: i64, cost: i64) -> Result<()> {
+    let org_id: Option<String> = sqlx::query_scalar(
+        "UPDATE org_members SET token_used = token_used + ?, cost_used = cost_used + ? WHERE user_id = ? RETURNING org_id"
+    )
+    .bind(tokens)
+    .bind(cost)
+    .bind(user_id)
+    .fetch_optional(&mut *conn).await?;
+
+    if let Some(org_id) = org_id {
+        sqlx::query("UPDATE organizations SET token_used = token_used + ?, cost_used = cost_used + ? WHERE id = ?")
+            .bind(tokens)
+            .bind(cost)
+            .bind(org_id)
+            .execute(&mut *conn).await?;
+    }
+    Ok(())
+}
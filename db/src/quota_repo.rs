@@ -0,0 +1,166 @@
+use crate::models::{QuotaPolicy, QuotaCounter};
+use crate::connection::DbConnection;
+use chrono::{DateTime, Utc};
+use utils::Result;
+
+/// 周期配额仓库
+/// 实现逻辑: 策略定义作用域 (全部/单模型/模型分组) 与周期；自然周期的用量记录在 `quota_counters` 中，
+/// 计数器的窗口起点落后于当前窗口时视为已重置，滚动窗口的用量直接由 `billing_ledger` 汇总。
+pub struct QuotaRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> QuotaRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 创建配额策略，返回策略 ID
+    pub async fn create_policy(&self, policy: &QuotaPolicy) -> Result<i64> {
+        let id = sqlx::query(
+            "INSERT INTO quota_policies (user_id, scope_type, scope_value, period, window_type, token_limit, cost_limit, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&policy.user_id)
+        .bind(&policy.scope_type)
+        .bind(&policy.scope_value)
+        .bind(&policy.period)
+        .bind(&policy.window_type)
+        .bind(policy.token_limit)
+        .bind(policy.cost_limit)
+        .bind(Utc::now())
+        .execute(&self.db.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    pub async fn find_policy(&self, id: i64) -> Result<Option<QuotaPolicy>> {
+        let policy = sqlx::query_as::<_, QuotaPolicy>("SELECT * FROM quota_policies WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(policy)
+    }
+
+    /// 删除配额策略 (计数器随外键级联删除)
+    pub async fn delete_policy(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM quota_policies WHERE id = ?")
+            .bind(id)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 列出策略 (指定用户时返回该用户的专属策略与全局策略)
+    pub async fn list_policies(&self, user_id: Option<&str>) -> Result<Vec<QuotaPolicy>> {
+        let policies = if let Some(uid) = user_id {
+            sqlx::query_as::<_, QuotaPolicy>("SELECT * FROM quota_policies WHERE user_id = ? OR user_id IS NULL ORDER BY id")
+                .bind(uid)
+                .fetch_all(&self.db.pool)
+                .await?
+        } else {
+            sqlx::query_as::<_, QuotaPolicy>("SELECT * FROM quota_policies ORDER BY id")
+                .fetch_all(&self.db.pool)
+                .await?
+        };
+        Ok(policies)
+    }
+
+    /// 查询对某用户调用某模型生效的策略
+    /// 同一作用域与周期下，用户专属策略覆盖全局策略。
+    pub async fn applicable_policies(&self, user_id: &str, model_id: &str, model_group: Option<&str>) -> Result<Vec<QuotaPolicy>> {
+        let candidates = sqlx::query_as::<_, QuotaPolicy>(
+            "SELECT * FROM quota_policies
+             WHERE (user_id = ? OR user_id IS NULL)
+               AND (scope_type = 'all'
+                    OR (scope_type = 'model' AND scope_value = ?)
+                    OR (scope_type = 'group' AND scope_value = ?))
+             ORDER BY user_id IS NULL, id"
+        )
+        .bind(user_id)
+        .bind(model_id)
+        .bind(model_group)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let mut policies: Vec<QuotaPolicy> = Vec::new();
+        for policy in candidates {
+            let overridden = policy.user_id.is_none() && policies.iter().any(|p| {
+                p.user_id.is_some()
+                    && p.scope_type == policy.scope_type
+                    && p.scope_value == policy.scope_value
+                    && p.period == policy.period
+            });
+            if !overridden {
+                policies.push(policy);
+            }
+        }
+        Ok(policies)
+    }
+
+    /// 获取自然周期计数器
+    pub async fn get_counter(&self, policy_id: i64, user_id: &str) -> Result<Option<QuotaCounter>> {
+        let counter = sqlx::query_as::<_, QuotaCounter>("SELECT * FROM quota_counters WHERE policy_id = ? AND user_id = ?")
+            .bind(policy_id)
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(counter)
+    }
+
+    /// 累加自然周期用量；计数器属于旧窗口时先清零再累加
+    pub async fn add_usage(&self, policy_id: i64, user_id: &str, window_start: DateTime<Utc>, tokens: i64, cost: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO quota_counters (policy_id, user_id, window_start, tokens_used, cost_used) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(policy_id, user_id) DO UPDATE SET
+                tokens_used = CASE WHEN window_start < excluded.window_start THEN excluded.tokens_used ELSE tokens_used + excluded.tokens_used END,
+                cost_used = CASE WHEN window_start < excluded.window_start THEN excluded.cost_used ELSE cost_used + excluded.cost_used END,
+                window_start = MAX(window_start, excluded.window_start)"
+        )
+        .bind(policy_id)
+        .bind(user_id)
+        .bind(window_start)
+        .bind(tokens)
+        .bind(cost)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 将指定周期下窗口已过期的计数器滚动到新窗口，返回重置的计数器数量
+    pub async fn roll_over(&self, period: &str, window_start: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE quota_counters SET window_start = ?, tokens_used = 0, cost_used = 0
+             WHERE window_start < ?
+               AND policy_id IN (SELECT id FROM quota_policies WHERE period = ? AND window_type = 'calendar')"
+        )
+        .bind(window_start)
+        .bind(window_start)
+        .bind(period)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// 汇总滚动窗口内的用量 (Token 数, 金额)，按策略作用域过滤账本
+    pub async fn ledger_usage_since(&self, policy: &QuotaPolicy, user_id: &str, since: DateTime<Utc>) -> Result<(i64, i64)> {
+        let usage: (i64, i64) = sqlx::query_as(
+            "SELECT COALESCE(SUM(input_tokens + output_tokens), 0), COALESCE(SUM(amount), 0) FROM billing_ledger
+             WHERE user_id = ? AND created_at >= ?
+               AND (? = 'all'
+                    OR (? = 'model' AND model_id = ?)
+                    OR (? = 'group' AND model_id IN (SELECT id FROM model_configs WHERE model_group = ?)))"
+        )
+        .bind(user_id)
+        .bind(since)
+        .bind(&policy.scope_type)
+        .bind(&policy.scope_type)
+        .bind(&policy.scope_value)
+        .bind(&policy.scope_type)
+        .bind(&policy.scope_value)
+        .fetch_one(&self.db.pool)
+        .await?;
+        Ok(usage)
+    }
+}
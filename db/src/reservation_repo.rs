@@ -0,0 +1,58 @@
+use crate::models::QuotaReservation;
+use crate::connection::DbConnection;
+use chrono::{DateTime, Utc};
+use utils::Result;
+
+/// 配额预占仓库
+/// 实现逻辑: 预占记录只用于进程重启后恢复未结算的预占，结算或过期时删除。
+pub struct ReservationRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> ReservationRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn insert(&self, reservation: &QuotaReservation) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO quota_reservations (id, user_id, api_key_id, tokens, cost, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&reservation.id)
+        .bind(&reservation.user_id)
+        .bind(reservation.api_key_id)
+        .bind(reservation.tokens)
+        .bind(reservation.cost)
+        .bind(reservation.created_at)
+        .bind(reservation.expires_at)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM quota_reservations WHERE id = ?")
+            .bind(id)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 查询所有尚未过期的预占 (用于进程启动后恢复)
+    pub async fn list_all_active(&self, now: DateTime<Utc>) -> Result<Vec<QuotaReservation>> {
+        let reservations = sqlx::query_as::<_, QuotaReservation>("SELECT * FROM quota_reservations WHERE expires_at > ?")
+            .bind(now)
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(reservations)
+    }
+
+    /// 删除所有已过期的预占，返回删除数量
+    pub async fn delete_expired(&self, now: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM quota_reservations WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::models::UserRole;
+use crate::connection::DbConnection;
+use crate::events::ChangeEvent;
+use utils::Result;
+
+/// 超级管理员角色 (与 `users.is_admin` 标记保持同步)
+pub const SUPER_ADMIN_ROLE: &str = "super-admin";
+
+/// 用户角色仓库
+/// 实现逻辑: 维护 `user_roles` 表；角色整体替换，超级管理员角色的增减同步到旧的 `is_admin` 标记。
+pub struct RoleRepo<'a> {
+    pub db: &'a DbConnection,
+}
+
+impl<'a> RoleRepo<'a> {
+    pub fn new(db: &'a DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// 获取用户的全部角色
+    pub async fn list_for_user(&self, user_id: &str) -> Result<Vec<String>> {
+        let roles = sqlx::query_scalar("SELECT role FROM user_roles WHERE user_id = ? ORDER BY role")
+            .bind(user_id)
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(roles)
+    }
+
+    /// 获取所有用户的角色
+    pub async fn list_all(&self) -> Result<Vec<UserRole>> {
+        let roles = sqlx::query_as::<_, UserRole>("SELECT * FROM user_roles ORDER BY user_id, role")
+            .fetch_all(&self.db.pool)
+            .await?;
+        Ok(roles)
+    }
+
+    /// 整体替换用户的角色
+    pub async fn set_roles(&self, user_id: &str, roles: &[&str]) -> Result<()> {
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query("DELETE FROM user_roles WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx).await?;
+        for role in roles {
+            sqlx::query("INSERT OR IGNORE INTO user_roles (user_id, role, created_at) VALUES (?, ?, ?)")
+                .bind(user_id)
+                .bind(role)
+                .bind(chrono::Utc::now())
+                .execute(&mut *tx).await?;
+        }
+        sqlx::query("UPDATE users SET is_admin = ? WHERE id = ?")
+            .bind(roles.contains(&SUPER_ADMIN_ROLE))
+            .bind(user_id)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
+        Ok(())
+    }
+}
@@ -1,7 +1,76 @@
-use crate::models::UsageStat;
+use crate::models::{UsageStat, UsageReportRow};
 use crate::connection::DbConnection;
+use chrono::{DateTime, Timelike, Utc};
+use sqlx::Row;
 use utils::Result;
 
+/// 耗时直方图区间上界 (毫秒)，最后一列统计超过最大上界的请求
+pub const LATENCY_BUCKETS_MS: [i64; 8] = [100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+const LATENCY_COLUMNS: [&str; 9] = [
+    "latency_le_100", "latency_le_250", "latency_le_500", "latency_le_1000", "latency_le_2500",
+    "latency_le_5000", "latency_le_10000", "latency_le_30000", "latency_gt_30000",
+];
+
+/// 报表分组维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportGroupBy {
+    User,
+    ApiKey,
+    Model,
+    Org,
+    Day,
+    Hour,
+}
+
+impl ReportGroupBy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "user" => Some(Self::User),
+            "key" | "api_key" => Some(Self::ApiKey),
+            "model" => Some(Self::Model),
+            "org" => Some(Self::Org),
+            "day" => Some(Self::Day),
+            "hour" => Some(Self::Hour),
+            _ => None,
+        }
+    }
+
+    fn key_expr(&self) -> &'static str {
+        match self {
+            Self::User => "user_id",
+            Self::ApiKey => "CAST(api_key_id AS TEXT)",
+            Self::Model => "model_id",
+            // 按用户当前所属组织归集，不属于任何组织的用户归入空分组
+            Self::Org => "COALESCE((SELECT m.org_id FROM org_members m WHERE m.user_id = usage_rollups_hourly.user_id), '')",
+            Self::Day => "substr(bucket, 1, 10)",
+            Self::Hour => "bucket",
+        }
+    }
+}
+
+/// 将时间截断到所在小时的起点
+fn hour_bucket(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.with_minute(0).and_then(|t| t.with_second(0)).and_then(|t| t.with_nanosecond(0)).unwrap_or(at)
+}
+
+/// 按累计分布取分位数所在区间的上界 (直方图近似值)
+/// 落入溢出区间时没有上界，取窗口内记录的最大耗时 (历史数据缺失时不低于最大区间上界)
+fn percentile(histogram: &[i64], total: i64, p: f64, max_latency: i64) -> i64 {
+    if total == 0 {
+        return 0;
+    }
+    let overflow = max_latency.max(LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]);
+    let target = (total as f64 * p).ceil() as i64;
+    let mut seen = 0;
+    for (i, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= target {
+            return LATENCY_BUCKETS_MS.get(i).copied().unwrap_or(overflow);
+        }
+    }
+    overflow
+}
+
 /// 使用统计资源仓库
 pub struct StatsRepo<'a> {
     pub db: &'a DbConnection,
@@ -58,4 +127,125 @@ impl<'a> StatsRepo<'a> {
             .await?;
         Ok(stats)
     }
+
+    /// 累加一次请求的汇总 (请求数、错误数、耗时)
+    pub async fn record_request_rollup(&self, at: DateTime<Utc>, user_id: &str, api_key_id: Option<i64>, model_id: &str, is_error: bool, latency_ms: i64) -> Result<()> {
+        let idx = LATENCY_BUCKETS_MS.iter().position(|b| latency_ms <= *b).unwrap_or(LATENCY_BUCKETS_MS.len());
+        let column = LATENCY_COLUMNS[idx];
+        let sql = format!(
+            "INSERT INTO usage_rollups_hourly (bucket, user_id, api_key_id, model_id, requests, errors, latency_ms_total, latency_ms_max, {column})
+             VALUES (?, ?, ?, ?, 1, ?, ?, ?, 1)
+             ON CONFLICT(bucket, user_id, api_key_id, model_id) DO UPDATE SET
+                requests = requests + 1,
+                errors = errors + excluded.errors,
+                latency_ms_total = latency_ms_total + excluded.latency_ms_total,
+                latency_ms_max = MAX(latency_ms_max, excluded.latency_ms_max),
+                {column} = {column} + 1"
+        );
+        sqlx::query(&sql)
+            .bind(hour_bucket(at))
+            .bind(user_id)
+            .bind(api_key_id.unwrap_or(0))
+            .bind(model_id)
+            .bind(is_error as i64)
+            .bind(latency_ms)
+            .bind(latency_ms)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 累加一次计费的汇总 (Token 与金额)
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record_usage_rollup(&self, at: DateTime<Utc>, user_id: &str, api_key_id: Option<i64>, model_id: &str, input_tokens: i64, output_tokens: i64, cost: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO usage_rollups_hourly (bucket, user_id, api_key_id, model_id, input_tokens, output_tokens, cost)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(bucket, user_id, api_key_id, model_id) DO UPDATE SET
+                input_tokens = input_tokens + excluded.input_tokens,
+                output_tokens = output_tokens + excluded.output_tokens,
+                cost = cost + excluded.cost"
+        )
+        .bind(hour_bucket(at))
+        .bind(user_id)
+        .bind(api_key_id.unwrap_or(0))
+        .bind(model_id)
+        .bind(input_tokens)
+        .bind(output_tokens)
+        .bind(cost)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 按维度汇总用量报表 (时间范围为 [from, to)，可限定为某组织的成员)，按分组键排序
+    pub async fn usage_report(&self, group_by: ReportGroupBy, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, org_id: Option<&str>) -> Result<Vec<UsageReportRow>> {
+        self.aggregate(group_by.key_expr(), from, to, org_id).await
+    }
+
+    /// 时间范围内的总计
+    pub async fn usage_total(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, org_id: Option<&str>) -> Result<UsageReportRow> {
+        let mut rows = self.aggregate("'total'", from, to, org_id).await?;
+        Ok(rows.pop().unwrap_or(UsageReportRow {
+            group: "total".to_string(),
+            requests: 0,
+            errors: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost: 0,
+            avg_latency_ms: 0,
+            p50_latency_ms: 0,
+            p95_latency_ms: 0,
+            p99_latency_ms: 0,
+        }))
+    }
+
+    async fn aggregate(&self, key_expr: &str, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, org_id: Option<&str>) -> Result<Vec<UsageReportRow>> {
+        let histogram_sums = LATENCY_COLUMNS.iter()
+            .map(|c| format!("SUM({c}) AS {c}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "SELECT {key_expr} AS grp, SUM(requests) AS requests, SUM(errors) AS errors,
+                SUM(input_tokens) AS input_tokens, SUM(output_tokens) AS output_tokens, SUM(cost) AS cost,
+                SUM(latency_ms_total) AS latency_ms_total, MAX(latency_ms_max) AS latency_ms_max, {histogram_sums}
+             FROM usage_rollups_hourly
+             WHERE (? IS NULL OR bucket >= ?) AND (? IS NULL OR bucket < ?)
+               AND (? IS NULL OR user_id IN (SELECT user_id FROM org_members WHERE org_id = ?))
+             GROUP BY grp ORDER BY grp"
+        );
+        let from = from.map(hour_bucket);
+        let rows = sqlx::query(&sql)
+            .bind(from)
+            .bind(from)
+            .bind(to)
+            .bind(to)
+            .bind(org_id)
+            .bind(org_id)
+            .fetch_all(&self.db.pool)
+            .await?;
+
+        let mut report = Vec::with_capacity(rows.len());
+        for row in rows {
+            let histogram = LATENCY_COLUMNS.iter()
+                .map(|c| row.try_get::<i64, _>(*c))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let timed: i64 = histogram.iter().sum();
+            let latency_total: i64 = row.try_get("latency_ms_total")?;
+            let latency_max: i64 = row.try_get("latency_ms_max")?;
+            report.push(UsageReportRow {
+                group: row.try_get("grp")?,
+                requests: row.try_get("requests")?,
+                errors: row.try_get("errors")?,
+                input_tokens: row.try_get("input_tokens")?,
+                output_tokens: row.try_get("output_tokens")?,
+                cost: row.try_get("cost")?,
+                avg_latency_ms: if timed > 0 { latency_total / timed } else { 0 },
+                p50_latency_ms: percentile(&histogram, timed, 0.50, latency_max),
+                p95_latency_ms: percentile(&histogram, timed, 0.95, latency_max),
+                p99_latency_ms: percentile(&histogram, timed, 0.99, latency_max),
+            });
+        }
+        Ok(report)
+    }
 }
@@ -46,15 +46,26 @@ impl<'a> ToolPolicyRepo<'a> {
         Ok(policy)
     }
 
+    /// 设置策略 (同一工具与用户只保留一条)
+    /// 表上没有 (tool_name, user_id) 唯一约束，且全局策略的 user_id 为 NULL，因此先按 `IS` (NULL 安全比较) 更新，未命中再插入。
     pub async fn upsert_policy(&self, tool_name: &str, user_id: Option<&str>, policy: &str) -> anyhow::Result<()> {
-        sqlx::query(
-            "INSERT INTO tool_policies (tool_name, user_id, policy) VALUES (?, ?, ?)
-             ON CONFLICT(tool_name, user_id) DO UPDATE SET policy = EXCLUDED.policy, updated_at = CURRENT_TIMESTAMP"
+        let mut tx = self.db.begin().await?;
+        let updated = sqlx::query(
+            "UPDATE tool_policies SET policy = ?, updated_at = CURRENT_TIMESTAMP WHERE tool_name = ? AND user_id IS ?"
         )
+        .bind(policy)
         .bind(tool_name)
         .bind(user_id)
-        .bind(policy)
-        .execute(self.db).await?;
+        .execute(&mut *tx).await?
+        .rows_affected();
+        if updated == 0 {
+            sqlx::query("INSERT INTO tool_policies (tool_name, user_id, policy) VALUES (?, ?, ?)")
+                .bind(tool_name)
+                .bind(user_id)
+                .bind(policy)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 }
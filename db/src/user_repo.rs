@@ -1,6 +1,7 @@
 use crate::models::User;
 use crate::connection::DbConnection;
-use utils::Result;
+use crate::events::ChangeEvent;
+use utils::{Crypto, Result};
 
 /// 用户资源仓库
 /// 实现逻辑: 提供对 `users` 表的增删改查操作。
@@ -13,46 +14,47 @@ impl<'a> UserRepo<'a> {
         Self { db }
     }
 
-    /// 根据 API Key 获取用户 (多 Key 支持)
+    /// 根据明文 API Key 获取用户 (多 Key 支持，按哈希查找，轮换宽限期内的旧 Key 同样有效)
     pub async fn find_by_api_key(&self, api_key: &str) -> Result<Option<User>> {
+        let key_hash = Crypto::hash_api_key(api_key);
         let user = sqlx::query_as::<_, User>(
             "SELECT u.* FROM users u 
              JOIN api_keys ak ON u.id = ak.user_id 
-             WHERE ak.api_key = ? AND ak.status = 'Active' AND u.status = 'Active'"
+             WHERE (ak.api_key = ? OR (ak.previous_key_hash = ? AND ak.previous_expires_at > ?))
+               AND ak.status = 'Active' AND u.status = 'Active'"
         )
-        .bind(api_key)
+        .bind(&key_hash)
+        .bind(&key_hash)
+        .bind(chrono::Utc::now())
         .fetch_optional(&self.db.pool)
         .await?;
-        
-        if user.is_none() {
-            tracing::debug!("未在 api_keys 表中找到有效的 Key: {}", api_key);
-        }
-        
         Ok(user)
     }
 
-    /// 创建用户
+    /// 创建用户 (Default Key 只保存哈希)
     pub async fn create(&self, user_id: &str, username: &str, api_key: &str, is_admin: bool) -> Result<()> {
         let mut tx = self.db.pool.begin().await?;
+        insert_user(&mut tx, user_id, username, api_key, is_admin).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        // 1. 插入用户表
-        sqlx::query(
-            "INSERT INTO users (id, username, api_key, status, is_admin, rpm_limit, token_quota, token_used) VALUES (?, ?, ?, 'Active', ?, 60, 1000000, 0)"
-        )
-        .bind(user_id)
-        .bind(username)
-        .bind(api_key)
-        .bind(is_admin)
-        .execute(&mut *tx).await?;
-
-        // 2. 同步插入 api_keys 表
+    /// 创建用户并加入组织
+    /// 实现逻辑: 用户、Default Key 与成员关系在同一事务内写入，任一步失败 (如角色不合法) 整体回滚，不留下孤立用户。
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_org_member(&self, user_id: &str, username: &str, api_key: &str, org_id: &str, role: &str, token_limit: Option<i64>, cost_limit: Option<i64>) -> Result<()> {
+        let mut tx = self.db.pool.begin().await?;
+        insert_user(&mut tx, user_id, username, api_key, false).await?;
         sqlx::query(
-            "INSERT INTO api_keys (user_id, api_key, label, status) VALUES (?, ?, 'Default', 'Active')"
+            "INSERT INTO org_members (org_id, user_id, role, token_limit, cost_limit, created_at) VALUES (?, ?, ?, ?, ?, ?)"
         )
+        .bind(org_id)
         .bind(user_id)
-        .bind(api_key)
+        .bind(role)
+        .bind(token_limit)
+        .bind(cost_limit)
+        .bind(chrono::Utc::now())
         .execute(&mut *tx).await?;
-
         tx.commit().await?;
         Ok(())
     }
@@ -76,15 +78,85 @@ impl<'a> UserRepo<'a> {
     }
 
 
-    /// 设置管理员状态
+    /// 设置管理员状态 (同步授予或撤销超级管理员角色)
     pub async fn set_admin(&self, user_id: &str, is_admin: bool) -> Result<()> {
+        let mut tx = self.db.pool.begin().await?;
         sqlx::query("UPDATE users SET is_admin = ? WHERE id = ?")
             .bind(is_admin)
             .bind(user_id)
+            .execute(&mut *tx).await?;
+        let sql = if is_admin {
+            "INSERT OR IGNORE INTO user_roles (user_id, role) VALUES (?, ?)"
+        } else {
+            "DELETE FROM user_roles WHERE user_id = ? AND role = ?"
+        };
+        sqlx::query(sql)
+            .bind(user_id)
+            .bind(crate::role_repo::SUPER_ADMIN_ROLE)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
+        Ok(())
+    }
+
+    /// 根据用户名获取用户
+    pub async fn find_by_username(&self, username: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(user)
+    }
+
+    /// 获取管理员密码哈希 (未设置密码时为 None)
+    pub async fn password_hash(&self, user_id: &str) -> Result<Option<String>> {
+        let hash: Option<Option<String>> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(hash.flatten())
+    }
+
+    /// 设置或清除管理员密码哈希
+    pub async fn set_password_hash(&self, user_id: &str, password_hash: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(password_hash)
+            .bind(user_id)
+            .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
+        Ok(())
+    }
+
+    /// 获取两步验证密钥 (Base32)
+    pub async fn totp_secret(&self, user_id: &str) -> Result<Option<String>> {
+        let secret: Option<Option<String>> = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(secret.flatten())
+    }
+
+    /// 设置两步验证密钥与启用状态 (密钥为 None 时清除)，同时清除已接受的时间步
+    pub async fn set_totp(&self, user_id: &str, secret: Option<&str>, enabled: bool) -> Result<()> {
+        sqlx::query("UPDATE users SET totp_secret = ?, totp_enabled = ?, totp_last_step = NULL WHERE id = ?")
+            .bind(secret)
+            .bind(enabled)
+            .bind(user_id)
             .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
         Ok(())
     }
 
+    /// 记录已接受的两步验证时间步；时间步不大于上次接受的 (验证码被重放) 时返回 false
+    pub async fn accept_totp_step(&self, user_id: &str, step: i64) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)")
+            .bind(step)
+            .bind(user_id)
+            .bind(step)
+            .execute(&self.db.pool).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
     /// 获取所有用户
     pub async fn list_all(&self) -> Result<Vec<User>> {
         let users = sqlx::query_as::<_, User>("SELECT * FROM users")
@@ -92,40 +164,78 @@ impl<'a> UserRepo<'a> {
         Ok(users)
     }
 
-    /// 更新用户配额
-    pub async fn update_quota(&self, user_id: &str, rpm_limit: i64, token_quota: i64) -> Result<()> {
-        sqlx::query("UPDATE users SET rpm_limit = ?, token_quota = ? WHERE id = ?")
+    /// 根据 ID 获取用户
+    pub async fn find_by_id(&self, user_id: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(user)
+    }
+
+    /// 更新用户配额 (cost_quota 为 None 表示不限金额)
+    pub async fn update_quota(&self, user_id: &str, rpm_limit: i64, token_quota: i64, cost_quota: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE users SET rpm_limit = ?, token_quota = ?, cost_quota = ? WHERE id = ?")
             .bind(rpm_limit)
             .bind(token_quota)
+            .bind(cost_quota)
             .bind(user_id)
             .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
         Ok(())
     }
 
-    /// 更新用户信息 (用户名、API Key、状态)
-    pub async fn update_info(&self, user_id: &str, username: &str, api_key: &str, status: &str) -> Result<()> {
+    /// 更新用户每分钟 Token 数限制 (None 表示不限)
+    pub async fn update_tpm_limit(&self, user_id: &str, tpm_limit: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE users SET tpm_limit = ? WHERE id = ?")
+            .bind(tpm_limit)
+            .bind(user_id)
+            .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
+        Ok(())
+    }
+
+    /// 更新用户排队优先级
+    pub async fn update_priority(&self, user_id: &str, priority: i64) -> Result<()> {
+        sqlx::query("UPDATE users SET priority = ? WHERE id = ?")
+            .bind(priority)
+            .bind(user_id)
+            .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
+        Ok(())
+    }
+
+    /// 更新用户信息 (用户名、状态；传入新的 API Key 时同时替换 Default Key)
+    pub async fn update_info(&self, user_id: &str, username: &str, api_key: Option<&str>, status: &str) -> Result<()> {
         let mut tx = self.db.pool.begin().await?;
 
         // 1. 更新用户表
-        sqlx::query("UPDATE users SET username = ?, api_key = ?, status = ? WHERE id = ?")
+        sqlx::query("UPDATE users SET username = ?, status = ? WHERE id = ?")
             .bind(username)
-            .bind(api_key)
             .bind(status)
             .bind(user_id)
             .execute(&mut *tx).await?;
 
-        // 2. 同步更新/重置 api_keys 表中的 Default Key (如果存在)
-        // 注意：这里简单处理，如果 api_key 变化了，且它是 "Default" 标签，则更新它。
-        // 或者直接尝试 upsert 一个 Default key。
-        sqlx::query(
-            "INSERT INTO api_keys (user_id, api_key, label, status) VALUES (?, ?, 'Default', 'Active')
-             ON CONFLICT(user_id, label) DO UPDATE SET api_key = excluded.api_key, status = excluded.status"
-        )
-        .bind(user_id)
-        .bind(api_key)
-        .execute(&mut *tx).await?;
+        // 2. 同步更新/重置 api_keys 表中的 Default Key (不存在时创建)
+        if let Some(api_key) = api_key {
+            let (key_hash, key_prefix) = (Crypto::hash_api_key(api_key), Crypto::api_key_prefix(api_key));
+            sqlx::query("UPDATE users SET api_key = ?, key_prefix = ? WHERE id = ?")
+                .bind(&key_hash)
+                .bind(&key_prefix)
+                .bind(user_id)
+                .execute(&mut *tx).await?;
+            sqlx::query(
+                "INSERT INTO api_keys (user_id, api_key, key_prefix, label, status) VALUES (?, ?, ?, 'Default', 'Active')
+                 ON CONFLICT(user_id, label) DO UPDATE SET api_key = excluded.api_key, key_prefix = excluded.key_prefix, status = excluded.status"
+            )
+            .bind(user_id)
+            .bind(&key_hash)
+            .bind(&key_prefix)
+            .execute(&mut *tx).await?;
+        }
 
         tx.commit().await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
         Ok(())
     }
 
@@ -134,6 +244,7 @@ impl<'a> UserRepo<'a> {
         sqlx::query("DELETE FROM users WHERE id = ?")
             .bind(user_id)
             .execute(&self.db.pool).await?;
+        self.db.changes.publish(ChangeEvent::User(user_id.to_string()));
         Ok(())
     }
 
@@ -147,3 +258,37 @@ impl<'a> UserRepo<'a> {
         Ok(())
     }
 }
+
+/// 在事务内插入用户与 Default Key，管理员同时授予超级管理员角色
+async fn insert_user(tx: &mut sqlx::SqliteConnection, user_id: &str, username: &str, api_key: &str, is_admin: bool) -> Result<()> {
+    let (key_hash, key_prefix) = (Crypto::hash_api_key(api_key), Crypto::api_key_prefix(api_key));
+
+    // 1. 插入用户表
+    sqlx::query(
+        "INSERT INTO users (id, username, api_key, key_prefix, status, is_admin, rpm_limit, token_quota, token_used) VALUES (?, ?, ?, ?, 'Active', ?, 60, 1000000, 0)"
+    )
+    .bind(user_id)
+    .bind(username)
+    .bind(&key_hash)
+    .bind(&key_prefix)
+    .bind(is_admin)
+    .execute(&mut *tx).await?;
+
+    // 2. 同步插入 api_keys 表
+    sqlx::query(
+        "INSERT INTO api_keys (user_id, api_key, key_prefix, label, status) VALUES (?, ?, ?, 'Default', 'Active')"
+    )
+    .bind(user_id)
+    .bind(&key_hash)
+    .bind(&key_prefix)
+    .execute(&mut *tx).await?;
+
+    // 3. 管理员授予超级管理员角色
+    if is_admin {
+        sqlx::query("INSERT INTO user_roles (user_id, role) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(crate::role_repo::SUPER_ADMIN_ROLE)
+            .execute(&mut *tx).await?;
+    }
+    Ok(())
+}
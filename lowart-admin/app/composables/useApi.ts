@@ -2,31 +2,58 @@ export const useApi = () => {
     const config = useRuntimeConfig()
     const authStore = useAuthStore()
     const baseUrl = 'http://localhost:8080'
-    const adminKey = computed(() => authStore.adminKey)
+    const sessionToken = computed(() => authStore.sessionToken)
 
-    const getAuthHeaders = () => ({
-        'Authorization': `Bearer ${adminKey.value}`,
+    // 管理接口使用会话令牌；调用 /v1 接口 (如聊天测试) 需显式传入 API Key
+    const getAuthHeaders = (apiKey?: string) => ({
+        'Authorization': `Bearer ${apiKey || sessionToken.value}`,
         'Content-Type': 'application/json'
     })
 
     const fetchWithAuth = async (url: string, options: any = {}) => {
-        return await $fetch(url, {
-            baseURL: baseUrl,
-            ...options,
-            headers: {
-                ...getAuthHeaders(),
-                ...options.headers,
+        try {
+            return await $fetch(url, {
+                baseURL: baseUrl,
+                ...options,
+                headers: {
+                    ...getAuthHeaders(),
+                    ...options.headers,
+                }
+            })
+        } catch (e: any) {
+            // 会话过期或已被撤销: 回到登录页
+            if (e.status === 401 && e.data?.error?.code === 'invalid_session') {
+                authStore.clearAuth()
+                navigateTo('/login')
             }
-        })
+            throw e
+        }
     }
 
     return {
         baseUrl,
         getAuthHeaders,
         // Auth API
-        login: (api_key: string) => $fetch(`${baseUrl}/admin/login`, {
+        login: (payload: { username?: string, password?: string, totp_code?: string, api_key?: string }) => $fetch(`${baseUrl}/admin/login`, {
+            method: 'POST',
+            body: payload
+        }),
+        // 单点登录: 浏览器整页跳转到该地址，IdP 回调后带着会话令牌回到登录页
+        ssoLoginUrl: `${baseUrl}/admin/oidc/login`,
+        getAccount: () => fetchWithAuth('/admin/account'),
+        logout: () => fetchWithAuth('/admin/logout', { method: 'POST' }),
+        changePassword: (payload: { current_password?: string, new_password: string }) => fetchWithAuth('/admin/account/password', {
+            method: 'POST',
+            body: payload
+        }),
+        setupTotp: () => fetchWithAuth('/admin/account/totp/setup', { method: 'POST' }),
+        enableTotp: (code: string) => fetchWithAuth('/admin/account/totp/enable', {
             method: 'POST',
-            body: { api_key }
+            body: { code }
+        }),
+        disableTotp: (code: string) => fetchWithAuth('/admin/account/totp/disable', {
+            method: 'POST',
+            body: { code }
         }),
 
         // User APIs
@@ -35,7 +62,7 @@ export const useApi = () => {
             method: 'POST',
             body: payload
         }),
-        updateUser: (payload: { user_id: string, username: string, api_key: string, status: string }) => fetchWithAuth('/admin/users', {
+        updateUser: (payload: { user_id: string, username: string, api_key?: string, status: string }) => fetchWithAuth('/admin/users', {
             method: 'PUT',
             body: payload
         }),
@@ -48,6 +75,18 @@ export const useApi = () => {
             body: payload
         }),
 
+        // Role APIs
+        getUserRoles: (user_id: string) => fetchWithAuth(`/admin/users/${user_id}/roles`),
+        setUserRoles: (user_id: string, roles: string[]) => fetchWithAuth('/admin/users/roles', {
+            method: 'POST',
+            body: { user_id, roles }
+        }),
+
+        setUserPassword: (payload: { user_id: string, password?: string, reset_totp?: boolean }) => fetchWithAuth('/admin/users/password', {
+            method: 'POST',
+            body: payload
+        }),
+
         // API Key APIs
         getUserKeys: (user_id: string) => fetchWithAuth(`/admin/users/${user_id}/keys`),
         createKey: (payload: { user_id: string, label: string }) => fetchWithAuth('/admin/keys', {
@@ -58,13 +97,43 @@ export const useApi = () => {
             method: 'POST',
             body: { key_id }
         }),
+        rotateKey: (key_id: number, grace_seconds?: number) => fetchWithAuth('/admin/keys/rotate', {
+            method: 'POST',
+            body: { key_id, grace_seconds }
+        }),
+        getStaleKeys: (days: number) => fetchWithAuth(`/admin/keys/stale?days=${days}`),
         deleteKey: (key_id: number) => fetchWithAuth('/admin/keys/delete', {
             method: 'POST',
             body: { key_id }
         }),
+        setKeyClientCert: (key_id: number, subject: string | null) => fetchWithAuth('/admin/keys/client-cert', {
+            method: 'POST',
+            body: { key_id, subject }
+        }),
+        setKeyUnixPeer: (key_id: number, uid: number | null, gid: number | null) => fetchWithAuth('/admin/keys/unix-peer', {
+            method: 'POST',
+            body: { key_id, uid, gid }
+        }),
 
         // Model APIs
         getModels: () => fetchWithAuth('/admin/models'),
+        getModelHealth: () => fetchWithAuth('/admin/models/health'),
+        getModelHealthHistory: (model_id: string, limit = 20) => fetchWithAuth('/admin/models/health/history', {
+            query: { model_id, limit }
+        }),
+        getCircuits: () => fetchWithAuth('/admin/circuits'),
+        forceOpenCircuit: (model_id: string) => fetchWithAuth('/admin/circuits/open', {
+            method: 'POST',
+            body: { model_id }
+        }),
+        forceCloseCircuit: (model_id: string) => fetchWithAuth('/admin/circuits/close', {
+            method: 'POST',
+            body: { model_id }
+        }),
+        resetCircuit: (model_id: string) => fetchWithAuth('/admin/circuits/reset', {
+            method: 'POST',
+            body: { model_id }
+        }),
         createModel: (payload: { title: string, model_id: string, api_key: string, base_url: string, vendor_type: string, cost_per_1k_tokens: number, is_active: boolean }) => fetchWithAuth('/admin/models', {
             method: 'POST',
             body: payload
@@ -89,11 +158,17 @@ export const useApi = () => {
         // Stats APIs
         getStats: () => fetchWithAuth('/admin/stats'),
 
+        // Audit APIs
+        getAuditLog: (query: { actor_id?: string, action?: string, target_type?: string, target_id?: string, before_id?: number, limit?: number } = {}) =>
+            fetchWithAuth('/admin/audit', { query }),
+        verifyAuditLog: () => fetchWithAuth('/admin/audit/verify'),
+
         // Chat APIs
-        chat: (payload: any) => {
+        chat: (payload: any, apiKey: string) => {
             return fetchWithAuth('/v1/chat/completions', {
                 method: 'POST',
-                body: payload
+                body: payload,
+                headers: getAuthHeaders(apiKey)
             })
         }
     }
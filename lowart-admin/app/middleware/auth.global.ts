@@ -6,13 +6,14 @@ export default defineNuxtRouteMiddleware((to, from) => {
 
     const authStore = useAuthStore()
 
-    // SSR Hydration fix: If store is empty but sessionStorage has the key, restore it
+    // SSR Hydration fix: If store is empty but sessionStorage has the session, restore it
     if (!authStore.isAuthenticated && import.meta.client) {
-        const savedKey = sessionStorage.getItem('lowart_admin_key')
+        const savedToken = sessionStorage.getItem('lowart_admin_session')
+        const savedExpires = sessionStorage.getItem('lowart_admin_session_expires') || ''
         const savedUser = sessionStorage.getItem('lowart_current_user')
-        if (savedKey && savedUser) {
+        if (savedToken && savedUser) {
             try {
-                authStore.setAuth(savedKey, JSON.parse(savedUser))
+                authStore.setAuth(savedToken, savedExpires, JSON.parse(savedUser))
             } catch (e) {
                 console.error('Failed to restore session:', e)
             }
@@ -28,8 +29,9 @@ export default defineNuxtRouteMiddleware((to, from) => {
         return
     }
 
-    // Redirect to login if not authenticated
+    // Redirect to login if not authenticated (including expired sessions)
     if (!authStore.isAuthenticated) {
+        authStore.clearAuth()
         return navigateTo('/login')
     }
 })
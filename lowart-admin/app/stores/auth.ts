@@ -1,34 +1,60 @@
 import { defineStore } from 'pinia'
 
+// 浏览器只保存短期会话令牌 (登出或过期后失效)，不再保存 API Key
+const TOKEN_KEY = 'lowart_admin_session'
+const EXPIRES_KEY = 'lowart_admin_session_expires'
+const USER_KEY = 'lowart_current_user'
+
 export const useAuthStore = defineStore('auth', () => {
-    const adminKey = ref(import.meta.client ? sessionStorage.getItem('lowart_admin_key') || '' : '')
-    const currentUser = ref(import.meta.client ? JSON.parse(sessionStorage.getItem('lowart_current_user') || 'null') : null)
-    const isAuthenticated = computed(() => !!adminKey.value)
+    if (import.meta.client) {
+        sessionStorage.removeItem('lowart_admin_key') // 清理旧版本保存的 API Key
+    }
+
+    const sessionToken = ref(import.meta.client ? sessionStorage.getItem(TOKEN_KEY) || '' : '')
+    const expiresAt = ref(import.meta.client ? sessionStorage.getItem(EXPIRES_KEY) || '' : '')
+    const currentUser = ref(import.meta.client ? JSON.parse(sessionStorage.getItem(USER_KEY) || 'null') : null)
+    const isAuthenticated = computed(() =>
+        !!sessionToken.value && (!expiresAt.value || new Date(expiresAt.value) > new Date())
+    )
 
-    const setAuth = (key: string, user: any) => {
-        adminKey.value = key
+    const setAuth = (token: string, expires: string, user: any) => {
+        sessionToken.value = token
+        expiresAt.value = expires
         currentUser.value = user
         if (import.meta.client) {
-            sessionStorage.setItem('lowart_admin_key', key)
-            sessionStorage.setItem('lowart_current_user', JSON.stringify(user))
+            sessionStorage.setItem(TOKEN_KEY, token)
+            sessionStorage.setItem(EXPIRES_KEY, expires)
+            sessionStorage.setItem(USER_KEY, JSON.stringify(user))
         }
     }
 
-    const logout = () => {
-        adminKey.value = ''
+    const clearAuth = () => {
+        sessionToken.value = ''
+        expiresAt.value = ''
         currentUser.value = null
         if (import.meta.client) {
-            sessionStorage.removeItem('lowart_admin_key')
-            sessionStorage.removeItem('lowart_current_user')
+            sessionStorage.removeItem(TOKEN_KEY)
+            sessionStorage.removeItem(EXPIRES_KEY)
+            sessionStorage.removeItem(USER_KEY)
+        }
+    }
+
+    const logout = async () => {
+        if (sessionToken.value) {
+            // 服务端注销会话；失败 (如已过期) 不影响本地退出
+            await useApi().logout().catch(() => {})
         }
+        clearAuth()
         navigateTo('/login')
     }
 
     return {
-        adminKey,
+        sessionToken,
+        expiresAt,
         currentUser,
         isAuthenticated,
         setAuth,
+        clearAuth,
         logout
     }
 })
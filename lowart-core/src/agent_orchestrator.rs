@@ -13,6 +13,12 @@ pub struct AgentOrchestrator {
     // 基础的总线功能可以集成在这里，或者使用专门的总线实现
 }
 
+impl Default for AgentOrchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AgentOrchestrator {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,84 @@
+use serde_json::{json, Map, Value};
+
+/// 脱敏后的占位值
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 审计记录中需要脱敏的字段 (不区分大小写)
+const SECRET_FIELDS: &[&str] = &[
+    "api_key", "key", "password", "current_password", "new_password", "password_hash",
+    "totp_secret", "secret", "client_secret", "token", "key_hash", "previous_key_hash", "authorization",
+];
+
+pub fn is_secret_field(name: &str) -> bool {
+    SECRET_FIELDS.iter().any(|f| f.eq_ignore_ascii_case(name))
+}
+
+/// 递归脱敏: 密钥类字段 (非空时) 替换为占位值
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), if is_secret_field(k) { mask(v) } else { redact(v) }))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+fn mask(value: &Value) -> Value {
+    if value.is_null() { Value::Null } else { json!(REDACTED) }
+}
+
+/// 计算审计变更 `{字段: {before, after}}`，只保留发生变化的字段，结果已脱敏
+/// 实现逻辑: 新建时 before 为 null、删除时 after 为 null，按空对象处理，即记录全部字段；
+/// 非对象的值 (如策略字符串) 记为 `value` 字段。密钥类字段变化时只记录"有变化"，不记录内容。
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let (before, after) = (fields(before), fields(after));
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys().filter(|k| !before.contains_key(*k))) {
+        let (old, new) = (before.get(key).unwrap_or(&Value::Null), after.get(key).unwrap_or(&Value::Null));
+        if old == new {
+            continue;
+        }
+        let change = if is_secret_field(key) {
+            json!({"before": mask(old), "after": mask(new)})
+        } else {
+            json!({"before": redact(old), "after": redact(new)})
+        };
+        changes.insert(key.clone(), change);
+    }
+    Value::Object(changes)
+}
+
+fn fields(value: &Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map.clone(),
+        Value::Null => Map::new(),
+        other => Map::from_iter([("value".to_string(), other.clone())]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_diff_redacts_secrets() {
+        let before = json!({"id": "m1", "title": "GPT", "api_key": "sk-old", "extra": {"token": "t", "n": 1}});
+        let after = json!({"id": "m1", "title": "GPT-4", "api_key": "sk-new", "extra": {"token": "t", "n": 2}});
+        let changes = diff(&before, &after);
+        assert_eq!(changes["title"], json!({"before": "GPT", "after": "GPT-4"}));
+        assert_eq!(changes["api_key"], json!({"before": REDACTED, "after": REDACTED}));
+        assert_eq!(changes["extra"]["after"], json!({"token": REDACTED, "n": 2}));
+        assert!(changes.get("id").is_none());
+        assert!(!changes.to_string().contains("sk-"));
+
+        // 新建与删除记录全部字段；未变化时为空
+        let created = diff(&Value::Null, &json!({"username": "alice", "Password": "p"}));
+        assert_eq!(created["username"], json!({"before": null, "after": "alice"}));
+        assert_eq!(created["Password"]["after"], REDACTED);
+        assert_eq!(diff(&json!("confirm"), &Value::Null), json!({"value": {"before": "confirm", "after": null}}));
+        assert_eq!(diff(&after, &after), json!({}));
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+use db::{BillingRepo, CreditRepo, LedgerEntry, ModelConfig, StatsRepo};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use utils::Result;
+use crate::model_manager::ModelManager;
+use crate::quota::QuotaService;
+use crate::reservation::{QuotaReservations, Reservation};
+use crate::tpm_limiter::TpmLimiter;
+
+/// 1 分 = 10,000 微单位 (用于兼容旧的 cost_per_1k_tokens 字段)
+pub const MICROS_PER_CENT: i64 = 10_000;
+
+/// 模型分项单价 (每千 Token，微单位)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input_price_per_1k: i64,
+    pub cached_input_price_per_1k: i64,
+    pub output_price_per_1k: i64,
+}
+
+impl From<&ModelConfig> for ModelPricing {
+    fn from(config: &ModelConfig) -> Self {
+        Self {
+            input_price_per_1k: config.input_price_per_1k,
+            cached_input_price_per_1k: config.cached_input_price_per_1k,
+            output_price_per_1k: config.output_price_per_1k,
+        }
+    }
+}
+
+impl ModelPricing {
+    /// 计算一次请求的费用 (微单位，四舍五入)
+    /// 实现逻辑: 缓存命中的输入按缓存单价计费，其余输入按输入单价计费，输出按输出单价计费。
+    pub fn cost(&self, usage: &TokenUsage) -> i64 {
+        let cached = usage.cached_input_tokens.clamp(0, usage.input_tokens) as i128;
+        let uncached = usage.input_tokens as i128 - cached;
+        let total = uncached * self.input_price_per_1k as i128
+            + cached * self.cached_input_price_per_1k as i128
+            + usage.output_tokens as i128 * self.output_price_per_1k as i128;
+        ((total + 500) / 1000) as i64
+    }
+}
+
+/// 单次请求的 Token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// 输入 Token 总数 (包含命中缓存的部分)
+    pub input_tokens: i64,
+    /// 其中命中缓存的输入 Token
+    pub cached_input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: i64, output_tokens: i64) -> Self {
+        Self { input_tokens, cached_input_tokens: 0, output_tokens }
+    }
+
+    pub fn total(&self) -> i64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// 从厂商响应的 `usage` 字段解析用量 (兼容 OpenAI 与 Anthropic 格式)
+    pub fn from_response(res: &Value) -> Option<Self> {
+        let usage = res.get("usage")?;
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_i64());
+
+        // OpenAI: prompt_tokens 已包含 cached_tokens
+        if let Some(prompt) = field("prompt_tokens") {
+            let cached = usage.get("prompt_tokens_details")
+                .and_then(|d| d.get("cached_tokens"))
+                .and_then(|v| v.as_i64())
+                .unwrap_or(0);
+            return Some(Self {
+                input_tokens: prompt,
+                cached_input_tokens: cached,
+                output_tokens: field("completion_tokens").unwrap_or(0),
+            });
+        }
+
+        // Anthropic: input_tokens 不包含缓存读取部分
+        if let Some(input) = field("input_tokens") {
+            let cached = field("cache_read_input_tokens").unwrap_or(0);
+            return Some(Self {
+                input_tokens: input + cached,
+                cached_input_tokens: cached,
+                output_tokens: field("output_tokens").unwrap_or(0),
+            });
+        }
+
+        None
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// 一次请求结束后的计费记录
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub user_id: String,
+    pub api_key_id: Option<i64>,
+    pub model_id: String,
+    pub usage: TokenUsage,
+    pub duration_ms: i64,
+    pub reservation: Option<Reservation>, // 放行时的配额预占，计费后结算
+}
+
+/// 计费服务
+/// 实现原理: 请求结束后的统一计费入口。根据模型分项单价计算费用，
+/// 写入只追加的 `billing_ledger`，同时累加用户的 Token 与金额用量 (预付费用户同步扣减余额)，
+/// 并结算配额预占、修正 TPM 计数、累加周期配额计数、记录使用统计。
+pub struct BillingService {
+    model_manager: Arc<ModelManager>,
+    quota: Arc<QuotaService>,
+    reservations: Arc<QuotaReservations>,
+    tpm: Arc<TpmLimiter>,
+}
+
+impl BillingService {
+    pub fn new(model_manager: Arc<ModelManager>, quota: Arc<QuotaService>, reservations: Arc<QuotaReservations>, tpm: Arc<TpmLimiter>) -> Self {
+        Self { model_manager, quota, reservations, tpm }
+    }
+
+    /// 记录一次请求的用量并扣费
+    pub async fn record_usage(&self, mut record: UsageRecord) -> Result<LedgerEntry> {
+        let db = self.model_manager.db();
+        let pricing = match self.model_manager.get_pricing(&record.model_id).await {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::warn!("获取模型 {} 定价失败，按 0 计费: {}", record.model_id, e);
+                ModelPricing::default()
+            }
+        };
+
+        let mut entry = LedgerEntry {
+            id: 0, // 自动递增
+            user_id: record.user_id.clone(),
+            api_key_id: record.api_key_id,
+            model_id: record.model_id.clone(),
+            input_tokens: record.usage.input_tokens,
+            cached_input_tokens: record.usage.cached_input_tokens,
+            output_tokens: record.usage.output_tokens,
+            input_price_per_1k: pricing.input_price_per_1k,
+            cached_input_price_per_1k: pricing.cached_input_price_per_1k,
+            output_price_per_1k: pricing.output_price_per_1k,
+            amount: pricing.cost(&record.usage),
+            created_at: chrono::Utc::now(),
+        };
+        entry.id = BillingRepo::new(&db).record_charge(&entry).await?;
+
+        // 按实际用量修正 TPM 计数: 只修正放行时扣减过的作用域 (降级后实际调用的模型可能不同于放行时的主模型)
+        if let Some(reservation) = &record.reservation {
+            let scopes = reservation.tpm_scopes();
+            if !scopes.is_empty() {
+                self.tpm.correct(&scopes, record.usage.total() - reservation.estimated_input_tokens()).await;
+            }
+        }
+
+        // 先累加周期配额计数，再结算预占 (释放周期配额占用)，两者之间不会出现用量空档
+        let quota_recorded = self.quota.record(&record.user_id, &record.model_id, record.usage.total(), entry.amount).await;
+        self.reservations.settle(&record.user_id, record.api_key_id, record.reservation.take(), record.usage.total(), entry.amount);
+        quota_recorded?;
+
+        let stats_repo = StatsRepo::new(&db);
+        stats_repo.record_usage_rollup(
+            entry.created_at,
+            &record.user_id,
+            record.api_key_id,
+            &record.model_id,
+            record.usage.input_tokens,
+            record.usage.output_tokens,
+            entry.amount,
+        ).await?;
+        stats_repo.record_usage(
+            &record.user_id,
+            &record.model_id,
+            record.usage.input_tokens,
+            record.usage.output_tokens,
+            "厂商返回响应",
+            record.duration_ms,
+        ).await?;
+
+        Ok(entry)
+    }
+
+    /// 回收已过期的预付费额度 (由后台任务定期调用)
+    pub async fn expire_credits(&self) -> Result<u64> {
+        let db = self.model_manager.db();
+        let expired = CreditRepo::new(&db).expire_due(chrono::Utc::now()).await?;
+        if expired > 0 {
+            tracing::info!("已回收 {} 个过期的充值批次", expired);
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cost_splits_cached_input() {
+        let pricing = ModelPricing {
+            input_price_per_1k: 2_500,
+            cached_input_price_per_1k: 1_250,
+            output_price_per_1k: 10_000,
+        };
+        let usage = TokenUsage { input_tokens: 1_000, cached_input_tokens: 400, output_tokens: 200 };
+        // 600 * 2.5 + 400 * 1.25 + 200 * 10 = 1500 + 500 + 2000
+        assert_eq!(pricing.cost(&usage), 4_000);
+    }
+
+    #[test]
+    fn test_usage_from_response_formats() {
+        let openai = json!({"usage": {"prompt_tokens": 10, "completion_tokens": 5, "prompt_tokens_details": {"cached_tokens": 4}}});
+        assert_eq!(TokenUsage::from_response(&openai), Some(TokenUsage { input_tokens: 10, cached_input_tokens: 4, output_tokens: 5 }));
+
+        let anthropic = json!({"usage": {"input_tokens": 6, "cache_read_input_tokens": 4, "output_tokens": 5}});
+        assert_eq!(TokenUsage::from_response(&anthropic), Some(TokenUsage { input_tokens: 10, cached_input_tokens: 4, output_tokens: 5 }));
+
+        assert_eq!(TokenUsage::from_response(&json!({"choices": []})), None);
+    }
+}
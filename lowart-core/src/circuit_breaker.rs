@@ -1,106 +1,667 @@
-use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
+use db::ModelConfig;
+use metrics::{counter, gauge};
+use serde::Serialize;
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::clock::{Clock, SystemClock};
 
 /// 断路器状态
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum CircuitState {
     Closed,     // 闭合 (正常)
     Open,       // 开启 (熔断)
     HalfOpen,   // 半开 (探测)
 }
 
+impl CircuitState {
+    /// 指标取值: Closed 0，HalfOpen 1，Open 2
+    fn metric_value(self) -> f64 {
+        match self {
+            Self::Closed => 0.0,
+            Self::HalfOpen => 1.0,
+            Self::Open => 2.0,
+        }
+    }
+
+    /// 指标标签与持久化取值
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::HalfOpen => "half_open",
+            Self::Open => "open",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "closed" => Some(Self::Closed),
+            "half_open" => Some(Self::HalfOpen),
+            "open" => Some(Self::Open),
+            _ => None,
+        }
+    }
+}
+
+/// 状态转换记录 (用于持久化与多实例同步)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitRecord {
+    pub model_id: String,
+    pub state: CircuitState,
+    pub forced_open: bool,
+    pub consecutive_trips: u32,
+    pub open_until: Option<DateTime<Utc>>, // 熔断结束的墙钟时间 (强制熔断为 None)
+}
+
+/// 滑动窗口的分桶数，窗口随时间逐桶滚动
+const WINDOW_BUCKETS: u32 = 10;
+
+/// 断路器参数 (可按模型覆盖，见 `CircuitBreaker::config_for`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakerConfig {
+    pub window: Duration,           // 滑动窗口长度
+    pub min_requests: u32,          // 窗口内请求数达到该值才判断是否熔断
+    pub error_rate: f64,            // 失败率阈值 (0~1)
+    pub slow_call: Option<Duration>, // 慢调用耗时阈值，None 表示不统计慢调用
+    pub slow_call_rate: f64,        // 慢调用率阈值 (0~1)
+    pub open_duration: Duration,    // 首次熔断时长
+    pub max_open_duration: Duration, // 连续熔断退避的上限
+    pub half_open_probes: u32,      // 半开期间同时放行的探测请求数
+    pub half_open_successes: u32,   // 连续探测成功该次数后闭合
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            min_requests: 20,
+            error_rate: 0.5,
+            slow_call: None,
+            slow_call_rate: 0.8,
+            open_duration: Duration::from_secs(30),
+            max_open_duration: Duration::from_secs(600),
+            half_open_probes: 2,
+            half_open_successes: 3,
+        }
+    }
+}
+
+impl BreakerConfig {
+    /// 第 `trips` 次连续熔断的时长: 首次为 open_duration，之后每次翻倍，不超过 max_open_duration
+    fn open_duration_for(&self, trips: u32) -> Duration {
+        let factor = 1u32 << trips.saturating_sub(1).min(16);
+        self.open_duration.saturating_mul(factor).min(self.max_open_duration.max(self.open_duration))
+    }
+
+    fn bucket_width(&self) -> Duration {
+        (self.window / WINDOW_BUCKETS).max(Duration::from_millis(1))
+    }
+}
+
+/// 窗口中的一个时间桶 (`index` 为该桶对应的时间片序号，用于识别过期桶)
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    index: u64,
+    total: u32,
+    failures: u32,
+    slow: u32,
+}
+
 /// 模型健康统计
 struct HealthStats {
     state: CircuitState,
-    failure_count: u32,
-    last_failure_time: Option<Instant>,
-    last_success_time: Option<Instant>,
+    buckets: Vec<Bucket>,
+    bucket_width: Duration,
+    trips: u32,                   // 连续熔断次数 (用于退避)
+    open_until: Option<Instant>,
+    closed_since: Instant,
+    generation: u64,              // 半开轮次，每次进入 HalfOpen 加一，用于识别过期的探测许可
+    probes_in_flight: u32,
+    probe_successes: u32,
+    checks_until: Option<Instant>, // 主动健康检查接管探测的截止时间
+    forced_open: bool,             // 管理员强制熔断 (不会自动恢复)
+    successes_total: u64,
+    failures_total: u64,
+    last_success_at: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+    changed: bool,                 // 有待发布的状态转换
 }
 
-impl Default for HealthStats {
-    fn default() -> Self {
+impl HealthStats {
+    fn new(model_id: &str, now: Instant) -> Self {
+        gauge!("gateway_circuit_state", "model" => model_id.to_string()).set(CircuitState::Closed.metric_value());
         Self {
             state: CircuitState::Closed,
-            failure_count: 0,
-            last_failure_time: None,
-            last_success_time: None,
+            buckets: vec![Bucket::default(); WINDOW_BUCKETS as usize],
+            bucket_width: Duration::ZERO,
+            trips: 0,
+            open_until: None,
+            closed_since: now,
+            generation: 0,
+            probes_in_flight: 0,
+            probe_successes: 0,
+            checks_until: None,
+            forced_open: false,
+            successes_total: 0,
+            failures_total: 0,
+            last_success_at: None,
+            last_failure_at: None,
+            changed: false,
         }
     }
+
+    /// 切换状态并更新指标
+    fn transition(&mut self, model_id: &str, to: CircuitState) {
+        let from = self.state;
+        self.state = to;
+        if from != to {
+            self.changed = true;
+            counter!("gateway_circuit_transitions_total", "model" => model_id.to_string(), "from" => from.label(), "to" => to.label())
+                .increment(1);
+            gauge!("gateway_circuit_state", "model" => model_id.to_string()).set(to.metric_value());
+        }
+    }
+
+    fn reset_window(&mut self) {
+        self.buckets.iter_mut().for_each(|b| *b = Bucket::default());
+    }
+
+    /// 返回窗口内的 (请求数, 失败数, 慢调用数)
+    fn totals(&self, current: u64) -> (u32, u32, u32) {
+        self.buckets.iter()
+            .filter(|b| b.total > 0 && b.index + WINDOW_BUCKETS as u64 > current)
+            .fold((0, 0, 0), |(t, f, s), b| (t + b.total, f + b.failures, s + b.slow))
+    }
+
+    /// 进入 Open 状态，熔断时长按连续熔断次数指数退避
+    fn trip(&mut self, model_id: &str, config: &BreakerConfig, now: Instant) {
+        self.trips = self.trips.saturating_add(1);
+        let duration = config.open_duration_for(self.trips);
+        self.transition(model_id, CircuitState::Open);
+        self.open_until = Some(now + duration);
+        self.reset_window();
+        if self.trips > 1 {
+            tracing::warn!("模型 {} 第 {} 次连续熔断，熔断 {:?}", model_id, self.trips, duration);
+        }
+    }
+
+    /// 转换为持久化记录 (熔断结束时间按当前墙钟换算)
+    fn record(&self, model_id: &str, now: Instant) -> CircuitRecord {
+        let open_until = match self.state {
+            CircuitState::Open => self.open_until.map(|until| {
+                Utc::now() + chrono::Duration::from_std(until.saturating_duration_since(now)).unwrap_or_default()
+            }),
+            _ => None,
+        };
+        CircuitRecord {
+            model_id: model_id.to_string(),
+            state: self.state,
+            forced_open: self.forced_open,
+            consecutive_trips: self.trips,
+            open_until,
+        }
+    }
+
+    /// 当前的半开轮次 (非 HalfOpen 时为 None)
+    fn probe_generation(&self) -> Option<u64> {
+        (self.state == CircuitState::HalfOpen).then_some(self.generation)
+    }
 }
 
-/// 智能断路器管理器
-/// 实现原理: 基于简单计数与超时机制的状态机。
-/// 1. 当失败次数超过阈值时，进入 Open 状态。
-/// 2. 在 Open 状态维持一段时间后，自动进入 HalfOpen。
-/// 3. HalfOpen 期间，第一个成功请求会将状态重置为 Closed。
-pub struct CircuitBreaker {
-    stats: RwLock<HashMap<String, HealthStats>>,
-    failure_threshold: u32,
-    reset_timeout: Duration,
+/// 断路器与其发出的许可共享的状态
+struct Shared {
+    stats: Mutex<HashMap<String, HealthStats>>,
+    clock: Arc<dyn Clock>,
+    epoch: Instant,
+    transitions: OnceLock<mpsc::UnboundedSender<CircuitRecord>>,
 }
 
-impl CircuitBreaker {
-    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
-        Self {
-            stats: RwLock::new(HashMap::new()),
-            failure_threshold,
-            reset_timeout,
+impl Shared {
+    /// 发布待处理的状态转换 (未订阅时只清除标记)
+    fn publish(&self, model_id: &str, health: &mut HealthStats, now: Instant) {
+        if !std::mem::take(&mut health.changed) {
+            return;
+        }
+        if let Some(tx) = self.transitions.get() {
+            let _ = tx.send(health.record(model_id, now));
         }
     }
 
-    /// 检查指定模型是否允许访问
-    pub async fn is_allowed(&self, model_id: &str) -> bool {
-        let mut stats_map = self.stats.write().await;
-        let health = stats_map.entry(model_id.to_string()).or_default();
+    /// 结算一次调用 (`probe` 为探测许可所属的半开轮次)
+    fn settle(&self, model_id: &str, probe: Option<u64>, config: &BreakerConfig, is_success: bool, latency: Duration) {
+        let now = self.clock.now();
+        let mut stats_map = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let health = stats_map.entry(model_id.to_string()).or_insert_with(|| HealthStats::new(model_id, now));
+        self.apply_result(model_id, health, probe, config, is_success, latency, now);
+        self.publish(model_id, health, now);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn apply_result(
+        &self,
+        model_id: &str,
+        health: &mut HealthStats,
+        probe: Option<u64>,
+        config: &BreakerConfig,
+        is_success: bool,
+        latency: Duration,
+        now: Instant,
+    ) {
+        let is_slow = config.slow_call.is_some_and(|threshold| latency >= threshold);
+        if is_success {
+            health.successes_total += 1;
+            health.last_success_at = Some(Utc::now());
+        } else {
+            health.failures_total += 1;
+            health.last_failure_at = Some(Utc::now());
+        }
 
         match health.state {
-            CircuitState::Closed => true,
-            CircuitState::Open => {
-                if let Some(last_fail) = health.last_failure_time {
-                    if last_fail.elapsed() > self.reset_timeout {
-                        // 熔断时间到，进入探测期
-                        health.state = CircuitState::HalfOpen;
-                        tracing::info!("模型 {} 进入半开 (Half-Open) 状态", model_id);
-                        return true;
+            CircuitState::HalfOpen => {
+                // 只有本轮发出的探测计入，熔断前发出、此时才返回的请求不影响探测结果
+                if probe != Some(health.generation) {
+                    return;
+                }
+                health.probes_in_flight = health.probes_in_flight.saturating_sub(1);
+                if !is_success || is_slow {
+                    tracing::warn!("模型 {} 探测请求{}，重新熔断", model_id, if is_success { "过慢" } else { "失败" });
+                    health.trip(model_id, config, now);
+                    return;
+                }
+                health.probe_successes += 1;
+                if health.probe_successes >= config.half_open_successes.max(1) {
+                    health.transition(model_id, CircuitState::Closed);
+                    health.closed_since = now;
+                    health.reset_window();
+                    tracing::info!("模型 {} 恢复正常 (Closed)", model_id);
+                }
+            }
+            CircuitState::Open => {} // 熔断前已发出的请求，结果不再计入
+            CircuitState::Closed => {
+                // 窗口长度变化 (模型配置修改) 时重新开始统计
+                let width = config.bucket_width();
+                if health.bucket_width != width {
+                    health.bucket_width = width;
+                    health.reset_window();
+                }
+                let current = self.bucket_index(now, width);
+                let bucket = &mut health.buckets[(current % WINDOW_BUCKETS as u64) as usize];
+                if bucket.index != current {
+                    *bucket = Bucket { index: current, ..Bucket::default() };
+                }
+                bucket.total += 1;
+                bucket.failures += u32::from(!is_success);
+                bucket.slow += u32::from(is_slow);
+
+                let (total, failures, slow) = health.totals(current);
+                if total < config.min_requests.max(1) {
+                    return;
+                }
+                let error_rate = failures as f64 / total as f64;
+                let slow_rate = slow as f64 / total as f64;
+                if error_rate >= config.error_rate || (config.slow_call.is_some() && slow_rate >= config.slow_call_rate) {
+                    tracing::warn!(
+                        "模型 {} 触发熔断 (Open)，窗口内请求 {} 次，失败率 {:.0}%，慢调用率 {:.0}%",
+                        model_id, total, error_rate * 100.0, slow_rate * 100.0
+                    );
+                    // 稳定闭合超过最长熔断时长后，退避重新计数
+                    if now.duration_since(health.closed_since) >= config.max_open_duration {
+                        health.trips = 0;
                     }
+                    health.trip(model_id, config, now);
                 }
-                false
             }
-            CircuitState::HalfOpen => true, // 允许少量探测流量
         }
     }
 
-    /// 上报执行结果
-    pub async fn report_result(&self, model_id: &str, is_success: bool) {
-        let mut stats_map = self.stats.write().await;
-        let health = stats_map.entry(model_id.to_string()).or_default();
+    /// 当前时间所在的时间片序号
+    fn bucket_index(&self, now: Instant, width: Duration) -> u64 {
+        (now.duration_since(self.epoch).as_nanos() / width.as_nanos().max(1)) as u64
+    }
 
-        if is_success {
-            if health.state == CircuitState::HalfOpen {
-                health.state = CircuitState::Closed;
-                health.failure_count = 0;
-                tracing::info!("模型 {} 恢复正常 (Closed)", model_id);
+    /// 归还未结算的探测许可 (调用在上游请求前放弃，如模型加载失败、排队超时)
+    fn release_probe(&self, model_id: &str, generation: u64) {
+        let mut stats_map = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(health) = stats_map.get_mut(model_id) {
+            if health.probe_generation() == Some(generation) {
+                health.probes_in_flight = health.probes_in_flight.saturating_sub(1);
             }
-            health.last_success_time = Some(Instant::now());
+        }
+    }
+}
+
+/// 模型断路器状态快照 (用于管理接口)
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitSnapshot {
+    pub model_id: String,
+    pub state: CircuitState,
+    pub forced_open: bool,             // 是否为管理员强制熔断
+    pub window_requests: u32,          // 滑动窗口内的请求数
+    pub window_failures: u32,
+    pub window_slow_calls: u32,
+    pub successes_total: u64,          // 自上次重置以来的累计成功数
+    pub failures_total: u64,
+    pub consecutive_trips: u32,        // 连续熔断次数 (决定下次熔断时长)
+    pub open_remaining_ms: Option<u64>, // 距离进入半开的剩余时间 (强制熔断时为 None)
+    pub probes_in_flight: u32,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// 断路器许可
+/// 实现逻辑: Closed 状态下为普通许可；HalfOpen 状态下为探测许可，占用一个探测名额。
+/// 调用结束后通过 `record` 上报结果；未上报即丢弃时归还探测名额，不计入统计。
+pub struct BreakerPermit {
+    shared: Arc<Shared>,
+    model_id: String,
+    probe: Option<u64>,
+    settled: bool,
+}
+
+impl BreakerPermit {
+    /// 是否为半开期间的探测许可
+    pub fn is_probe(&self) -> bool {
+        self.probe.is_some()
+    }
+
+    /// 上报执行结果与耗时
+    pub fn record(mut self, config: &BreakerConfig, is_success: bool, latency: Duration) {
+        self.settled = true;
+        self.shared.settle(&self.model_id, self.probe, config, is_success, latency);
+    }
+}
+
+impl Drop for BreakerPermit {
+    fn drop(&mut self) {
+        if let (false, Some(generation)) = (self.settled, self.probe) {
+            self.shared.release_probe(&self.model_id, generation);
+        }
+    }
+}
+
+/// 智能断路器管理器
+/// 实现原理: 按模型维护时间分桶的滑动窗口，统计最近一个窗口内的请求数、失败数与慢调用数。
+/// 1. Closed 状态下，窗口内请求数达到 min_requests 且失败率或慢调用率超过阈值时，进入 Open 状态。
+/// 2. Open 状态维持熔断时长后，自动进入 HalfOpen；连续熔断时熔断时长按指数退避，
+///    模型稳定闭合超过最长熔断时长后退避清零。
+/// 3. HalfOpen 期间最多同时放行 half_open_probes 个探测请求，其余请求视同熔断；
+///    连续 half_open_successes 次探测健康后闭合并清空窗口，任一探测失败或过慢则立即重新熔断。
+/// 4. 启用主动健康检查时，熔断中的模型只由健康检查探测 (见 `try_acquire_check`)。
+///
+/// 时间取自注入的时钟，测试中可用手动时钟确定性地驱动状态变化。
+pub struct CircuitBreaker {
+    shared: Arc<Shared>,
+    defaults: BreakerConfig,
+}
+
+impl CircuitBreaker {
+    /// 按失败次数构造: 窗口内至少 `failure_threshold` 次请求且失败过半即熔断，熔断时长为 `reset_timeout`，
+    /// 半开期间单个探测成功即闭合
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        Self::with_config(BreakerConfig {
+            min_requests: failure_threshold,
+            open_duration: reset_timeout,
+            max_open_duration: reset_timeout.saturating_mul(20),
+            half_open_probes: 1,
+            half_open_successes: 1,
+            ..BreakerConfig::default()
+        })
+    }
+
+    /// 以默认参数构造 (模型未单独配置的参数使用该默认值)
+    pub fn with_config(defaults: BreakerConfig) -> Self {
+        Self::with_clock(defaults, Arc::new(SystemClock))
+    }
+
+    /// 以指定时钟构造
+    pub fn with_clock(defaults: BreakerConfig, clock: Arc<dyn Clock>) -> Self {
+        let epoch = clock.now();
+        Self {
+            shared: Arc::new(Shared { stats: Mutex::new(HashMap::new()), clock, epoch, transitions: OnceLock::new() }),
+            defaults,
+        }
+    }
+
+    /// 默认参数
+    pub fn defaults(&self) -> &BreakerConfig {
+        &self.defaults
+    }
+
+    /// 合并模型配置中的断路器参数 (未配置的项使用默认值)
+    pub fn config_for(&self, config: Option<&ModelConfig>) -> BreakerConfig {
+        let mut merged = self.defaults.clone();
+        let Some(config) = config else { return merged };
+        let secs = |v: i64| Duration::from_secs(v.max(1) as u64);
+        let rate = |percent: i64| percent.clamp(1, 100) as f64 / 100.0;
+        if let Some(v) = config.breaker_window_secs { merged.window = secs(v); }
+        if let Some(v) = config.breaker_min_requests { merged.min_requests = v.max(1) as u32; }
+        if let Some(v) = config.breaker_error_rate { merged.error_rate = rate(v); }
+        if let Some(v) = config.breaker_slow_call_ms { merged.slow_call = Some(Duration::from_millis(v.max(1) as u64)); }
+        if let Some(v) = config.breaker_slow_call_rate { merged.slow_call_rate = rate(v); }
+        if let Some(v) = config.breaker_open_secs { merged.open_duration = secs(v); }
+        if let Some(v) = config.breaker_max_open_secs { merged.max_open_duration = secs(v); }
+        merged
+    }
+
+    /// 查询模型当前状态 (不触发 Open 到 HalfOpen 的转换)
+    pub fn state(&self, model_id: &str) -> CircuitState {
+        let stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats_map.get(model_id).map_or(CircuitState::Closed, |h| h.state)
+    }
+
+    /// 查询模型的状态快照 (未产生过调用的模型返回闭合的空快照)
+    pub fn snapshot(&self, model_id: &str) -> CircuitSnapshot {
+        let now = self.shared.clock.now();
+        let stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        match stats_map.get(model_id) {
+            Some(health) => self.snapshot_of(model_id, health, now),
+            None => self.snapshot_of(model_id, &HealthStats::new(model_id, now), now),
+        }
+    }
+
+    /// 全部已产生过调用的模型的状态快照 (按模型 ID 排序)
+    pub fn snapshots(&self) -> Vec<CircuitSnapshot> {
+        let now = self.shared.clock.now();
+        let stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<CircuitSnapshot> = stats_map.iter()
+            .map(|(model_id, health)| self.snapshot_of(model_id, health, now))
+            .collect();
+        list.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+        list
+    }
+
+    fn snapshot_of(&self, model_id: &str, health: &HealthStats, now: Instant) -> CircuitSnapshot {
+        let (window_requests, window_failures, window_slow_calls) = if health.bucket_width.is_zero() {
+            (0, 0, 0)
         } else {
-            health.failure_count += 1;
-            health.last_failure_time = Some(Instant::now());
+            health.totals(self.shared.bucket_index(now, health.bucket_width))
+        };
+        CircuitSnapshot {
+            model_id: model_id.to_string(),
+            state: health.state,
+            forced_open: health.forced_open,
+            window_requests,
+            window_failures,
+            window_slow_calls,
+            successes_total: health.successes_total,
+            failures_total: health.failures_total,
+            consecutive_trips: health.trips,
+            open_remaining_ms: match health.state {
+                CircuitState::Open => health.open_until.map(|until| until.saturating_duration_since(now).as_millis() as u64),
+                _ => None,
+            },
+            probes_in_flight: health.probes_in_flight,
+            last_success_at: health.last_success_at,
+            last_failure_at: health.last_failure_at,
+        }
+    }
+
+    /// 强制熔断 (维护时摘除模型)，直到强制闭合或重置前不会自动恢复
+    pub fn force_open(&self, model_id: &str) {
+        self.update(model_id, |health| {
+            health.transition(model_id, CircuitState::Open);
+            health.changed = true;
+            health.forced_open = true;
+            health.open_until = None;
+            health.reset_window();
+        });
+        tracing::warn!("模型 {} 已被强制熔断", model_id);
+    }
+
+    /// 强制闭合: 立即恢复放行并清空窗口与退避计数，之后按正常规则统计
+    pub fn force_close(&self, model_id: &str) {
+        let now = self.shared.clock.now();
+        self.update(model_id, |health| {
+            health.transition(model_id, CircuitState::Closed);
+            health.changed = true;
+            health.forced_open = false;
+            health.open_until = None;
+            health.trips = 0;
+            health.closed_since = now;
+            health.reset_window();
+        });
+        tracing::info!("模型 {} 已被强制闭合", model_id);
+    }
+
+    /// 重置: 清除该模型的全部统计 (含累计计数与最近成功 / 失败时间)
+    pub fn reset(&self, model_id: &str) {
+        let now = self.shared.clock.now();
+        self.update(model_id, |health| {
+            health.transition(model_id, CircuitState::Closed);
+            // 保留半开轮次，避免重置前发出的探测许可被误认为新一轮的探测
+            let generation = health.generation;
+            *health = HealthStats::new(model_id, now);
+            health.generation = generation;
+            health.changed = true;
+        });
+        tracing::info!("模型 {} 的断路器统计已重置", model_id);
+    }
+
+    fn update(&self, model_id: &str, apply: impl FnOnce(&mut HealthStats)) {
+        let now = self.shared.clock.now();
+        let mut stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let health = stats_map.entry(model_id.to_string()).or_insert_with(|| HealthStats::new(model_id, now));
+        apply(health);
+        self.shared.publish(model_id, health, now);
+    }
+
+    /// 订阅状态转换 (只能订阅一次，重复订阅返回 None)
+    pub fn subscribe_transitions(&self) -> Option<mpsc::UnboundedReceiver<CircuitRecord>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.shared.transitions.set(tx).ok().map(|_| rx)
+    }
+
+    /// 应用持久化或其他实例的状态 (不发布转换)
+    /// 实现逻辑: 熔断剩余时长按墙钟换算到本地时钟；半开探测无法跨进程延续，按熔断到期处理，
+    /// 下一个请求重新探测。同时作废本地已发出的探测许可并清空窗口。
+    pub fn restore(&self, record: &CircuitRecord) {
+        let now = self.shared.clock.now();
+        let mut stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let health = stats_map.entry(record.model_id.clone()).or_insert_with(|| HealthStats::new(&record.model_id, now));
+        let remaining = record.open_until
+            .and_then(|until| (until - Utc::now()).to_std().ok())
+            .unwrap_or(Duration::ZERO);
+        let (state, open_until) = match record.state {
+            CircuitState::Closed => (CircuitState::Closed, None),
+            _ if record.forced_open => (CircuitState::Open, None),
+            CircuitState::Open => (CircuitState::Open, Some(now + remaining)),
+            CircuitState::HalfOpen => (CircuitState::Open, Some(now)),
+        };
+        health.transition(&record.model_id, state);
+        health.changed = false;
+        health.forced_open = record.forced_open && state == CircuitState::Open;
+        health.trips = record.consecutive_trips;
+        health.open_until = open_until;
+        health.generation += 1;
+        health.probes_in_flight = 0;
+        health.probe_successes = 0;
+        if state == CircuitState::Closed {
+            health.closed_since = now;
+        }
+        health.reset_window();
+    }
 
-            if health.failure_count >= self.failure_threshold {
-                if health.state != CircuitState::Open {
-                    health.state = CircuitState::Open;
-                    tracing::warn!("模型 {} 触发熔断 (Open)，当前失败数: {}", model_id, health.failure_count);
+    /// 申请调用许可，熔断中或半开探测名额已满时返回 None
+    /// 主动健康检查接管期间，熔断中的模型不放行用户请求，由健康检查负责探测恢复。
+    pub fn try_acquire(&self, model_id: &str, config: &BreakerConfig) -> Option<BreakerPermit> {
+        self.acquire(model_id, config, None)
+    }
+
+    /// 为主动健康检查申请许可
+    /// 实现逻辑: 在 `lease` 时长内由健康检查接管该模型的半开探测，用户请求不再充当探测流量；
+    /// 健康检查停止后租约到期，恢复由用户请求探测。
+    pub fn try_acquire_check(&self, model_id: &str, config: &BreakerConfig, lease: Duration) -> Option<BreakerPermit> {
+        self.acquire(model_id, config, Some(lease))
+    }
+
+    fn acquire(&self, model_id: &str, config: &BreakerConfig, check_lease: Option<Duration>) -> Option<BreakerPermit> {
+        let now = self.shared.clock.now();
+        let mut stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let health = stats_map.entry(model_id.to_string()).or_insert_with(|| HealthStats::new(model_id, now));
+
+        match check_lease {
+            Some(lease) => health.checks_until = Some(now + lease),
+            None if health.state != CircuitState::Closed && health.checks_until.is_some_and(|until| now < until) => {
+                return None;
+            }
+            None => {}
+        }
+
+        if health.state == CircuitState::Open {
+            if health.open_until.is_none_or(|until| now < until) {
+                return None;
+            }
+            // 熔断时间到，进入探测期
+            health.transition(model_id, CircuitState::HalfOpen);
+            health.generation += 1;
+            health.probes_in_flight = 0;
+            health.probe_successes = 0;
+            self.shared.publish(model_id, health, now);
+            tracing::info!("模型 {} 进入半开 (Half-Open) 状态", model_id);
+        }
+
+        let probe = match health.state {
+            CircuitState::HalfOpen => {
+                if health.probes_in_flight >= config.half_open_probes.max(1) {
+                    return None;
                 }
+                health.probes_in_flight += 1;
+                Some(health.generation)
             }
+            _ => None,
+        };
+        Some(BreakerPermit { shared: Arc::clone(&self.shared), model_id: model_id.to_string(), probe, settled: false })
+    }
+
+    /// 检查指定模型是否允许访问 (按默认参数申请许可；半开期间占用的探测名额由 `report_result` 归还)
+    pub async fn is_allowed(&self, model_id: &str) -> bool {
+        match self.try_acquire(model_id, &self.defaults) {
+            Some(mut permit) => {
+                permit.settled = true;
+                true
+            }
+            None => false,
         }
     }
+
+    /// 按默认参数上报执行结果 (不统计耗时；半开期间视为本轮探测的结果)
+    pub async fn report_result(&self, model_id: &str, is_success: bool) {
+        let probe = {
+            let stats_map = self.shared.stats.lock().unwrap_or_else(|e| e.into_inner());
+            stats_map.get(model_id).and_then(HealthStats::probe_generation)
+        };
+        self.shared.settle(model_id, probe, &self.defaults, is_success, Duration::ZERO);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
 
     #[tokio::test]
     async fn test_circuit_breaker_transitions() {
@@ -149,5 +710,307 @@ mod tests {
         cb.report_result(model, false).await;
         assert!(cb.is_allowed(model).await);
     }
-}
 
+    fn window_config() -> BreakerConfig {
+        BreakerConfig {
+            window: Duration::from_secs(10),
+            min_requests: 4,
+            error_rate: 0.5,
+            slow_call: Some(Duration::from_millis(500)),
+            slow_call_rate: 0.75,
+            open_duration: Duration::from_secs(5),
+            max_open_duration: Duration::from_secs(15),
+            half_open_probes: 2,
+            half_open_successes: 3,
+        }
+    }
+
+    fn manual_breaker() -> (CircuitBreaker, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        (CircuitBreaker::with_clock(window_config(), clock.clone()), clock)
+    }
+
+    /// 申请许可并立即上报结果，返回是否获得许可
+    fn call(cb: &CircuitBreaker, model: &str, config: &BreakerConfig, is_success: bool, latency: Duration) -> bool {
+        match cb.try_acquire(model, config) {
+            Some(permit) => {
+                permit.record(config, is_success, latency);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 连续失败直至熔断
+    fn trip(cb: &CircuitBreaker, model: &str, config: &BreakerConfig) {
+        for _ in 0..config.min_requests {
+            call(cb, model, config, false, Duration::ZERO);
+        }
+        assert_eq!(cb.state(model), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_sliding_window() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "window-model";
+
+        // 1. 失败分散在多个窗口之外，不累计
+        for _ in 0..3 {
+            call(&cb, model, &config, false, Duration::ZERO);
+            clock.advance(Duration::from_secs(11));
+        }
+        call(&cb, model, &config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed);
+
+        // 2. 未达到最少请求数时不熔断；失败率低于阈值时不熔断
+        call(&cb, model, &config, true, Duration::ZERO);
+        call(&cb, model, &config, true, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed);
+        call(&cb, model, &config, true, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed); // 4 次中失败 1 次
+
+        // 3. 旧桶随时间滚出窗口: 9s 后窗口内 5 次中失败 2 次；再过 2s 前 4 次滚出，只剩 1 次失败
+        clock.advance(Duration::from_secs(9));
+        call(&cb, model, &config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed);
+        clock.advance(Duration::from_secs(2));
+        call(&cb, model, &config, false, Duration::ZERO);
+        call(&cb, model, &config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed); // 窗口内 3 次，未达到最少请求数
+        call(&cb, model, &config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Open);
+        assert!(cb.try_acquire(model, &config).is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker_slow_calls() {
+        let (cb, _clock) = manual_breaker();
+        let config = window_config();
+        let model = "slow-model";
+
+        // 成功但耗时超过阈值的调用按慢调用率熔断
+        let slow = Duration::from_millis(800);
+        call(&cb, model, &config, true, Duration::ZERO);
+        call(&cb, model, &config, true, slow);
+        call(&cb, model, &config, true, slow);
+        assert_eq!(cb.state(model), CircuitState::Closed); // 未达到最少请求数
+        call(&cb, model, &config, true, slow);
+        assert_eq!(cb.state(model), CircuitState::Open); // 4 次中慢调用 3 次
+
+        // 未配置慢调用阈值时只看失败率
+        let no_slow = BreakerConfig { slow_call: None, ..window_config() };
+        for _ in 0..6 {
+            call(&cb, "fast-model", &no_slow, true, slow);
+        }
+        assert_eq!(cb.state("fast-model"), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_backoff() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "backoff-model";
+        trip(&cb, model, &config);
+
+        // 1. 首次熔断 5s，探测失败后熔断时长翻倍为 10s
+        clock.advance(Duration::from_secs(5));
+        assert!(call(&cb, model, &config, false, Duration::ZERO));
+        clock.advance(Duration::from_secs(9));
+        assert!(cb.try_acquire(model, &config).is_none());
+        clock.advance(Duration::from_secs(1));
+
+        // 2. 再次探测失败，熔断时长不超过上限 15s
+        assert!(call(&cb, model, &config, false, Duration::ZERO));
+        clock.advance(Duration::from_secs(14));
+        assert!(cb.try_acquire(model, &config).is_none());
+        clock.advance(Duration::from_secs(1));
+
+        // 3. 探测全部成功后闭合；稳定闭合超过上限后再次熔断，退避清零
+        for _ in 0..config.half_open_successes {
+            assert!(call(&cb, model, &config, true, Duration::ZERO));
+        }
+        assert_eq!(cb.state(model), CircuitState::Closed);
+        clock.advance(Duration::from_secs(15));
+        trip(&cb, model, &config);
+        clock.advance(Duration::from_secs(5));
+        assert!(cb.try_acquire(model, &config).is_some());
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_probe_permits() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "probe-model";
+        trip(&cb, model, &config);
+        clock.advance(Duration::from_secs(5));
+
+        // 1. 半开期间最多同时放行 2 个探测，其余请求拒绝
+        let first = cb.try_acquire(model, &config).unwrap();
+        let second = cb.try_acquire(model, &config).unwrap();
+        assert!(first.is_probe() && second.is_probe());
+        assert_eq!(cb.state(model), CircuitState::HalfOpen);
+        assert!(cb.try_acquire(model, &config).is_none());
+
+        // 2. 未上报即丢弃的探测归还名额，不计入结果
+        drop(second);
+        let third = cb.try_acquire(model, &config).unwrap();
+        assert!(cb.try_acquire(model, &config).is_none());
+
+        // 3. 探测成功释放名额，但未达到连续成功次数前保持半开
+        first.record(&config, true, Duration::ZERO);
+        third.record(&config, true, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::HalfOpen);
+        let last = cb.try_acquire(model, &config).unwrap();
+        last.record(&config, true, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Closed);
+
+        // 4. 闭合后不再限制并发
+        let permits: Vec<_> = (0..5).map(|_| cb.try_acquire(model, &config).unwrap()).collect();
+        assert!(permits.iter().all(|p| !p.is_probe()));
+    }
+
+    #[test]
+    fn test_circuit_breaker_probe_failure_reopens() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "reopen-model";
+
+        // 熔断前发出的请求在半开期间才返回，不影响探测
+        let stale = cb.try_acquire(model, &config).unwrap();
+        trip(&cb, model, &config);
+        clock.advance(Duration::from_secs(5));
+        let probe = cb.try_acquire(model, &config).unwrap();
+        let other = cb.try_acquire(model, &config).unwrap();
+        stale.record(&config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::HalfOpen);
+
+        // 1. 已有成功探测时，任一探测失败仍立即重新熔断
+        probe.record(&config, true, Duration::ZERO);
+        other.record(&config, false, Duration::ZERO);
+        assert_eq!(cb.state(model), CircuitState::Open);
+        assert!(cb.try_acquire(model, &config).is_none());
+
+        // 2. 慢调用探测同样重新熔断 (第 2 次熔断，时长 10s)；上一轮的许可在新一轮中无效
+        clock.advance(Duration::from_secs(10));
+        let slow = cb.try_acquire(model, &config).unwrap();
+        slow.record(&config, true, Duration::from_secs(1));
+        assert_eq!(cb.state(model), CircuitState::Open);
+        clock.advance(Duration::from_secs(14));
+        assert!(cb.try_acquire(model, &config).is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(cb.try_acquire(model, &config).is_some());
+    }
+
+    #[test]
+    fn test_circuit_breaker_health_check_lease() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "checked-model";
+        let lease = Duration::from_secs(20);
+
+        // 1. 闭合时健康检查与用户请求都可获得许可
+        assert!(cb.try_acquire_check(model, &config, lease).is_some());
+        assert!(cb.try_acquire(model, &config).is_some());
+
+        // 2. 熔断到期后，租约内用户请求不触发半开，探测由健康检查发出
+        trip(&cb, model, &config);
+        clock.advance(Duration::from_secs(5));
+        assert!(cb.try_acquire(model, &config).is_none());
+        assert_eq!(cb.state(model), CircuitState::Open);
+        let probe = cb.try_acquire_check(model, &config, lease).unwrap();
+        assert!(probe.is_probe());
+        assert!(cb.try_acquire(model, &config).is_none());
+        drop(probe);
+
+        // 3. 租约到期 (健康检查停止) 后恢复由用户请求探测
+        clock.advance(lease);
+        assert!(cb.try_acquire(model, &config).unwrap().is_probe());
+    }
+
+    #[test]
+    fn test_circuit_breaker_manual_overrides() {
+        let (cb, clock) = manual_breaker();
+        let config = window_config();
+        let model = "managed-model";
+
+        // 1. 快照统计窗口与累计计数
+        call(&cb, model, &config, true, Duration::ZERO);
+        call(&cb, model, &config, false, Duration::ZERO);
+        call(&cb, model, &config, true, Duration::from_secs(1));
+        let snapshot = cb.snapshot(model);
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!((snapshot.window_requests, snapshot.window_failures, snapshot.window_slow_calls), (3, 1, 1));
+        assert_eq!((snapshot.successes_total, snapshot.failures_total), (2, 1));
+        assert!(snapshot.last_success_at.is_some() && snapshot.last_failure_at.is_some());
+        assert_eq!(cb.snapshot("unknown-model").state, CircuitState::Closed);
+
+        // 2. 强制熔断后不会自动进入半开，健康检查也不放行
+        cb.force_open(model);
+        clock.advance(Duration::from_secs(60));
+        assert!(cb.try_acquire(model, &config).is_none());
+        assert!(cb.try_acquire_check(model, &config, Duration::from_secs(20)).is_none());
+        let snapshot = cb.snapshot(model);
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert!(snapshot.forced_open);
+        assert_eq!(snapshot.open_remaining_ms, None);
+
+        // 3. 强制闭合立即放行，并清空退避计数
+        cb.force_close(model);
+        assert!(call(&cb, model, &config, true, Duration::ZERO));
+        let snapshot = cb.snapshot(model);
+        assert!(!snapshot.forced_open);
+        assert_eq!(snapshot.consecutive_trips, 0);
+        assert_eq!(snapshot.window_requests, 1);
+
+        // 4. 重置清除熔断状态与累计计数
+        trip(&cb, model, &config);
+        cb.reset(model);
+        let snapshot = cb.snapshot(model);
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!((snapshot.successes_total, snapshot.failures_total), (0, 0));
+        assert!(snapshot.last_failure_at.is_none());
+        assert!(cb.try_acquire(model, &config).is_some());
+        assert_eq!(cb.snapshots().len(), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_transitions_restore() {
+        let (cb, _clock) = manual_breaker();
+        let config = window_config();
+        let model = "persisted-model";
+        let mut transitions = cb.subscribe_transitions().unwrap();
+        assert!(cb.subscribe_transitions().is_none());
+
+        // 1. 状态转换发布记录，窗口内的普通调用不发布
+        call(&cb, model, &config, true, Duration::ZERO);
+        assert!(transitions.try_recv().is_err());
+        trip(&cb, model, &config);
+        let record = transitions.try_recv().unwrap();
+        assert_eq!((record.state, record.consecutive_trips, record.forced_open), (CircuitState::Open, 1, false));
+        let remaining = (record.open_until.unwrap() - Utc::now()).num_milliseconds();
+        assert!(remaining > 4000 && remaining <= 5000);
+        cb.force_open(model);
+        let forced = transitions.try_recv().unwrap();
+        assert_eq!((forced.state, forced.forced_open, forced.open_until), (CircuitState::Open, true, None));
+
+        // 2. 在新的断路器上恢复: 熔断剩余时长与退避次数延续，恢复本身不发布
+        let (restored, restored_clock) = manual_breaker();
+        let mut restored_transitions = restored.subscribe_transitions().unwrap();
+        restored.restore(&record);
+        assert!(restored_transitions.try_recv().is_err());
+        assert!(restored.try_acquire(model, &config).is_none());
+        restored_clock.advance(Duration::from_secs(5));
+        assert!(restored.try_acquire(model, &config).unwrap().is_probe());
+        assert_eq!(restored_transitions.try_recv().unwrap().state, CircuitState::HalfOpen);
+        assert_eq!(restored.snapshot(model).consecutive_trips, 1);
+
+        // 3. 强制熔断与半开状态的恢复
+        restored.restore(&forced);
+        restored_clock.advance(Duration::from_secs(60));
+        assert!(restored.try_acquire(model, &config).is_none());
+        assert!(restored.snapshot(model).forced_open);
+        restored.restore(&CircuitRecord { state: CircuitState::HalfOpen, forced_open: false, open_until: None, ..record.clone() });
+        assert!(restored.try_acquire(model, &config).unwrap().is_probe());
+    }
+}
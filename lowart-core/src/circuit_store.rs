@@ -0,0 +1,126 @@
+use chrono::Utc;
+use db::{CircuitRepo, CircuitStateRecord, DbConnection};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use utils::Result;
+
+use crate::circuit_breaker::{CircuitBreaker, CircuitRecord, CircuitState};
+
+/// 断路器状态持久化与多实例同步
+/// 实现原理: 断路器的状态转换经通道交给后台任务按顺序写入 `circuit_states` (不阻塞请求)，
+/// 进程启动时按表中状态恢复，重启后不会立即把全部流量发往仍在故障的上游。
+/// 多个实例共用同一数据库时，定期按 seq 增量拉取其他实例写入的转换并应用到本地 (后写入者为准)。
+pub struct CircuitStore {
+    db: Arc<DbConnection>,
+    breaker: Arc<CircuitBreaker>,
+    instance_id: String,
+    last_seq: AtomicI64, // 已读取到的最大 seq
+}
+
+impl CircuitStore {
+    pub fn new(db: Arc<DbConnection>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self {
+            db,
+            breaker,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            last_seq: AtomicI64::new(0),
+        }
+    }
+
+    /// 本实例标识 (写入的状态携带该标识，同步时跳过自身的写入)
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// 从数据库恢复全部模型的断路器状态，返回恢复为非闭合状态的模型数
+    pub async fn restore(&self) -> Result<usize> {
+        let records = CircuitRepo::new(&self.db).list_since(0).await?;
+        let mut restored = 0;
+        for record in &records {
+            self.last_seq.fetch_max(record.seq, Ordering::SeqCst);
+            if let Some(record) = to_record(record) {
+                if record.state != CircuitState::Closed {
+                    tracing::info!("恢复模型 {} 的断路器状态: {:?}", record.model_id, record.state);
+                    restored += 1;
+                }
+                self.breaker.restore(&record);
+            }
+        }
+        Ok(restored)
+    }
+
+    /// 启动落库任务 (需在 Tokio 运行时内调用；每个断路器只能启动一次)
+    pub fn spawn_writer(&self) {
+        let Some(mut rx) = self.breaker.subscribe_transitions() else {
+            tracing::warn!("断路器状态已在持久化，忽略重复启动");
+            return;
+        };
+        let db = Arc::clone(&self.db);
+        let instance_id = self.instance_id.clone();
+        tokio::spawn(async move {
+            while let Some(record) = rx.recv().await {
+                let row = CircuitStateRecord {
+                    model_id: record.model_id,
+                    state: record.state.label().to_string(),
+                    forced_open: record.forced_open,
+                    consecutive_trips: record.consecutive_trips as i64,
+                    open_until: record.open_until,
+                    instance_id: instance_id.clone(),
+                    seq: 0,
+                    updated_at: Utc::now(),
+                };
+                if let Err(e) = CircuitRepo::new(&db).upsert(&row).await {
+                    tracing::error!("断路器状态落库失败: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 拉取其他实例写入的状态转换并应用，返回应用数量
+    pub async fn sync(&self) -> Result<usize> {
+        let records = CircuitRepo::new(&self.db).list_since(self.last_seq.load(Ordering::SeqCst)).await?;
+        let mut applied = 0;
+        for record in &records {
+            self.last_seq.fetch_max(record.seq, Ordering::SeqCst);
+            if record.instance_id == self.instance_id {
+                continue;
+            }
+            if let Some(record) = to_record(record) {
+                tracing::info!("同步其他实例的断路器状态: 模型 {} -> {:?}", record.model_id, record.state);
+                self.breaker.restore(&record);
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// 启动后台定期同步
+    pub fn spawn_sync(self: &Arc<Self>, interval: Duration) {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = store.sync().await {
+                    tracing::error!("同步断路器状态失败: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn to_record(row: &CircuitStateRecord) -> Option<CircuitRecord> {
+    let Some(state) = CircuitState::from_label(&row.state) else {
+        tracing::warn!("忽略模型 {} 的无效断路器状态: {}", row.model_id, row.state);
+        return None;
+    };
+    Some(CircuitRecord {
+        model_id: row.model_id.clone(),
+        state,
+        forced_open: row.forced_open,
+        consecutive_trips: row.consecutive_trips.clamp(0, u32::MAX as i64) as u32,
+        open_until: row.open_until,
+    })
+}
@@ -0,0 +1,301 @@
+use metrics::{counter, gauge, histogram};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// 未配置队列长度时的默认上限
+pub const DEFAULT_MAX_QUEUE: usize = 100;
+/// 未配置排队超时时的默认值
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 单个模型的并发与排队参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConcurrencyConfig {
+    pub max_in_flight: usize,
+    pub max_queue: usize,
+    pub queue_timeout: Duration,
+}
+
+/// 排队失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum QueueError {
+    #[error("模型并发已满且排队队列已满")]
+    QueueFull,
+    #[error("排队等待超时")]
+    Timeout,
+}
+
+impl QueueError {
+    fn reason(&self) -> &'static str {
+        match self {
+            Self::QueueFull => "queue_full",
+            Self::Timeout => "timeout",
+        }
+    }
+}
+
+/// 排队顺序: 优先级高者在前；同一优先级内按用户轮转 (每个用户的第 n 个请求排在第 n 轮)，同轮按到达顺序
+type QueueKey = (Reverse<i64>, u64, u64);
+
+struct Waiter {
+    user_id: String,
+    tx: oneshot::Sender<()>,
+}
+
+struct SlotState {
+    in_flight: usize,
+    queue: BTreeMap<QueueKey, Waiter>,
+    queued_per_user: HashMap<String, u64>,
+    served_round: u64,
+    next_seq: u64,
+}
+
+impl SlotState {
+    fn remove(&mut self, key: &QueueKey) -> Option<Waiter> {
+        let waiter = self.queue.remove(key)?;
+        if let Some(n) = self.queued_per_user.get_mut(&waiter.user_id) {
+            *n -= 1;
+            if *n == 0 {
+                self.queued_per_user.remove(&waiter.user_id);
+            }
+        }
+        Some(waiter)
+    }
+}
+
+/// 单个模型的并发槽位与公平排队队列
+/// 实现原理: 在途请求数未达上限时直接放行；否则进入有界队列等待，槽位释放时直接移交给队首请求
+/// (在途数不变)，队首已取消的请求被跳过。排队超过超时时间的请求被移出队列并返回错误。
+pub struct ModelSlots {
+    model_id: String,
+    state: Mutex<SlotState>,
+}
+
+impl ModelSlots {
+    pub fn new(model_id: &str) -> Self {
+        Self {
+            model_id: model_id.to_string(),
+            state: Mutex::new(SlotState {
+                in_flight: 0,
+                queue: BTreeMap::new(),
+                queued_per_user: HashMap::new(),
+                served_round: 0,
+                next_seq: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SlotState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 获取一个槽位，必要时按优先级排队等待 (参数每次传入，配置热更新后立即生效)
+    pub async fn acquire(self: &Arc<Self>, user_id: &str, priority: i64, config: ConcurrencyConfig) -> Result<SlotPermit, QueueError> {
+        let started = Instant::now();
+        let (key, rx) = {
+            let mut state = self.lock();
+            if state.in_flight < config.max_in_flight && state.queue.is_empty() {
+                state.in_flight += 1;
+                self.report(&state);
+                return Ok(SlotPermit { slots: Arc::clone(self) });
+            }
+            if state.queue.len() >= config.max_queue {
+                drop(state);
+                return Err(self.reject(QueueError::QueueFull));
+            }
+
+            let queued = state.queued_per_user.entry(user_id.to_string()).or_insert(0);
+            let round = *queued;
+            *queued += 1;
+            let key = (Reverse(priority), state.served_round + round, state.next_seq);
+            state.next_seq += 1;
+            let (tx, rx) = oneshot::channel();
+            state.queue.insert(key, Waiter { user_id: user_id.to_string(), tx });
+            self.report(&state);
+            (key, rx)
+        };
+
+        let mut ticket = QueueTicket { slots: Arc::clone(self), key, rx: Some(rx) };
+        let result = tokio::time::timeout(config.queue_timeout, ticket.rx.as_mut().expect("rx")).await;
+        histogram!("gateway_model_queue_wait_seconds", "model" => self.model_id.clone())
+            .record(started.elapsed().as_secs_f64());
+        match result {
+            Ok(Ok(())) => {
+                ticket.rx = None;
+                Ok(SlotPermit { slots: Arc::clone(self) })
+            }
+            _ => match ticket.cancel() {
+                // 超时的同时恰好被移交了槽位
+                true => Ok(SlotPermit { slots: Arc::clone(self) }),
+                false => Err(self.reject(QueueError::Timeout)),
+            },
+        }
+    }
+
+    /// 释放槽位: 移交给队首仍在等待的请求，没有等待者时在途数减一
+    fn release(&self) {
+        let mut state = self.lock();
+        while let Some(key) = state.queue.keys().next().copied() {
+            let Some(waiter) = state.remove(&key) else { break };
+            state.served_round = state.served_round.max(key.1);
+            if waiter.tx.send(()).is_ok() {
+                self.report(&state);
+                return;
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+        self.report(&state);
+    }
+
+    fn reject(&self, error: QueueError) -> QueueError {
+        counter!("gateway_model_queue_rejected_total", "model" => self.model_id.clone(), "reason" => error.reason())
+            .increment(1);
+        error
+    }
+
+    fn report(&self, state: &SlotState) {
+        gauge!("gateway_model_queue_depth", "model" => self.model_id.clone()).set(state.queue.len() as f64);
+        gauge!("gateway_model_in_flight", "model" => self.model_id.clone()).set(state.in_flight as f64);
+    }
+
+    /// 当前 (在途数, 排队数)
+    pub fn snapshot(&self) -> (usize, usize) {
+        let state = self.lock();
+        (state.in_flight, state.queue.len())
+    }
+}
+
+/// 排队中的请求；被取消 (超时或请求中断) 时移出队列，若槽位已被移交则归还
+struct QueueTicket {
+    slots: Arc<ModelSlots>,
+    key: QueueKey,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl QueueTicket {
+    /// 取消排队，返回是否已被移交槽位
+    fn cancel(&mut self) -> bool {
+        let Some(mut rx) = self.rx.take() else {
+            return false;
+        };
+        let mut state = self.slots.lock();
+        if state.remove(&self.key).is_some() {
+            self.slots.report(&state);
+            return false;
+        }
+        rx.try_recv().is_ok()
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if self.cancel() {
+            self.slots.release();
+        }
+    }
+}
+
+/// 已获取的槽位，释放时移交给下一个排队请求
+pub struct SlotPermit {
+    slots: Arc<ModelSlots>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        self.slots.release();
+    }
+}
+
+/// 模型调用许可 (未限制并发的模型不占用槽位)，随请求或流式响应持有至调用结束
+pub struct ModelPermit {
+    _slot: Option<SlotPermit>,
+}
+
+impl ModelPermit {
+    pub fn unlimited() -> Self {
+        Self { _slot: None }
+    }
+
+    pub fn slot(permit: SlotPermit) -> Self {
+        Self { _slot: Some(permit) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_in_flight: usize, max_queue: usize, timeout_ms: u64) -> ConcurrencyConfig {
+        ConcurrencyConfig { max_in_flight, max_queue, queue_timeout: Duration::from_millis(timeout_ms) }
+    }
+
+    #[tokio::test]
+    async fn test_queue_bound_and_timeout() {
+        let slots = Arc::new(ModelSlots::new("m"));
+        let held = slots.acquire("u1", 0, config(1, 1, 50)).await.unwrap();
+
+        let waiting = {
+            let slots = Arc::clone(&slots);
+            tokio::spawn(async move { slots.acquire("u2", 0, config(1, 1, 50)).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(slots.snapshot(), (1, 1));
+        assert_eq!(slots.acquire("u3", 0, config(1, 1, 50)).await.err(), Some(QueueError::QueueFull));
+
+        assert_eq!(waiting.await.unwrap(), Err(QueueError::Timeout));
+        assert_eq!(slots.snapshot(), (1, 0));
+        drop(held);
+        assert_eq!(slots.snapshot(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_priority_and_fair_order() {
+        let cfg = config(1, 10, 1000);
+        let slots = Arc::new(ModelSlots::new("m"));
+        let held = slots.acquire("first", 0, cfg).await.unwrap();
+
+        // 同一用户连续排队 3 个请求，随后其他用户与高优先级用户各排 1 个
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for (user, priority) in [("a", 0), ("a", 0), ("a", 0), ("b", 0), ("vip", 10)] {
+            let slots = Arc::clone(&slots);
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _permit = slots.acquire(user, priority, cfg).await.unwrap();
+                order_tx.send(user).unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            });
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        drop(order_tx);
+        drop(held);
+
+        let mut order = Vec::new();
+        while let Some(user) = order_rx.recv().await {
+            order.push(user);
+        }
+        assert_eq!(order, vec!["vip", "a", "b", "a", "a"]);
+        assert_eq!(slots.snapshot(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_is_skipped() {
+        let cfg = config(1, 10, 1000);
+        let slots = Arc::new(ModelSlots::new("m"));
+        let held = slots.acquire("u1", 0, cfg).await.unwrap();
+
+        let cancelled = {
+            let slots = Arc::clone(&slots);
+            tokio::spawn(async move { slots.acquire("u2", 0, cfg).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancelled.abort();
+        let _ = cancelled.await;
+        assert_eq!(slots.snapshot(), (1, 0));
+
+        drop(held);
+        assert_eq!(slots.snapshot(), (0, 0));
+        assert!(slots.acquire("u3", 0, cfg).await.is_ok());
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use db::{ConfigRepo, HealthRepo, ModelConfig};
+use metrics::{gauge, histogram};
+use models::HealthProbe;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use utils::{anyhow, Result};
+
+use crate::circuit_breaker::{CircuitBreaker, CircuitState};
+use crate::model_manager::ModelManager;
+
+/// 主动健康检查参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthCheckConfig {
+    pub interval: Duration, // 检查间隔
+    pub timeout: Duration,  // 单次探测超时
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// 模型健康状态 (最近一次主动检查的结果)
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelHealth {
+    pub model_id: String,
+    pub healthy: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+    pub consecutive_failures: u32,
+    pub checked_at: DateTime<Utc>,
+    pub circuit: CircuitState, // 读取时的断路器状态
+}
+
+/// 解析模型配置中的探测方式 (None 表示不检查)
+pub fn parse_probe(spec: Option<&str>) -> Result<Option<HealthProbe>> {
+    match spec.map(str::trim) {
+        None | Some("") => Ok(Some(HealthProbe::Endpoint)),
+        Some("off") => Ok(None),
+        Some(payload) => serde_json::from_str::<serde_json::Value>(payload)
+            .ok()
+            .filter(|v| v.is_object())
+            .map(|v| Some(HealthProbe::Completion(v)))
+            .ok_or_else(|| anyhow!("无效的健康检查探测方式: 应为 off 或 JSON 对话请求体")),
+    }
+}
+
+/// 主动健康检查调度器
+/// 实现原理: 按间隔并发探测全部启用的模型，探测经断路器许可发出并上报结果:
+/// 1. Closed 状态下探测结果计入滑动窗口，故障可在用户请求失败前被发现。
+/// 2. 熔断期间由健康检查接管半开探测 (用户请求继续降级)，探测成功后立即连续探测直至闭合，
+///    恢复过程不消耗用户流量。
+/// 3. 熔断退避未结束时仍发出探测以更新健康状态，但结果不计入断路器。
+///
+/// 每次检查结果作为样本落库 (每个模型保留最近若干条)，进程重启后恢复最近一次的健康状态。
+pub struct HealthChecker {
+    model_manager: Arc<ModelManager>,
+    breaker: Arc<CircuitBreaker>,
+    config: HealthCheckConfig,
+    results: Mutex<HashMap<String, ModelHealth>>,
+}
+
+impl HealthChecker {
+    pub fn new(model_manager: Arc<ModelManager>, breaker: Arc<CircuitBreaker>, config: HealthCheckConfig) -> Self {
+        Self {
+            model_manager,
+            breaker,
+            config,
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 检查全部启用的模型 (关闭检查或已停用的模型从结果中移除)
+    pub async fn check_all(self: &Arc<Self>) -> Result<()> {
+        let configs = ConfigRepo::new(&self.model_manager.db()).list_active().await?;
+        let mut tasks = JoinSet::new();
+        for config in configs {
+            let probe = match parse_probe(config.health_probe.as_deref()) {
+                Ok(Some(probe)) => probe,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("模型 {} {}，使用厂商默认探测", config.model_id, e);
+                    HealthProbe::Endpoint
+                }
+            };
+            let checker = Arc::clone(self);
+            tasks.spawn(async move {
+                checker.check_model(&config, &probe).await;
+                config.model_id
+            });
+        }
+
+        let mut checked = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(model_id) => checked.push(model_id),
+                Err(e) => tracing::error!("健康检查任务异常: {}", e),
+            }
+        }
+        self.results.lock().unwrap_or_else(|e| e.into_inner()).retain(|model_id, _| checked.contains(model_id));
+        Ok(())
+    }
+
+    /// 探测单个模型并上报断路器，返回最新健康状态
+    pub async fn check_model(&self, config: &ModelConfig, probe: &HealthProbe) -> ModelHealth {
+        let model_id = config.model_id.as_str();
+        let breaker_config = self.breaker.config_for(Some(config));
+        // 租约覆盖两次检查，单次检查延迟不会让用户请求提前接管探测
+        let lease = self.config.interval * 2 + self.config.timeout;
+
+        let mut permit = self.breaker.try_acquire_check(model_id, &breaker_config, lease);
+        let mut rounds = 0;
+        let outcome = loop {
+            let outcome = self.probe(model_id, probe).await;
+            let Some(current) = permit.take() else { break outcome };
+            let is_probe = current.is_probe();
+            current.record(&breaker_config, outcome.0.is_ok(), outcome.1);
+            rounds += 1;
+            // 半开期间探测成功则继续探测，直至闭合、重新熔断或达到所需的成功次数
+            if !is_probe || outcome.0.is_err() || rounds >= breaker_config.half_open_successes
+                || self.breaker.state(model_id) != CircuitState::HalfOpen
+            {
+                break outcome;
+            }
+            permit = self.breaker.try_acquire_check(model_id, &breaker_config, lease);
+            if permit.is_none() {
+                break outcome;
+            }
+        };
+
+        let (result, latency) = outcome;
+        let health = self.record(model_id, result, latency);
+        let saved = HealthRepo::new(&self.model_manager.db()).insert(
+            model_id,
+            health.healthy,
+            health.latency_ms as i64,
+            health.error.as_deref(),
+            health.consecutive_failures as i64,
+            health.checked_at,
+        ).await;
+        if let Err(e) = saved {
+            tracing::error!("健康检查样本落库失败: {}", e);
+        }
+        health
+    }
+
+    /// 记录一次检查结果并更新指标
+    fn record(&self, model_id: &str, result: Result<()>, latency: Duration) -> ModelHealth {
+        let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        let failures = results.get(model_id).map_or(0, |h| h.consecutive_failures);
+        let health = ModelHealth {
+            model_id: model_id.to_string(),
+            healthy: result.is_ok(),
+            latency_ms: latency.as_millis() as u64,
+            consecutive_failures: if result.is_ok() { 0 } else { failures + 1 },
+            error: result.err().map(|e| e.to_string()),
+            checked_at: Utc::now(),
+            circuit: self.breaker.state(model_id),
+        };
+        if let Some(error) = &health.error {
+            tracing::warn!("模型 {} 健康检查失败 (连续 {} 次): {}", model_id, health.consecutive_failures, error);
+        }
+        gauge!("gateway_model_healthy", "model" => model_id.to_string()).set(if health.healthy { 1.0 } else { 0.0 });
+        histogram!("gateway_model_health_check_seconds", "model" => model_id.to_string()).record(latency.as_secs_f64());
+        results.insert(model_id.to_string(), health.clone());
+        health
+    }
+
+    /// 从落库的样本恢复各模型最近一次的健康状态，返回恢复的模型数
+    pub async fn restore(&self) -> Result<usize> {
+        let samples = HealthRepo::new(&self.model_manager.db()).latest_per_model().await?;
+        let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        for sample in &samples {
+            gauge!("gateway_model_healthy", "model" => sample.model_id.clone()).set(if sample.healthy { 1.0 } else { 0.0 });
+            results.entry(sample.model_id.clone()).or_insert_with(|| ModelHealth {
+                model_id: sample.model_id.clone(),
+                healthy: sample.healthy,
+                latency_ms: sample.latency_ms.max(0) as u64,
+                error: sample.error.clone(),
+                consecutive_failures: sample.consecutive_failures.max(0) as u32,
+                checked_at: sample.checked_at,
+                circuit: self.breaker.state(&sample.model_id),
+            });
+        }
+        Ok(samples.len())
+    }
+
+    /// 发出一次探测，返回结果与耗时
+    async fn probe(&self, model_id: &str, probe: &HealthProbe) -> (Result<()>, Duration) {
+        let started = Instant::now();
+        let result = match self.model_manager.get_model(model_id).await {
+            Ok(adapter) => match tokio::time::timeout(self.config.timeout, adapter.health_check(probe)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("探测超时 ({:?})", self.config.timeout)),
+            },
+            Err(e) => Err(e),
+        };
+        (result, started.elapsed())
+    }
+
+    /// 全部模型的最近健康状态 (按模型 ID 排序，断路器状态取当前值)
+    pub fn snapshot(&self) -> Vec<ModelHealth> {
+        let mut list: Vec<ModelHealth> = self.results.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect();
+        for health in &mut list {
+            health.circuit = self.breaker.state(&health.model_id);
+        }
+        list.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+        list
+    }
+
+    /// 启动后台定期检查
+    pub fn spawn(self: &Arc<Self>) {
+        let checker = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(checker.config.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = checker.check_all().await {
+                    tracing::error!("模型健康检查失败: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        assert_eq!(parse_probe(None).unwrap(), Some(HealthProbe::Endpoint));
+        assert_eq!(parse_probe(Some(" ")).unwrap(), Some(HealthProbe::Endpoint));
+        assert_eq!(parse_probe(Some("off")).unwrap(), None);
+        let payload = r#"{"model": "gpt-4o-mini", "messages": [{"role": "user", "content": "ping"}], "max_tokens": 1}"#;
+        assert!(matches!(parse_probe(Some(payload)).unwrap(), Some(HealthProbe::Completion(v)) if v["max_tokens"] == 1));
+        assert!(parse_probe(Some("[1, 2]")).is_err());
+        assert!(parse_probe(Some("ping")).is_err());
+    }
+}
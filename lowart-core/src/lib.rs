@@ -3,18 +3,37 @@ pub mod token_counter;
 pub mod rhai_engine;
 pub mod model_manager;
 pub mod circuit_breaker;
+pub mod circuit_store;
+pub mod clock;
+pub mod health;
 pub mod mcp_manager;
 pub mod agent_orchestrator;
+pub mod billing;
+pub mod quota;
+pub mod reservation;
+pub mod rate_limiter;
+pub mod tpm_limiter;
+pub mod concurrency;
+pub mod audit;
 
 
 pub use request_context::RequestContext;
 pub use token_counter::TokenCounter;
 pub use rhai_engine::RhaiEngine;
 pub use model_manager::ModelManager;
-pub use circuit_breaker::CircuitBreaker;
+pub use circuit_breaker::{BreakerConfig, BreakerPermit, CircuitBreaker, CircuitRecord, CircuitSnapshot, CircuitState};
+pub use circuit_store::CircuitStore;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use health::{HealthCheckConfig, HealthChecker, ModelHealth};
 pub use mcp_manager::McpManager;
 
 pub use agent_orchestrator::AgentOrchestrator;
+pub use billing::{BillingService, ModelPricing, TokenUsage, UsageRecord};
+pub use quota::{QuotaHold, QuotaService, QuotaUsage, QuotaViolation};
+pub use reservation::{QuotaReservations, Reservation};
+pub use rate_limiter::{BucketConfig, RateLimitDecision, RateLimiter};
+pub use tpm_limiter::TpmLimiter;
+pub use concurrency::{ModelPermit, QueueError};
 
 
 
@@ -1,10 +1,13 @@
-use std::sync::Arc;
-use db::{DbConnection, ConfigRepo};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use db::{DbConnection, ConfigRepo, ModelConfig, ChangeEvent};
 use moka::future::Cache;
 use std::time::Duration;
 
 use models::{AiModel, OpenAiAdapter, AnthropicAdapter, ComfyUiAdapter};
 use utils::{Result, anyhow};
+use crate::billing::ModelPricing;
+use crate::concurrency::{self, ConcurrencyConfig, ModelPermit, ModelSlots, QueueError};
 
 /// 模型管理器缓存项
 #[derive(Clone)]
@@ -12,33 +15,80 @@ struct ModelCacheItem {
     adapter: Arc<dyn AiModel>,
     request_script: Option<String>,
     response_script: Option<String>,
+    config: Arc<ModelConfig>,
 }
 
 /// 模型管理器
 /// 实现原理: 负责维护模型适配器的生命周期和缓存。
 /// 使用 moka 高性能缓存，支持过期自动清理，减少数据库压力和解密运算。
+/// 订阅模型配置变更事件，只淘汰被修改的配置对应的缓存项 (按配置 ID 匹配，改名前的 model_id 同样失效)。
 pub struct ModelManager {
     db: Arc<DbConnection>,
     // 聚合缓存: model_id -> (适配器, 转换脚本)
     cache: Cache<String, ModelCacheItem>,
+    // 并发槽位: model_id -> 槽位与排队队列 (不随配置缓存淘汰，保证在途计数连续)
+    slots: Mutex<HashMap<String, Arc<ModelSlots>>>,
 }
 
 impl ModelManager {
     pub fn new(db: Arc<DbConnection>) -> Self {
+        let cache: Cache<String, ModelCacheItem> = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(3600)) // 1小时过期
+            .support_invalidation_closures()
+            .build();
+        let watched = cache.clone();
+        db.changes.subscribe(move |event| {
+            if let ChangeEvent::Model(config_id) = event {
+                let config_id = config_id.clone();
+                if let Err(e) = watched.invalidate_entries_if(move |_, item| item.config.id == config_id) {
+                    tracing::warn!("按配置淘汰模型缓存失败，清空全部缓存: {}", e);
+                    watched.invalidate_all();
+                }
+            }
+        });
         Self {
             db,
-            cache: Cache::builder()
-                .max_capacity(100)
-                .time_to_live(Duration::from_secs(3600)) // 1小时过期
-                .build(),
+            cache,
+            slots: Mutex::new(HashMap::new()),
         }
     }
 
     /// 获取模型适配器及其转换脚本
     pub async fn get_model_with_scripts(&self, model_id: &str) -> Result<(Arc<dyn AiModel>, Option<String>, Option<String>)> {
+        let item = self.load(model_id).await?;
+        Ok((item.adapter, item.request_script, item.response_script))
+    }
+
+    /// 获取模型分项单价
+    pub async fn get_pricing(&self, model_id: &str) -> Result<ModelPricing> {
+        Ok(ModelPricing::from(self.load(model_id).await?.config.as_ref()))
+    }
+
+    /// 获取模型配置 (走缓存)
+    pub async fn get_config(&self, model_id: &str) -> Result<Arc<ModelConfig>> {
+        Ok(self.load(model_id).await?.config)
+    }
+
+    /// 获取模型调用许可
+    /// 实现逻辑: 模型配置了 max_concurrency 时占用一个并发槽位，槽位已满则按用户优先级公平排队，
+    /// 队列已满或等待超时返回错误；未配置时直接放行。许可需持有至上游调用 (含流式响应) 结束。
+    pub async fn acquire_slot(&self, model_id: &str, user_id: &str, priority: i64) -> std::result::Result<ModelPermit, QueueError> {
+        let Some(config) = self.get_config(model_id).await.ok().and_then(|c| concurrency_config(&c)) else {
+            return Ok(ModelPermit::unlimited());
+        };
+        let slots = {
+            let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(slots.entry(model_id.to_string()).or_insert_with(|| Arc::new(ModelSlots::new(model_id))))
+        };
+        slots.acquire(user_id, priority, config).await.map(ModelPermit::slot)
+    }
+
+    /// 加载缓存项 (缓存未命中时查库并实例化适配器)
+    async fn load(&self, model_id: &str) -> Result<ModelCacheItem> {
         // 1. 尝试从缓存获取
         if let Some(item) = self.cache.get(model_id).await {
-            return Ok((item.adapter, item.request_script, item.response_script));
+            return Ok(item);
         }
 
         // 2. 缓存未命中，查数据库获取完整配置
@@ -79,13 +129,14 @@ impl ModelManager {
 
         // 4. 写入缓存并返回
         let item = ModelCacheItem {
-            adapter: Arc::clone(&adapter),
-            request_script: request_script.clone(),
-            response_script: response_script.clone(),
+            adapter,
+            request_script,
+            response_script,
+            config: Arc::new(config),
         };
-        self.cache.insert(model_id.to_string(), item).await;
-        
-        Ok((adapter, request_script, response_script))
+        self.cache.insert(model_id.to_string(), item.clone()).await;
+
+        Ok(item)
     }
 
     /// 获取模型适配器 (向下兼容)
@@ -104,3 +155,14 @@ impl ModelManager {
         tracing::info!("模型管理器缓存已清除");
     }
 }
+
+/// 从模型配置读取并发参数 (未配置 max_concurrency 表示不限)
+fn concurrency_config(config: &ModelConfig) -> Option<ConcurrencyConfig> {
+    let max_in_flight = config.max_concurrency?.max(0) as usize;
+    Some(ConcurrencyConfig {
+        max_in_flight,
+        max_queue: config.max_queue.map_or(concurrency::DEFAULT_MAX_QUEUE, |q| q.max(0) as usize),
+        queue_timeout: config.queue_timeout_ms
+            .map_or(concurrency::DEFAULT_QUEUE_TIMEOUT, |ms| Duration::from_millis(ms.max(0) as u64)),
+    })
+}
@@ -0,0 +1,262 @@
+use crate::model_manager::ModelManager;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use db::{QuotaPolicy, QuotaRepo};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use utils::Result;
+
+/// 进行中请求对各策略的占用: (策略 ID, 用户 ID) -> (Token, 金额)
+type InFlight = Arc<Mutex<HashMap<(i64, String), (i64, i64)>>>;
+
+/// 已超限的配额策略
+#[derive(Debug, Clone)]
+pub struct QuotaViolation {
+    pub policy_id: i64,
+    pub period: String,
+    pub tokens_used: i64,
+    pub cost_used: i64,
+}
+
+/// 一次请求对周期配额的占用，随配额预占结算或丢弃时释放
+pub struct QuotaHold {
+    in_flight: InFlight,
+    user_id: String,
+    policies: Vec<i64>,
+    tokens: i64,
+    cost: i64,
+}
+
+impl Drop for QuotaHold {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        for policy_id in &self.policies {
+            let key = (*policy_id, self.user_id.clone());
+            if let Some((tokens, cost)) = in_flight.get_mut(&key) {
+                *tokens -= self.tokens;
+                *cost -= self.cost;
+                if *tokens <= 0 && *cost <= 0 {
+                    in_flight.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for QuotaHold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotaHold").field("policies", &self.policies).field("tokens", &self.tokens).finish()
+    }
+}
+
+/// 某条策略在当前窗口内的用量
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QuotaUsage {
+    pub policy: QuotaPolicy,
+    pub window_start: DateTime<Utc>,
+    pub tokens_used: i64,
+    pub cost_used: i64,
+}
+
+/// 周期配额服务
+/// 实现原理: 自然周期 (calendar) 按 UTC 日/周一/月初对齐，用量累加在计数器中并由后台任务滚动清零；
+/// 滚动窗口 (rolling) 取最近 1/7/30 天，用量直接从计费账本汇总。策略可限定到模型或模型分组。
+/// 放行时按预估用量占用匹配的策略 (进行中的占用计入已用量)，计费后释放，避免并发请求同时透支。
+pub struct QuotaService {
+    model_manager: Arc<ModelManager>,
+    in_flight: InFlight,
+}
+
+impl QuotaService {
+    pub fn new(model_manager: Arc<ModelManager>) -> Self {
+        Self { model_manager, in_flight: Arc::default() }
+    }
+
+    /// 按预估用量占用用户调用某模型时匹配的全部策略
+    /// 实现逻辑: 任一策略的已用量、进行中的占用与本次预估之和超过上限时返回该策略，否则在全部策略上记录占用。
+    /// 占用在返回的 `QuotaHold` 被丢弃时释放 (通常随配额预占在计费后结算)。
+    pub async fn reserve(&self, user_id: &str, model_id: &str, tokens: i64, cost: i64) -> Result<std::result::Result<QuotaHold, QuotaViolation>> {
+        let usages = self.usage_for_model(user_id, model_id, Utc::now()).await?;
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(violation) = first_violation(&usages, user_id, &in_flight, tokens, cost) {
+            return Ok(Err(violation));
+        }
+        let policies: Vec<i64> = usages.iter().map(|u| u.policy.id).collect();
+        for policy_id in &policies {
+            let entry = in_flight.entry((*policy_id, user_id.to_string())).or_default();
+            entry.0 += tokens;
+            entry.1 += cost;
+        }
+        Ok(Ok(QuotaHold {
+            in_flight: Arc::clone(&self.in_flight),
+            user_id: user_id.to_string(),
+            policies,
+            tokens,
+            cost,
+        }))
+    }
+
+    /// 累加一次请求的用量到所有生效的自然周期策略
+    pub async fn record(&self, user_id: &str, model_id: &str, tokens: i64, cost: i64) -> Result<()> {
+        let now = Utc::now();
+        let db = self.model_manager.db();
+        let repo = QuotaRepo::new(&db);
+        let group = self.model_group(model_id).await;
+        for policy in repo.applicable_policies(user_id, model_id, group.as_deref()).await? {
+            if policy.window_type == "calendar" {
+                repo.add_usage(policy.id, user_id, window_start(&policy, now), tokens, cost).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 查询用户所有策略在当前窗口内的用量
+    pub async fn usage(&self, user_id: &str) -> Result<Vec<QuotaUsage>> {
+        let now = Utc::now();
+        let db = self.model_manager.db();
+        let repo = QuotaRepo::new(&db);
+        let mut result = Vec::new();
+        for policy in repo.list_policies(Some(user_id)).await? {
+            result.push(self.window_usage(&repo, policy, user_id, now).await?);
+        }
+        Ok(result)
+    }
+
+    /// 将所有过期窗口的计数器滚动清零 (由后台任务定期调用)
+    pub async fn roll_over(&self) -> Result<u64> {
+        let now = Utc::now();
+        let db = self.model_manager.db();
+        let repo = QuotaRepo::new(&db);
+        let mut total = 0;
+        for period in ["daily", "weekly", "monthly"] {
+            total += repo.roll_over(period, calendar_start(period, now)).await?;
+        }
+        if total > 0 {
+            tracing::info!("已重置 {} 个配额计数器", total);
+        }
+        Ok(total)
+    }
+
+    async fn usage_for_model(&self, user_id: &str, model_id: &str, now: DateTime<Utc>) -> Result<Vec<QuotaUsage>> {
+        let db = self.model_manager.db();
+        let repo = QuotaRepo::new(&db);
+        let group = self.model_group(model_id).await;
+        let mut result = Vec::new();
+        for policy in repo.applicable_policies(user_id, model_id, group.as_deref()).await? {
+            result.push(self.window_usage(&repo, policy, user_id, now).await?);
+        }
+        Ok(result)
+    }
+
+    async fn window_usage(&self, repo: &QuotaRepo<'_>, policy: QuotaPolicy, user_id: &str, now: DateTime<Utc>) -> Result<QuotaUsage> {
+        let start = window_start(&policy, now);
+        let (tokens_used, cost_used) = if policy.window_type == "rolling" {
+            repo.ledger_usage_since(&policy, user_id, start).await?
+        } else {
+            match repo.get_counter(policy.id, user_id).await? {
+                // 计数器仍停留在旧窗口说明本窗口尚无用量
+                Some(c) if c.window_start >= start => (c.tokens_used, c.cost_used),
+                _ => (0, 0),
+            }
+        };
+        Ok(QuotaUsage { policy, window_start: start, tokens_used, cost_used })
+    }
+
+    /// 模型不存在时不影响全局策略，仅无法匹配分组策略
+    async fn model_group(&self, model_id: &str) -> Option<String> {
+        match self.model_manager.get_config(model_id).await {
+            Ok(config) => config.model_group.clone(),
+            Err(_) => None,
+        }
+    }
+}
+
+/// 返回第一条容纳不下本次预估用量的策略 (已用量含进行中的占用)
+fn first_violation(usages: &[QuotaUsage], user_id: &str, in_flight: &HashMap<(i64, String), (i64, i64)>, tokens: i64, cost: i64) -> Option<QuotaViolation> {
+    usages.iter().find_map(|usage| {
+        let policy = &usage.policy;
+        let (tokens_held, cost_held) = in_flight.get(&(policy.id, user_id.to_string())).copied().unwrap_or_default();
+        let tokens_used = usage.tokens_used + tokens_held;
+        let cost_used = usage.cost_used + cost_held;
+        let token_exceeded = policy.token_limit.is_some_and(|limit| tokens_used + tokens > limit);
+        let cost_exceeded = policy.cost_limit.is_some_and(|limit| cost_used + cost > limit);
+        (token_exceeded || cost_exceeded).then(|| QuotaViolation {
+            policy_id: policy.id,
+            period: policy.period.clone(),
+            tokens_used,
+            cost_used,
+        })
+    })
+}
+
+/// 计算策略在 `now` 时刻所处窗口的起点
+pub fn window_start(policy: &QuotaPolicy, now: DateTime<Utc>) -> DateTime<Utc> {
+    if policy.window_type == "rolling" {
+        let days = match policy.period.as_str() {
+            "weekly" => 7,
+            "monthly" => 30,
+            _ => 1,
+        };
+        now - Duration::days(days)
+    } else {
+        calendar_start(&policy.period, now)
+    }
+}
+
+/// 自然周期起点 (UTC): 当日零点 / 本周一零点 / 本月一日零点
+fn calendar_start(period: &str, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now.date_naive();
+    let date = match period {
+        "weekly" => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+        "monthly" => today.with_day(1).unwrap_or(today),
+        _ => today,
+    };
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(period: &str, window_type: &str) -> QuotaPolicy {
+        QuotaPolicy {
+            id: 1,
+            user_id: None,
+            scope_type: "all".to_string(),
+            scope_value: None,
+            period: period.to_string(),
+            window_type: window_type.to_string(),
+            token_limit: None,
+            cost_limit: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_window_start() {
+        // 2024-05-16 是周四
+        let now = Utc.with_ymd_and_hms(2024, 5, 16, 13, 45, 0).unwrap();
+        assert_eq!(window_start(&policy("daily", "calendar"), now), Utc.with_ymd_and_hms(2024, 5, 16, 0, 0, 0).unwrap());
+        assert_eq!(window_start(&policy("weekly", "calendar"), now), Utc.with_ymd_and_hms(2024, 5, 13, 0, 0, 0).unwrap());
+        assert_eq!(window_start(&policy("monthly", "calendar"), now), Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap());
+        assert_eq!(window_start(&policy("weekly", "rolling"), now), Utc.with_ymd_and_hms(2024, 5, 9, 13, 45, 0).unwrap());
+    }
+
+    #[test]
+    fn test_violation_includes_estimate() {
+        let usage = QuotaUsage {
+            policy: QuotaPolicy { token_limit: Some(1000), ..policy("daily", "calendar") },
+            window_start: Utc::now(),
+            tokens_used: 600,
+            cost_used: 0,
+        };
+        let mut in_flight = HashMap::new();
+        assert!(first_violation(std::slice::from_ref(&usage), "u", &in_flight, 400, 0).is_none());
+        assert!(first_violation(std::slice::from_ref(&usage), "u", &in_flight, 401, 0).is_some());
+
+        // 进行中的占用与本次预估一并计入
+        in_flight.insert((1, "u".to_string()), (300, 0));
+        assert!(first_violation(std::slice::from_ref(&usage), "u", &in_flight, 100, 0).is_none());
+        let violation = first_violation(std::slice::from_ref(&usage), "u", &in_flight, 101, 0).unwrap();
+        assert_eq!(violation.tokens_used, 900);
+    }
+}
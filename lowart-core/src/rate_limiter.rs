@@ -0,0 +1,219 @@
+use moka::future::Cache;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 限流键: 用户、API Key、模型 (模型的限制由全部调用方共享)、用户对某工具的调用
+pub fn user_scope(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+pub fn key_scope(key_id: i64) -> String {
+    format!("key:{}", key_id)
+}
+
+pub fn model_scope(model_id: &str) -> String {
+    format!("model:{}", model_id)
+}
+
+pub fn tool_scope(user_id: &str, tool_name: &str) -> String {
+    format!("tool:{}:{}", user_id, tool_name)
+}
+
+/// 令牌桶参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketConfig {
+    pub burst: f64,          // 桶容量 (允许的最大突发)
+    pub refill_per_sec: f64, // 每秒补充的令牌数
+}
+
+impl BucketConfig {
+    pub fn new(burst: f64, refill_per_sec: f64) -> Self {
+        Self { burst, refill_per_sec }
+    }
+
+    /// 按每分钟配额构造: 容量等于配额，匀速补充
+    pub fn per_minute(limit: i64) -> Self {
+        let limit = limit.max(0) as f64;
+        Self::new(limit, limit / 60.0)
+    }
+
+    /// 调整突发容量 (补充速率不变)
+    pub fn with_burst(self, burst: i64) -> Self {
+        Self { burst: burst.max(0) as f64, ..self }
+    }
+
+    /// 令牌从空桶补满所需时间
+    fn time_to_refill(&self, missing: f64) -> Duration {
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else if self.refill_per_sec <= 0.0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_sec)
+        }
+    }
+}
+
+/// 一次限流判断的结果
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: i64,            // 桶容量
+    pub remaining: i64,        // 判断后剩余的令牌数
+    pub retry_after: Duration, // 被拒绝时至少需等待的时间
+    pub reset_after: Duration, // 令牌补满所需时间
+}
+
+/// 单个令牌桶 (记录最近一次使用的参数，供修正时使用)
+#[derive(Debug)]
+struct Bucket {
+    config: BucketConfig,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(config: BucketConfig, now: Instant) -> Self {
+        Self { config, tokens: config.burst, updated: now }
+    }
+
+    fn refill(&mut self, config: BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.config = config;
+        self.tokens = (self.tokens + elapsed * config.refill_per_sec).min(config.burst);
+        self.updated = now;
+    }
+
+    /// 令牌足够时扣减并放行，否则不扣减
+    fn take(&mut self, config: BucketConfig, cost: f64, now: Instant) -> RateLimitDecision {
+        self.refill(config, now);
+        let allowed = self.tokens >= cost;
+        if allowed {
+            self.tokens -= cost;
+        }
+        RateLimitDecision {
+            allowed,
+            limit: config.burst as i64,
+            remaining: self.tokens.max(0.0).floor() as i64,
+            retry_after: if allowed { Duration::ZERO } else { config.time_to_refill(cost - self.tokens) },
+            reset_after: config.time_to_refill(config.burst - self.tokens),
+        }
+    }
+
+    /// 修正已扣减的令牌: 正数追加扣减 (允许欠账)，负数归还 (不超过容量)
+    fn adjust(&mut self, delta: f64, now: Instant) {
+        self.refill(self.config, now);
+        self.tokens = (self.tokens - delta).min(self.config.burst);
+    }
+}
+
+/// 令牌桶限流器
+/// 实现原理: 每个键 (用户、Key、模型、工具等) 一个令牌桶，按时间匀速补充令牌，容量决定允许的突发。
+/// 桶存放在 moka 缓存中，超过空闲时间未访问的桶被自动淘汰 (此时桶早已补满，淘汰不影响判断)，
+/// 键数量超过上限时按访问频率淘汰，内存占用有界。同一实例可为不同键使用不同的参数。
+pub struct RateLimiter {
+    buckets: Cache<String, Arc<Mutex<Bucket>>>,
+}
+
+impl RateLimiter {
+    /// `idle_timeout` 应不短于桶从空到满的时间，否则被淘汰的桶会提前恢复为满桶
+    pub fn new(max_keys: u64, idle_timeout: Duration) -> Self {
+        Self {
+            buckets: Cache::builder()
+                .max_capacity(max_keys)
+                .time_to_idle(idle_timeout)
+                .build(),
+        }
+    }
+
+    /// 尝试扣减 `cost` 个令牌
+    pub async fn acquire(&self, key: &str, config: BucketConfig, cost: f64) -> RateLimitDecision {
+        let now = Instant::now();
+        let bucket = self.buckets
+            .get_with_by_ref(key, async { Arc::new(Mutex::new(Bucket::new(config, now))) })
+            .await;
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.take(config, cost, now)
+    }
+
+    /// 按实际消耗修正 (delta 为实际值减去已扣减值)；不存在的桶 (未受限或已淘汰) 忽略
+    pub async fn adjust(&self, key: &str, delta: f64) {
+        if delta == 0.0 {
+            return;
+        }
+        if let Some(bucket) = self.buckets.get(key).await {
+            bucket.lock().unwrap_or_else(|e| e.into_inner()).adjust(delta, Instant::now());
+        }
+    }
+
+    /// 当前跟踪的键数量 (近似值)
+    pub fn len(&self) -> u64 {
+        self.buckets.entry_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_burst_and_refill() {
+        let start = Instant::now();
+        let config = BucketConfig::per_minute(60); // 容量 60，每秒补充 1 个
+        let mut bucket = Bucket::new(config, start);
+
+        for _ in 0..60 {
+            assert!(bucket.take(config, 1.0, start).allowed);
+        }
+        let decision = bucket.take(config, 1.0, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after, Duration::from_secs(1));
+
+        // 半分钟后只补充一半，不会出现固定窗口在分钟边界的双倍突发
+        let later = start + Duration::from_secs(30);
+        let decision = bucket.take(config, 30.0, later);
+        assert!(decision.allowed);
+        assert!(!bucket.take(config, 1.0, later).allowed);
+
+        // 补充不超过容量
+        let much_later = later + Duration::from_secs(3600);
+        let decision = bucket.take(config.with_burst(10), 1.0, much_later);
+        assert_eq!(decision.remaining, 9);
+    }
+
+    #[test]
+    fn test_bucket_adjust() {
+        let now = Instant::now();
+        let config = BucketConfig::per_minute(100);
+        let mut bucket = Bucket::new(config, now);
+        assert!(bucket.take(config, 50.0, now).allowed);
+
+        bucket.adjust(-20.0, now); // 实际用量低于预估，归还
+        assert_eq!(bucket.take(config, 0.0, now).remaining, 70);
+        bucket.adjust(100.0, now); // 超出预估，欠账需补充后才能再次放行
+        let decision = bucket.take(config, 1.0, now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        bucket.adjust(-1000.0, now);
+        assert_eq!(bucket.take(config, 0.0, now).remaining, 100);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_keys_are_independent() {
+        let limiter = RateLimiter::new(100, Duration::from_secs(60));
+        let config = BucketConfig::per_minute(1);
+        assert!(limiter.acquire("user:a", config, 1.0).await.allowed);
+        assert!(!limiter.acquire("user:a", config, 1.0).await.allowed);
+        assert!(limiter.acquire("user:b", config, 1.0).await.allowed);
+
+        limiter.adjust("user:a", -1.0).await;
+        assert!(limiter.acquire("user:a", config, 1.0).await.allowed);
+        limiter.adjust("user:unknown", 5.0).await;
+        assert!(limiter.buckets.get("user:unknown").await.is_none());
+    }
+}
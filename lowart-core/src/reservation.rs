@@ -0,0 +1,508 @@
+use crate::billing::{ModelPricing, TokenUsage};
+use crate::quota::QuotaHold;
+use crate::token_counter::TokenCounter;
+use chrono::{DateTime, Duration, Utc};
+use db::{ApiKeyRepo, DbConnection, OrgRepo, QuotaReservation, ReservationRepo, UserRepo};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, OnceCell};
+use utils::{Result, anyhow};
+
+/// 请求未指定 max_tokens 时按此输出上限预估
+pub const DEFAULT_MAX_OUTPUT_TOKENS: i64 = 4096;
+
+/// 计数加载后被并发失效时的最大重试次数
+const LOAD_ATTEMPTS: usize = 3;
+
+/// 预估一次请求的最大用量: 提示词 Token 数 + 输出上限
+pub fn estimate_usage(payload: &Value) -> TokenUsage {
+    let prompt_tokens = payload.get("messages")
+        .map(TokenCounter::count_messages_tokens)
+        .unwrap_or(0) as i64;
+    let max_tokens = payload.get("max_tokens")
+        .or_else(|| payload.get("max_completion_tokens"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS);
+    TokenUsage::new(prompt_tokens, max_tokens.max(0))
+}
+
+/// 配额作用域键: 用户自身配额、所属组织的共享预算、成员子限额、Key 消费上限
+fn user_scope(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+fn org_scope(org_id: &str) -> String {
+    format!("org:{}", org_id)
+}
+
+fn member_scope(user_id: &str) -> String {
+    format!("member:{}", user_id)
+}
+
+fn key_scope(key_id: i64) -> String {
+    format!("key:{}", key_id)
+}
+
+/// 单个作用域的内存计数 (上限为 None 表示不限)
+#[derive(Debug, Clone, Default)]
+struct QuotaCounter {
+    token_quota: Option<i64>,
+    token_used: i64,
+    tokens_reserved: i64,
+    cost_quota: Option<i64>,
+    cost_used: i64,
+    cost_reserved: i64,
+}
+
+impl QuotaCounter {
+    fn new(token_quota: Option<i64>, token_used: i64, cost_quota: Option<i64>, cost_used: i64) -> Self {
+        Self { token_quota, token_used, cost_quota, cost_used, ..Default::default() }
+    }
+
+    fn fits(&self, tokens: i64, cost: i64) -> bool {
+        let tokens_ok = self.token_quota.is_none_or(|q| self.token_used + self.tokens_reserved + tokens <= q);
+        let cost_ok = self.cost_quota.is_none_or(|q| self.cost_used + self.cost_reserved + cost <= q);
+        tokens_ok && cost_ok
+    }
+
+    fn has_remaining(&self) -> bool {
+        let tokens_ok = self.token_quota.is_none_or(|q| self.token_used + self.tokens_reserved < q);
+        let cost_ok = self.cost_quota.is_none_or(|q| self.cost_used + self.cost_reserved < q);
+        tokens_ok && cost_ok
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ActiveReservation {
+    scopes: Vec<String>,
+    tokens: i64,
+    cost: i64,
+    expires_at: DateTime<Utc>,
+}
+
+/// 受同一把锁保护的计数状态 (锁内不跨越 await)
+#[derive(Default)]
+struct Ledger {
+    counters: HashMap<String, QuotaCounter>,
+    user_scopes: HashMap<String, Vec<String>>,
+    active: HashMap<String, ActiveReservation>,
+}
+
+impl Ledger {
+    /// 释放预占额度 (已释放过的返回 false)
+    fn release(&mut self, id: &str) -> bool {
+        let Some(r) = self.active.remove(id) else {
+            return false;
+        };
+        for scope in &r.scopes {
+            if let Some(c) = self.counters.get_mut(scope) {
+                c.tokens_reserved -= r.tokens;
+                c.cost_reserved -= r.cost;
+            }
+        }
+        true
+    }
+
+    /// 用户 (及所用 Key) 的作用域列表，任一作用域的计数缺失时返回 None
+    fn loaded_scopes(&self, user_id: &str, api_key_id: Option<i64>) -> Option<Vec<String>> {
+        let mut scopes = self.user_scopes.get(user_id)?.clone();
+        scopes.extend(api_key_id.map(key_scope));
+        scopes.iter().all(|s| self.counters.contains_key(s)).then_some(scopes)
+    }
+
+    /// 新加载的计数需计入该作用域下仍在进行的预占
+    fn insert_counter(&mut self, scope: String, mut counter: QuotaCounter) {
+        if self.counters.contains_key(&scope) {
+            return;
+        }
+        for r in self.active.values().filter(|r| r.scopes.contains(&scope)) {
+            counter.tokens_reserved += r.tokens;
+            counter.cost_reserved += r.cost;
+        }
+        self.counters.insert(scope, counter);
+    }
+}
+
+/// 预占记录的落库操作，由后台写入任务按顺序执行
+enum PersistOp {
+    Insert(QuotaReservation),
+    Delete(String),
+}
+
+struct ReservationState {
+    db: Arc<DbConnection>,
+    persist_tx: mpsc::UnboundedSender<PersistOp>,
+    ttl: Duration,
+    ledger: Mutex<Ledger>,
+    restored: OnceCell<()>,
+}
+
+impl ReservationState {
+    fn ledger(&self) -> std::sync::MutexGuard<'_, Ledger> {
+        self.ledger.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn delete_row(&self, id: String) {
+        let _ = self.persist_tx.send(PersistOp::Delete(id));
+    }
+}
+
+struct ReservationHandle {
+    id: String,
+    input_tokens: i64,
+    state: Arc<ReservationState>,
+    settled: AtomicBool,
+    quota_holds: Mutex<Vec<QuotaHold>>, // 实际调用模型的周期配额占用
+    tpm_scopes: Mutex<Vec<String>>, // 放行时扣减了 TPM 令牌的作用域
+}
+
+impl Drop for ReservationHandle {
+    // 请求异常结束 (未结算) 时归还预占额度
+    fn drop(&mut self) {
+        if !self.settled.load(Ordering::SeqCst) && self.state.ledger().release(&self.id) {
+            tracing::debug!("预占 {} 未结算，已归还", self.id);
+            self.state.delete_row(self.id.clone());
+        }
+    }
+}
+
+/// 一次请求的配额预占，随请求传递至计费结算；未结算即被丢弃时自动归还
+#[derive(Clone)]
+pub struct Reservation {
+    inner: Arc<ReservationHandle>,
+}
+
+impl Reservation {
+    pub fn id(&self) -> &str {
+        &self.inner.id
+    }
+
+    /// 放行时预估的提示词 Token 数
+    pub fn estimated_input_tokens(&self) -> i64 {
+        self.inner.input_tokens
+    }
+
+    /// 将周期配额占用并入预占，与预占一同结算或归还
+    pub fn hold_quota(&self, hold: QuotaHold) {
+        self.inner.quota_holds.lock().unwrap_or_else(|e| e.into_inner()).push(hold);
+    }
+
+    /// 记录放行时扣减了 TPM 令牌的作用域 (计费时按实际用量修正这些作用域)
+    pub fn set_tpm_scopes(&self, scopes: Vec<String>) {
+        *self.inner.tpm_scopes.lock().unwrap_or_else(|e| e.into_inner()) = scopes;
+    }
+
+    /// 放行时扣减了 TPM 令牌的作用域
+    pub fn tpm_scopes(&self) -> Vec<String> {
+        self.inner.tpm_scopes.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl std::fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reservation").field("id", &self.inner.id).finish()
+    }
+}
+
+/// 配额预占服务
+/// 实现原理: 以内存计数作为配额的权威判断依据 (首次访问时从 `users`、`organizations` 与 `org_members` 加载)，
+/// 放行前按预估的最大用量在用户配额、组织共享预算与成员子限额上同时原子地预占 Token 与金额，
+/// 请求结束后按实际用量结算，避免并发或流式请求在异步计费落库前超额透支。
+/// 预占记录由后台任务按顺序落库 (不阻塞请求)，进程崩溃遗留的预占在重启后恢复，到期后由后台任务回收。
+pub struct QuotaReservations {
+    state: Arc<ReservationState>,
+}
+
+impl QuotaReservations {
+    /// 创建预占服务并启动落库任务 (需在 Tokio 运行时内调用)
+    pub fn new(db: Arc<DbConnection>, ttl: std::time::Duration) -> Self {
+        let (persist_tx, mut persist_rx) = mpsc::unbounded_channel();
+        let writer_db = Arc::clone(&db);
+        tokio::spawn(async move {
+            while let Some(op) = persist_rx.recv().await {
+                let repo = ReservationRepo::new(&writer_db);
+                let result = match &op {
+                    PersistOp::Insert(r) => repo.insert(r).await,
+                    PersistOp::Delete(id) => repo.delete(id).await,
+                };
+                if let Err(e) = result {
+                    tracing::error!("预占记录落库失败: {}", e);
+                }
+            }
+        });
+
+        Self {
+            state: Arc::new(ReservationState {
+                db,
+                persist_tx,
+                ttl: Duration::from_std(ttl).unwrap_or_else(|_| Duration::minutes(15)),
+                ledger: Mutex::new(Ledger::default()),
+                restored: OnceCell::new(),
+            }),
+        }
+    }
+
+    /// 预占配额，任一作用域余量不足时返回 None
+    pub async fn reserve(&self, user_id: &str, api_key_id: Option<i64>, usage: &TokenUsage, pricing: &ModelPricing) -> Result<Option<Reservation>> {
+        let tokens = usage.total();
+        let cost = pricing.cost(usage);
+        let now = Utc::now();
+        let reservation = QuotaReservation {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            api_key_id,
+            tokens,
+            cost,
+            created_at: now,
+            expires_at: now + self.state.ttl,
+        };
+
+        let admitted = self.with_loaded_scopes(user_id, api_key_id, |ledger, scopes| {
+            let fits = scopes.iter().all(|s| ledger.counters.get(s).is_some_and(|c| c.fits(tokens, cost)));
+            if !fits {
+                return false;
+            }
+            for scope in &scopes {
+                if let Some(c) = ledger.counters.get_mut(scope) {
+                    c.tokens_reserved += tokens;
+                    c.cost_reserved += cost;
+                }
+            }
+            ledger.active.insert(reservation.id.clone(), ActiveReservation {
+                scopes,
+                tokens,
+                cost,
+                expires_at: reservation.expires_at,
+            });
+            true
+        }).await?;
+        if !admitted {
+            return Ok(None);
+        }
+
+        let handle = Reservation {
+            inner: Arc::new(ReservationHandle {
+                id: reservation.id.clone(),
+                input_tokens: usage.input_tokens,
+                state: Arc::clone(&self.state),
+                settled: AtomicBool::new(false),
+                quota_holds: Mutex::new(Vec::new()),
+                tpm_scopes: Mutex::new(Vec::new()),
+            }),
+        };
+        let _ = self.state.persist_tx.send(PersistOp::Insert(reservation));
+        Ok(Some(handle))
+    }
+
+    /// 检查用户是否还有剩余配额 (用于不消耗 Token 的请求)
+    pub async fn has_remaining(&self, user_id: &str, api_key_id: Option<i64>) -> Result<bool> {
+        self.with_loaded_scopes(user_id, api_key_id, |ledger, scopes| {
+            scopes.iter().all(|s| ledger.counters.get(s).is_some_and(|c| c.has_remaining()))
+        }).await
+    }
+
+    /// 按实际用量结算: 归还预占并累加已用量
+    pub fn settle(&self, user_id: &str, api_key_id: Option<i64>, reservation: Option<Reservation>, tokens: i64, cost: i64) {
+        let mut ledger = self.state.ledger();
+        let mut scopes = ledger.user_scopes.get(user_id).cloned().map(|mut scopes| {
+            scopes.extend(api_key_id.map(key_scope));
+            scopes
+        });
+        if let Some(r) = reservation {
+            r.inner.settled.store(true, Ordering::SeqCst);
+            r.inner.quota_holds.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            if let Some(active) = ledger.active.get(&r.inner.id) {
+                scopes = Some(active.scopes.clone());
+            }
+            if ledger.release(&r.inner.id) {
+                self.state.delete_row(r.inner.id.clone());
+            }
+        }
+        for scope in scopes.unwrap_or_default() {
+            if let Some(c) = ledger.counters.get_mut(&scope) {
+                c.token_used += tokens;
+                c.cost_used += cost;
+            }
+        }
+    }
+
+    /// 丢弃用户的内存计数 (配额或组织成员关系被修改后调用)，下次访问时重新加载
+    pub fn invalidate_user(&self, user_id: &str) {
+        let mut ledger = self.state.ledger();
+        ledger.user_scopes.remove(user_id);
+        ledger.counters.remove(&user_scope(user_id));
+        ledger.counters.remove(&member_scope(user_id));
+    }
+
+    /// 丢弃 Key 的内存计数 (消费上限被修改后调用)
+    pub fn invalidate_key(&self, key_id: i64) {
+        self.state.ledger().counters.remove(&key_scope(key_id));
+    }
+
+    /// 丢弃组织及其成员的内存计数 (预算被修改或组织被删除后调用)
+    pub fn invalidate_org(&self, org_id: &str) {
+        let scope = org_scope(org_id);
+        let mut ledger = self.state.ledger();
+        ledger.counters.remove(&scope);
+        let members: Vec<String> = ledger.user_scopes.iter()
+            .filter(|(_, scopes)| scopes.contains(&scope))
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        for user_id in members {
+            ledger.user_scopes.remove(&user_id);
+            ledger.counters.remove(&member_scope(&user_id));
+        }
+    }
+
+    /// 回收已过期的预占 (由后台任务定期调用)，返回回收数量
+    pub async fn expire_stale(&self) -> Result<u64> {
+        let now = Utc::now();
+        let released = {
+            let mut ledger = self.state.ledger();
+            let expired: Vec<String> = ledger.active.iter()
+                .filter(|(_, r)| r.expires_at <= now)
+                .map(|(id, _)| id.clone())
+                .collect();
+            expired.iter().filter(|id| ledger.release(id)).count() as u64
+        };
+        ReservationRepo::new(&self.state.db).delete_expired(now).await?;
+        if released > 0 {
+            tracing::warn!("已回收 {} 个超时未结算的配额预占", released);
+        }
+        Ok(released)
+    }
+
+    /// 进程启动后首次使用时恢复尚未过期的预占
+    async fn restore(&self) -> Result<()> {
+        let rows = ReservationRepo::new(&self.state.db).list_all_active(Utc::now()).await?;
+        let org_repo = OrgRepo::new(&self.state.db);
+        let mut restored = Vec::with_capacity(rows.len());
+        for r in rows {
+            let mut scopes = vec![user_scope(&r.user_id)];
+            if let Some(member) = org_repo.find_membership(&r.user_id).await? {
+                scopes.push(org_scope(&member.org_id));
+                scopes.push(member_scope(&r.user_id));
+            }
+            scopes.extend(r.api_key_id.map(key_scope));
+            restored.push((r, scopes));
+        }
+
+        let mut ledger = self.state.ledger();
+        for (r, scopes) in restored {
+            ledger.active.entry(r.id).or_insert(ActiveReservation {
+                scopes,
+                tokens: r.tokens,
+                cost: r.cost,
+                expires_at: r.expires_at,
+            });
+        }
+        Ok(())
+    }
+
+    /// 在持锁状态下对本次请求涉及的全部作用域执行 `f` (作用域为用户相关作用域加上所用 Key 的消费上限)
+    /// 实现逻辑: 加载计数与判断余量之间会释放锁，其间计数可能被失效 (配额或成员关系被修改)；
+    /// 取锁后任一作用域的计数缺失即视为未加载，重新加载后重试，保证判断与预占基于同一份完整的计数。
+    async fn with_loaded_scopes<T>(&self, user_id: &str, api_key_id: Option<i64>, mut f: impl FnMut(&mut Ledger, Vec<String>) -> T) -> Result<T> {
+        for _ in 0..LOAD_ATTEMPTS {
+            self.ensure_loaded(user_id, api_key_id).await?;
+            let mut ledger = self.state.ledger();
+            if let Some(scopes) = ledger.loaded_scopes(user_id, api_key_id) {
+                return Ok(f(&mut ledger, scopes));
+            }
+        }
+        Err(anyhow!("用户 {} 的配额计数加载后反复失效", user_id))
+    }
+
+    /// 确保用户相关作用域与所用 Key 的计数已加载
+    async fn ensure_loaded(&self, user_id: &str, api_key_id: Option<i64>) -> Result<()> {
+        self.state.restored.get_or_try_init(|| self.restore()).await?;
+        if let Some(key_id) = api_key_id {
+            let scope = key_scope(key_id);
+            if !self.state.ledger().counters.contains_key(&scope) {
+                let key = ApiKeyRepo::new(&self.state.db).find_by_id(key_id).await?
+                    .ok_or_else(|| anyhow!("API Key #{} 不存在", key_id))?;
+                self.state.ledger().insert_counter(scope, QuotaCounter::new(None, 0, key.spend_cap, key.spend_used));
+            }
+        }
+        if self.state.ledger().loaded_scopes(user_id, None).is_some() {
+            return Ok(());
+        }
+
+        let user = UserRepo::new(&self.state.db).find_by_id(user_id).await?
+            .ok_or_else(|| anyhow!("用户 {} 不存在", user_id))?;
+        let org_repo = OrgRepo::new(&self.state.db);
+        let mut counters = vec![(
+            user_scope(user_id),
+            QuotaCounter::new(Some(user.token_quota), user.token_used, user.cost_quota, user.cost_used),
+        )];
+        if let Some(member) = org_repo.find_membership(user_id).await? {
+            if let Some(org) = org_repo.find_by_id(&member.org_id).await? {
+                counters.push((
+                    org_scope(&org.id),
+                    QuotaCounter::new(org.token_budget, org.token_used, org.cost_budget, org.cost_used),
+                ));
+            }
+            counters.push((
+                member_scope(user_id),
+                QuotaCounter::new(member.token_limit, member.token_used, member.cost_limit, member.cost_used),
+            ));
+        }
+
+        let scopes: Vec<String> = counters.iter().map(|(s, _)| s.clone()).collect();
+        let mut ledger = self.state.ledger();
+        for (scope, counter) in counters {
+            ledger.insert_counter(scope, counter);
+        }
+        ledger.user_scopes.insert(user_id.to_string(), scopes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimate_usage() {
+        let payload = json!({"messages": [{"role": "user", "content": "hello"}], "max_tokens": 100});
+        let usage = estimate_usage(&payload);
+        assert!(usage.input_tokens > 0);
+        assert_eq!(usage.output_tokens, 100);
+
+        let usage = estimate_usage(&json!({"messages": []}));
+        assert_eq!(usage.output_tokens, DEFAULT_MAX_OUTPUT_TOKENS);
+    }
+
+    #[test]
+    fn test_counter_fits() {
+        let counter = QuotaCounter { token_quota: Some(100), token_used: 40, tokens_reserved: 50, ..Default::default() };
+        assert!(counter.fits(10, 0));
+        assert!(!counter.fits(11, 0));
+        assert!(counter.has_remaining());
+
+        let counter = QuotaCounter { token_quota: None, cost_quota: Some(10), cost_used: 10, ..Default::default() };
+        assert!(counter.fits(1_000_000, 0));
+        assert!(!counter.fits(1, 1));
+        assert!(!counter.has_remaining());
+    }
+
+    #[test]
+    fn test_loaded_scopes_requires_every_counter() {
+        let mut ledger = Ledger::default();
+        assert!(ledger.loaded_scopes("u1", None).is_none());
+
+        ledger.user_scopes.insert("u1".to_string(), vec![user_scope("u1"), org_scope("o1")]);
+        ledger.insert_counter(user_scope("u1"), QuotaCounter::default());
+        ledger.insert_counter(org_scope("o1"), QuotaCounter::default());
+        assert_eq!(ledger.loaded_scopes("u1", None), Some(vec![user_scope("u1"), org_scope("o1")]));
+        // Key 的计数未加载
+        assert!(ledger.loaded_scopes("u1", Some(7)).is_none());
+
+        // 并发失效只移除了部分计数时同样视为未加载
+        ledger.counters.remove(&org_scope("o1"));
+        assert!(ledger.loaded_scopes("u1", None).is_none());
+    }
+}
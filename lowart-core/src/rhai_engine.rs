@@ -8,6 +8,12 @@ pub struct RhaiEngine {
     engine: Engine,
 }
 
+impl Default for RhaiEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RhaiEngine {
     pub fn new() -> Self {
         let mut engine = Engine::new();
@@ -29,19 +35,19 @@ impl RhaiEngine {
         // 分别转换，避免直接在非 Sync 类型上使用 ?
         let input_dynamic: Dynamic = match rhai::serde::to_dynamic(input) {
             Ok(d) => d,
-            Err(e) => return Err(anyhow!("Rhai 输入转换失败: {}", e.to_string())),
+            Err(e) => return Err(anyhow!("Rhai 输入转换失败: {}", e)),
         };
 
         scope.push("input", input_dynamic);
 
         let result: Dynamic = match self.engine.eval_with_scope(&mut scope, script) {
             Ok(d) => d,
-            Err(e) => return Err(anyhow!("Rhai 脚本执行失败: {}", e.to_string())),
+            Err(e) => return Err(anyhow!("Rhai 脚本执行失败: {}", e)),
         };
 
         let output = match rhai::serde::from_dynamic(&result) {
             Ok(v) => v,
-            Err(e) => return Err(anyhow!("Rhai 结果序列化失败: {}", e.to_string())),
+            Err(e) => return Err(anyhow!("Rhai 结果序列化失败: {}", e)),
         };
 
         Ok(output)
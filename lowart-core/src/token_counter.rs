@@ -1,6 +1,11 @@
-use tiktoken_rs::cl100k_base;
-
+use std::sync::OnceLock;
+use tiktoken_rs::{cl100k_base, CoreBPE};
 
+/// 分词器构建开销较大 (需在请求路径上预估用量)，全局只初始化一次
+fn bpe() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| cl100k_base().unwrap())
+}
 
 /// Token 计算器
 /// 实现原理: 基于 tiktoken-rs 封装 LLM Token 计算逻辑。支持根据模型类型选择不同的分词器。
@@ -10,8 +15,7 @@ impl TokenCounter {
     /// 计算文本的 Token 数量
     /// 示例: `let count = TokenCounter::count_tokens("Hello world");`
     pub fn count_tokens(text: &str) -> usize {
-        let bpe = cl100k_base().unwrap();
-        bpe.encode_with_special_tokens(text).len()
+        bpe().encode_with_special_tokens(text).len()
     }
 
     /// 根据消息列表计算 Token (OpenAI 格式)
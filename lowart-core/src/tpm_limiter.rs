@@ -0,0 +1,121 @@
+use crate::rate_limiter::{BucketConfig, RateLimitDecision, RateLimiter};
+use std::time::Duration;
+
+/// 超出 TPM 限制的作用域
+#[derive(Debug, Clone)]
+pub struct TpmExceeded {
+    pub scope: String,
+    pub limit: i64,
+    pub remaining: i64,
+    pub retry_after: Duration,
+    pub reset_after: Duration,
+    pub exceeds_limit: bool, // 本次请求的 Token 数超过每分钟限额，重试也无法通过
+}
+
+/// 每分钟 Token 数 (TPM) 限流器
+/// 实现原理: 每个受限作用域一个令牌桶 (容量为每分钟限额，匀速补充)。放行时按预估的提示词 Token 数
+/// 在所有受限作用域上扣减，任一作用域不足则归还已扣减的部分并拒绝；请求完成后按实际用量 (输入 + 输出)
+/// 与预估值的差额修正，超出部分以欠账形式延后后续请求。只有设置了限制的作用域才会产生令牌桶。
+/// 单次请求的 Token 数超过某作用域的每分钟限额时直接拒绝 (不扣减)，避免反复重试。
+pub struct TpmLimiter {
+    limiter: RateLimiter,
+}
+
+impl Default for TpmLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TpmLimiter {
+    pub fn new() -> Self {
+        Self { limiter: RateLimiter::new(100_000, Duration::from_secs(600)) }
+    }
+
+    /// 检查并扣减: 任一作用域令牌不足则整体拒绝 (不扣减)；放行时返回余量最少的作用域的判断结果
+    pub async fn admit(&self, limits: &[(String, i64)], tokens: i64) -> Result<Option<RateLimitDecision>, TpmExceeded> {
+        if let Some((scope, limit)) = limits.iter().find(|(_, limit)| tokens > *limit) {
+            return Err(TpmExceeded {
+                scope: scope.clone(),
+                limit: *limit,
+                remaining: *limit,
+                retry_after: Duration::ZERO,
+                reset_after: Duration::ZERO,
+                exceeds_limit: true,
+            });
+        }
+        let cost = tokens.max(0) as f64;
+        let mut tightest: Option<RateLimitDecision> = None;
+        for (i, (scope, limit)) in limits.iter().enumerate() {
+            let decision = self.limiter.acquire(scope, BucketConfig::per_minute(*limit), cost).await;
+            if !decision.allowed {
+                for (taken, _) in &limits[..i] {
+                    self.limiter.adjust(taken, -cost).await;
+                }
+                return Err(TpmExceeded {
+                    scope: scope.clone(),
+                    limit: *limit,
+                    remaining: decision.remaining,
+                    retry_after: decision.retry_after,
+                    reset_after: decision.reset_after,
+                    exceeds_limit: false,
+                });
+            }
+            if tightest.is_none_or(|t| decision.remaining < t.remaining) {
+                tightest = Some(decision);
+            }
+        }
+        Ok(tightest)
+    }
+
+    /// 按实际用量修正 (delta 为实际 Token 数减去放行时的预估值)
+    pub async fn correct(&self, scopes: &[String], delta: i64) {
+        for scope in scopes {
+            self.limiter.adjust(scope, delta as f64).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limiter::{model_scope, user_scope};
+
+    #[tokio::test]
+    async fn test_admit_and_correct() {
+        let limiter = TpmLimiter::new();
+        let limits = vec![(user_scope("u1"), 1000), (model_scope("m1"), 1500)];
+
+        assert!(limiter.admit(&limits, 600).await.is_ok());
+        let err = limiter.admit(&limits, 600).await.unwrap_err();
+        assert_eq!(err.scope, "user:u1");
+        assert!(err.retry_after > Duration::ZERO);
+
+        // 实际用量低于预估，额度归还
+        limiter.correct(&[user_scope("u1"), model_scope("m1")], -300).await;
+        assert!(limiter.admit(&limits, 600).await.is_ok());
+
+        // 模型作用域不足时，已在用户作用域扣减的部分归还
+        let limits = vec![(user_scope("u2"), 1000), (model_scope("m1"), 1500)];
+        let err = limiter.admit(&limits, 700).await.unwrap_err();
+        assert_eq!(err.scope, "model:m1");
+        assert!(limiter.admit(&limits[..1], 1000).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_admit_rejects_tokens_over_limit() {
+        let limiter = TpmLimiter::new();
+        let limits = vec![(user_scope("u3"), 1000), (model_scope("m3"), 500)];
+
+        // 超过任一作用域的每分钟限额: 不可重试，且不扣减任何作用域
+        let err = limiter.admit(&limits, 800).await.unwrap_err();
+        assert!(err.exceeds_limit);
+        assert_eq!(err.scope, "model:m3");
+        assert!(limiter.admit(&limits, 500).await.is_ok());
+
+        // 额度不足但未超过限额的请求仍可稍后重试
+        let err = limiter.admit(&limits, 500).await.unwrap_err();
+        assert!(!err.exceeds_limit);
+        assert!(err.retry_after > Duration::ZERO);
+    }
+}
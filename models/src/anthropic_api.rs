@@ -1,4 +1,4 @@
-use crate::traits::{AiModel, BoxStream};
+use crate::traits::{AiModel, BoxStream, HealthProbe};
 use async_trait::async_trait;
 use utils::{Result, anyhow};
 use serde_json::Value;
@@ -74,9 +74,9 @@ impl AiModel for AnthropicAdapter {
 
         let stream = response.bytes_stream().map(|item| {
             item.map_err(|e| anyhow!("流读取错误: {}", e))
-                .and_then(|bytes| {
+                .map(|bytes| {
                     let text = String::from_utf8_lossy(&bytes);
-                    Ok(serde_json::json!({ "raw": text }))
+                    serde_json::json!({ "raw": text })
                 })
         });
 
@@ -86,5 +86,22 @@ impl AiModel for AnthropicAdapter {
     fn model_id(&self) -> &str {
         &self.model_id
     }
+
+    async fn health_check(&self, probe: &HealthProbe) -> Result<()> {
+        let HealthProbe::Completion(payload) = probe else {
+            let url = format!("{}/v1/models", self.base_url.trim_end_matches('/'));
+            let response = self.client.get(&url)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .send()
+                .await
+                .map_err(|e| anyhow!("Anthropic 探测失败: {}", e))?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Anthropic 探测响应错误 ({})", response.status()));
+            }
+            return Ok(());
+        };
+        self.chat_completions(payload.clone()).await.map(|_| ())
+    }
 }
 
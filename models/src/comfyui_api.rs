@@ -1,4 +1,4 @@
-use crate::traits::AiModel;
+use crate::traits::{AiModel, HealthProbe};
 use async_trait::async_trait;
 use utils::{Result, anyhow};
 use serde_json::Value;
@@ -74,4 +74,19 @@ impl AiModel for ComfyUiAdapter {
     fn model_id(&self) -> &str {
         &self.model_id
     }
+
+    async fn health_check(&self, probe: &HealthProbe) -> Result<()> {
+        let HealthProbe::Completion(payload) = probe else {
+            let url = format!("{}/system_stats", self.base_url.trim_end_matches('/'));
+            let response = self.client.get(&url)
+                .send()
+                .await
+                .map_err(|e| anyhow!("ComfyUI 探测失败: {}", e))?;
+            if !response.status().is_success() {
+                return Err(anyhow!("ComfyUI 探测响应错误 ({})", response.status()));
+            }
+            return Ok(());
+        };
+        self.chat_completions(payload.clone()).await.map(|_| ())
+    }
 }
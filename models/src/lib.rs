@@ -4,7 +4,7 @@ pub mod anthropic_api;
 pub mod comfyui_api;
 pub mod mock_api;
 
-pub use traits::AiModel;
+pub use traits::{AiModel, HealthProbe};
 pub use openai_api::OpenAiAdapter;
 pub use anthropic_api::AnthropicAdapter;
 pub use comfyui_api::ComfyUiAdapter;
@@ -1,7 +1,7 @@
 use crate::traits::AiModel;
 use async_trait::async_trait;
 use serde_json::{Value, json};
-use utils::{Result, Error};
+use utils::Result;
 use futures::Stream;
 use std::pin::Pin;
 
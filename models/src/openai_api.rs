@@ -1,4 +1,4 @@
-use crate::traits::{AiModel, BoxStream};
+use crate::traits::{AiModel, BoxStream, HealthProbe};
 use async_trait::async_trait;
 use utils::{Result, anyhow};
 use serde_json::Value;
@@ -72,10 +72,10 @@ impl AiModel for OpenAiAdapter {
         // 使用 bytes_stream 处理 SSE 数据
         let stream = response.bytes_stream().map(|item| {
             item.map_err(|e| anyhow!("流读取错误: {}", e))
-                .and_then(|bytes| {
+                .map(|bytes| {
                     // 这里简化了处理：实际需要根据 SSE 规范解析 data: 字段
                     let text = String::from_utf8_lossy(&bytes);
-                    Ok(serde_json::json!({ "raw": text }))
+                    serde_json::json!({ "raw": text })
                 })
         });
 
@@ -85,5 +85,21 @@ impl AiModel for OpenAiAdapter {
     fn model_id(&self) -> &str {
         &self.model_id
     }
+
+    async fn health_check(&self, probe: &HealthProbe) -> Result<()> {
+        let HealthProbe::Completion(payload) = probe else {
+            let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+            let response = self.client.get(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .send()
+                .await
+                .map_err(|e| anyhow!("OpenAI 探测失败: {}", e))?;
+            if !response.status().is_success() {
+                return Err(anyhow!("OpenAI 探测响应错误 ({})", response.status()));
+            }
+            return Ok(());
+        };
+        self.chat_completions(payload.clone()).await.map(|_| ())
+    }
 }
 
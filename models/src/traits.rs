@@ -7,6 +7,15 @@ use std::pin::Pin;
 /// SSE 流类型定义
 pub type BoxStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
 
+/// 健康探测方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthProbe {
+    /// 厂商默认的轻量探测 (OpenAI / Anthropic 列出模型，ComfyUI 查询 /system_stats；其他厂商发送最小对话请求)
+    Endpoint,
+    /// 发送指定的最小对话请求
+    Completion(Value),
+}
+
 /// AI 模型通用接口
 /// 实现原则: 高模块化，通过 Trait 定义统一的模型调用行为，便于支持多种 AI 厂商。
 #[async_trait]
@@ -19,5 +28,23 @@ pub trait AiModel: Send + Sync {
 
     /// 获取模型标识符
     fn model_id(&self) -> &str;
+
+    /// 健康探测 (默认发送最小对话请求，各适配器可改用更轻量的接口)
+    async fn health_check(&self, probe: &HealthProbe) -> Result<()> {
+        let payload = match probe {
+            HealthProbe::Completion(payload) => payload.clone(),
+            HealthProbe::Endpoint => minimal_completion(self.model_id()),
+        };
+        self.chat_completions(payload).await.map(|_| ())
+    }
+}
+
+/// 最小对话请求 (1 个输出 Token)
+pub fn minimal_completion(model_id: &str) -> Value {
+    serde_json::json!({
+        "model": model_id,
+        "messages": [{"role": "user", "content": "ping"}],
+        "max_tokens": 1
+    })
 }
 
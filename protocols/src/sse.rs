@@ -20,16 +20,17 @@ impl SseEvent {
         }
     }
 
-    pub fn to_string(&self) -> String {
-        let mut s = String::new();
+}
+
+impl std::fmt::Display for SseEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(ref event) = self.event {
-            s.push_str(&format!("event: {}\n", event));
+            writeln!(f, "event: {}", event)?;
         }
         if let Some(ref id) = self.id {
-            s.push_str(&format!("id: {}\n", id));
+            writeln!(f, "id: {}", id)?;
         }
-        s.push_str(&format!("data: {}\n\n", self.data));
-        s
+        write!(f, "data: {}\n\n", self.data)
     }
 }
 
@@ -51,7 +51,7 @@ impl StdioMcpClient {
         });
 
         let mut stdin = self.stdin.lock().await;
-        let line = format!("{}\n", request.to_string());
+        let line = format!("{}\n", request);
         stdin.write_all(line.as_bytes()).await.map_err(|e| anyhow!("写入 stdin 失败: {}", e))?;
         stdin.flush().await.map_err(|e| anyhow!("刷新 stdin 失败: {}", e))?;
         drop(stdin);
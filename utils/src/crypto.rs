@@ -3,9 +3,15 @@ use aes_gcm::{
     Aes256Gcm, Nonce,
 };
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use std::env;
+use std::sync::OnceLock;
 use crate::{anyhow, Result};
 
+/// API Key 展示前缀长度 (如 `la-1a2b3`)
+const KEY_PREFIX_LEN: usize = 8;
+
 
 /// 加密工具类
 /// 实现原理: 使用 AES-256-GCM 算法进行对称加密。主密钥从环境变量 `MASTER_KEY` 读取。
@@ -72,4 +78,54 @@ impl Crypto {
         String::from_utf8(plaintext_bytes)
             .map_err(|e| anyhow!("UTF8 转换失败: {}", e))
     }
+
+    /// 计算 API Key 的哈希 (HMAC-SHA256，十六进制)
+    /// 实现原理: 使用服务端密钥 (环境变量 `API_KEY_SECRET`，未设置时使用 `MASTER_KEY`) 做带密钥哈希，
+    /// 数据库只保存哈希，泄露后无法还原出可用的 Key，也无法离线穷举。密钥变更后已有 Key 全部失效。
+    pub fn hash_api_key(api_key: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(Self::api_key_secret())
+            .expect("HMAC 接受任意长度的密钥");
+        mac.update(api_key.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// 以服务端密钥计算带用途标签的 HMAC-SHA256 (十六进制)
+    /// 实现原理: 与 API Key 哈希共用服务端密钥，`context` 作为前缀区分用途，不同用途的摘要互不通用。
+    /// 没有密钥便无法伪造摘要，用于数据库写权限泄露时仍需可校验的数据 (如审计日志哈希链)。
+    pub fn keyed_hash_hex(context: &str, data: &[u8]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(Self::api_key_secret())
+            .expect("HMAC 接受任意长度的密钥");
+        mac.update(context.as_bytes());
+        mac.update(&[0]);
+        mac.update(data);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// API Key 的展示前缀 (用于在列表中辨认 Key，不足以用于鉴权)
+    pub fn api_key_prefix(api_key: &str) -> String {
+        api_key.chars().take(KEY_PREFIX_LEN).collect()
+    }
+
+    /// 启动时校验 API Key 哈希密钥已配置 (`API_KEY_SECRET` 与 `MASTER_KEY` 均未设置时返回错误，服务拒绝启动)
+    pub fn ensure_api_key_secret() -> Result<()> {
+        Self::read_api_key_secret().map(|_| ())
+    }
+
+    /// 读取 API Key 哈希密钥 (进程内只读取一次；启动时已由 `ensure_api_key_secret` 校验)
+    fn api_key_secret() -> &'static [u8] {
+        static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+        SECRET.get_or_init(|| Self::read_api_key_secret().unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    fn read_api_key_secret() -> Result<Vec<u8>> {
+        let secret = ["API_KEY_SECRET", "MASTER_KEY"].iter()
+            .find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()));
+        match secret {
+            Some(secret) => Ok(secret.into_bytes()),
+            #[cfg(test)]
+            None => Ok(b"lowart-test-api-key-secret".to_vec()),
+            #[cfg(not(test))]
+            None => Err(anyhow!("环境变量 API_KEY_SECRET / MASTER_KEY 均未设置，无法计算 API Key 哈希")),
+        }
+    }
 }